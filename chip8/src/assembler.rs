@@ -0,0 +1,1041 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+
+use crate::chip8::{PROGRAM_START, XO_MEMORY_SIZE};
+
+// Stops a macro that expands to itself from running forever
+const MAX_EXPANSIONS: usize = 10_000;
+
+/// An assembled program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    /// The ROM image, to be loaded at `PROGRAM_START`.
+    pub rom: Vec<u8>,
+    /// Every label and its address.
+    pub symbols: BTreeMap<String, u16>,
+}
+
+impl Program {
+    /// The symbol file contents, one `name address` pair per line sorted by
+    /// address.
+    pub fn symbol_file(&self) -> String {
+        let mut symbols: Vec<(&String, &u16)> = self.symbols.iter().collect();
+        symbols.sort_by_key(|&(name, addr)| (*addr, name.clone()));
+        symbols
+            .iter()
+            .map(|(name, addr)| format!("{} 0x{:03X}\n", name, addr))
+            .collect()
+    }
+}
+
+/// Why a program couldn't be assembled, and where.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    /// 1 based line of the offending token.
+    pub line: usize,
+    /// 1 based column of the offending token.
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+/// Assembles Octo source into a ROM.
+///
+/// Execution starts at the label `main`. Unless the program begins with
+/// `: main` a `jump main` is placed at `PROGRAM_START` first, like Octo does.
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    Assembler::new(tokenize(source)).run()
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn error<T>(&self, message: String) -> Result<T, AsmError> {
+        Err(AsmError { line: self.line, column: self.column, message })
+    }
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let code = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        let mut start = None;
+        for (column, c) in code.char_indices().chain(Some((code.len(), ' '))) {
+            match (c.is_whitespace(), start) {
+                (false, None) => start = Some(column),
+                (true, Some(begin)) => {
+                    tokens.push(Token {
+                        text: code[begin..column].to_string(),
+                        line: index + 1,
+                        column: code[..begin].chars().count() + 1,
+                    });
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    tokens
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+// What a forward reference to a label should be patched with once it's known
+#[derive(Clone, Copy)]
+enum FixupKind {
+    // The low 12 bits of the opcode at the fixup address
+    Addr,
+    // Both halves of a `:unpack`, v0 := nibble and high bits, v1 := low bits
+    Unpack(u8),
+    // The whole 16 bit word at the fixup address, following an `f000`
+    Long,
+}
+
+struct Fixup {
+    at: u16,
+    kind: FixupKind,
+    token: Token,
+}
+
+enum Operand {
+    Register(u8),
+    Value(i64),
+}
+
+enum Condition {
+    Equals(u8, Operand),
+    NotEquals(u8, Operand),
+    Key(u8),
+    NotKey(u8),
+}
+
+impl Condition {
+    fn negate(self) -> Condition {
+        match self {
+            Condition::Equals(x, rhs) => Condition::NotEquals(x, rhs),
+            Condition::NotEquals(x, rhs) => Condition::Equals(x, rhs),
+            Condition::Key(x) => Condition::NotKey(x),
+            Condition::NotKey(x) => Condition::Key(x),
+        }
+    }
+
+    // The opcode that skips the next instruction when this condition holds
+    fn skip_opcode(&self) -> u16 {
+        let x = |x: u8| (x as u16) << 8;
+        match *self {
+            Condition::Equals(vx, Operand::Value(byte)) => 0x3000 | x(vx) | (byte as u16 & 0xff),
+            Condition::NotEquals(vx, Operand::Value(byte)) => 0x4000 | x(vx) | (byte as u16 & 0xff),
+            Condition::Equals(vx, Operand::Register(vy)) => 0x5000 | x(vx) | (vy as u16) << 4,
+            Condition::NotEquals(vx, Operand::Register(vy)) => 0x9000 | x(vx) | (vy as u16) << 4,
+            Condition::Key(vx) => 0xe09e | x(vx),
+            Condition::NotKey(vx) => 0xe0a1 | x(vx),
+        }
+    }
+}
+
+struct Assembler {
+    tokens: Vec<Token>,
+    pos: usize,
+    expansions: usize,
+    memory: Vec<u8>,
+    here: usize,
+    end: usize,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    // Unresolved `:next` label waiting for the next instruction
+    next: Option<Token>,
+    // For each open `loop`, its start and the `while` jumps out of it
+    loops: Vec<(Token, u16, Vec<u16>)>,
+    // For each open `begin`, the jump to patch at `else` or `end`
+    branches: Vec<(Token, u16)>,
+}
+
+impl Assembler {
+    fn new(tokens: Vec<Token>) -> Assembler {
+        Assembler {
+            tokens,
+            pos: 0,
+            expansions: 0,
+            memory: vec![0; XO_MEMORY_SIZE],
+            here: PROGRAM_START as usize,
+            end: PROGRAM_START as usize,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            next: None,
+            loops: Vec::new(),
+            branches: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Result<Program, AsmError> {
+        let starts_with_main = self.tokens.len() >= 2 && self.tokens[0].text == ":" && self.tokens[1].text == "main";
+        let entry = Token { text: "main".to_string(), line: 1, column: 1 };
+        if !starts_with_main {
+            self.emit_addr(0x1000, &entry)?;
+        }
+
+        while self.pos < self.tokens.len() {
+            self.statement()?;
+        }
+
+        if let Some((token, _, _)) = self.loops.pop() {
+            return token.error("loop without a matching again".to_string());
+        }
+        if let Some((token, _)) = self.branches.pop() {
+            return token.error("begin without a matching end".to_string());
+        }
+        if let Some(token) = self.next.take() {
+            return token.error(format!("no instruction follows :next {}", token.text));
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            let addr = match self.labels.get(&fixup.token.text) {
+                Some(&addr) => addr,
+                None => return fixup.token.error(format!("undefined name '{}'", fixup.token.text)),
+            };
+            let at = fixup.at as usize;
+            if addr > 0xfff && !matches!(fixup.kind, FixupKind::Long) {
+                return fixup.token.error(format!("address {:#X} doesn't fit in 12 bits", addr));
+            }
+            match fixup.kind {
+                FixupKind::Addr => self.patch(fixup.at, addr),
+                FixupKind::Unpack(nibble) => {
+                    self.memory[at + 1] = nibble << 4 | (addr >> 8) as u8;
+                    self.memory[at + 3] = addr as u8;
+                }
+                FixupKind::Long => {
+                    self.memory[at] = (addr >> 8) as u8;
+                    self.memory[at + 1] = addr as u8;
+                }
+            }
+        }
+
+        Ok(Program {
+            rom: self.memory[PROGRAM_START as usize..self.end].to_vec(),
+            symbols: self.labels.into_iter().collect(),
+        })
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next_token()?;
+
+        match token.text.as_str() {
+            ":" => {
+                let name = self.name()?;
+                let addr = self.here as u16;
+                self.define_label(name, addr)
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value()?;
+                self.define_constant(name, value as f64)
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.next_token()?;
+                let x = self.register(&register)?;
+                self.check_unused(&name)?;
+                self.aliases.insert(name.text, x);
+                Ok(())
+            }
+            ":macro" => self.define_macro(),
+            ":calc" => {
+                let name = self.name()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.define_constant(name, value)
+            }
+            ":byte" => {
+                let value = if self.peek() == Some("{") {
+                    self.next_token()?;
+                    self.calc()?.floor() as i64
+                } else {
+                    self.value()?
+                };
+                self.emit_value(value, &token)
+            }
+            ":org" => {
+                let value = self.value()?;
+                if value < PROGRAM_START as i64 || value >= self.memory.len() as i64 {
+                    return token.error(format!("can't place code at {:#X}", value));
+                }
+                self.here = value as usize;
+                Ok(())
+            }
+            ":call" => {
+                let target = self.next_token()?;
+                self.address(0x2000, &target)
+            }
+            ":next" => {
+                let name = self.name()?;
+                self.check_unused(&name)?;
+                self.next = Some(name);
+                Ok(())
+            }
+            ":unpack" => {
+                let nibble = self.value()?;
+                if !(0..=0xf).contains(&nibble) {
+                    return token.error(format!("{} doesn't fit in a nibble", nibble));
+                }
+                let target = self.next_token()?;
+                let addr = match self.resolve(&target)? {
+                    Some(addr) if addr > 0xfff => return target.error(format!("address {:#X} doesn't fit in 12 bits", addr)),
+                    Some(addr) => addr,
+                    None => {
+                        self.fixups.push(Fixup { at: self.here as u16, kind: FixupKind::Unpack(nibble as u8), token: target.clone() });
+                        0
+                    }
+                };
+                self.emit(0x6000 | (nibble as u16) << 4 | addr >> 8, &token)?;
+                self.emit(0x6100 | (addr & 0xff), &token)
+            }
+            "clear" => self.emit(0x00e0, &token),
+            "hires" => self.emit(0x00ff, &token),
+            "lores" => self.emit(0x00fe, &token),
+            "scroll-down" => {
+                let n = self.value()?;
+                if !(0..=0xf).contains(&n) {
+                    return token.error(format!("scroll amount {} doesn't fit in a nibble", n));
+                }
+                self.emit(0x00c0 | n as u16, &token)
+            }
+            "scroll-up" => {
+                let n = self.value()?;
+                if !(0..=0xf).contains(&n) {
+                    return token.error(format!("scroll amount {} doesn't fit in a nibble", n));
+                }
+                self.emit(0x00d0 | n as u16, &token)
+            }
+            "scroll-right" => self.emit(0x00fb, &token),
+            "scroll-left" => self.emit(0x00fc, &token),
+            "exit" => self.emit(0x00fd, &token),
+            "saveflags" => self.register_op(0xf075, &token),
+            "loadflags" => self.register_op(0xf085, &token),
+            "return" | ";" => self.emit(0x00ee, &token),
+            "jump" => {
+                let target = self.next_token()?;
+                self.address(0x1000, &target)
+            }
+            "jump0" => {
+                let target = self.next_token()?;
+                self.address(0xb000, &target)
+            }
+            "bcd" => self.register_op(0xf033, &token),
+            "save" => self.save_or_load(0xf055, 0x5002, &token),
+            "load" => self.save_or_load(0xf065, 0x5003, &token),
+            "plane" => {
+                let n = self.value()?;
+                if !(0..=3).contains(&n) {
+                    return token.error(format!("plane mask {} isn't between 0 and 3", n));
+                }
+                self.emit(0xf001 | (n as u16) << 8, &token)
+            }
+            "sprite" => {
+                let x = self.next_register()?;
+                let y = self.next_register()?;
+                let n = self.value()?;
+                if !(0..=0xf).contains(&n) {
+                    return token.error(format!("sprite height {} doesn't fit in a nibble", n));
+                }
+                self.emit(0xd000 | (x as u16) << 8 | (y as u16) << 4 | n as u16, &token)
+            }
+            "delay" => {
+                self.expect(":=")?;
+                self.register_op(0xf015, &token)
+            }
+            "audio" => self.emit(0xf002, &token),
+            "pitch" => {
+                self.expect(":=")?;
+                self.register_op(0xf03a, &token)
+            }
+            "buzzer" => {
+                self.expect(":=")?;
+                self.register_op(0xf018, &token)
+            }
+            "i" => self.i_statement(&token),
+            "if" => self.if_statement(&token),
+            "else" => {
+                let (opener, jump) = match self.branches.pop() {
+                    Some(branch) => branch,
+                    None => return token.error("else without a matching begin".to_string()),
+                };
+                let skip = self.here as u16;
+                self.emit(0x1000, &token)?;
+                self.patch(jump, self.here as u16);
+                self.branches.push((opener, skip));
+                Ok(())
+            }
+            "end" => match self.branches.pop() {
+                Some((_, jump)) => {
+                    self.patch(jump, self.here as u16);
+                    Ok(())
+                }
+                None => token.error("end without a matching begin".to_string()),
+            },
+            "loop" => {
+                self.loops.push((token.clone(), self.here as u16, Vec::new()));
+                Ok(())
+            }
+            "while" => {
+                if self.loops.is_empty() {
+                    return token.error("while outside of a loop".to_string());
+                }
+                let condition = self.condition()?;
+                self.emit(condition.skip_opcode(), &token)?;
+                let jump = self.here as u16;
+                self.emit(0x1000, &token)?;
+                if let Some(frame) = self.loops.last_mut() {
+                    frame.2.push(jump);
+                }
+                Ok(())
+            }
+            "again" => match self.loops.pop() {
+                Some((_, start, breaks)) => {
+                    self.emit(0x1000 | start, &token)?;
+                    for jump in breaks {
+                        self.patch(jump, self.here as u16);
+                    }
+                    Ok(())
+                }
+                None => token.error("again without a matching loop".to_string()),
+            },
+            _ => {
+                if let Some(x) = self.register_index(&token.text) {
+                    return self.register_statement(x, &token);
+                }
+                if self.macros.contains_key(&token.text) {
+                    return self.expand(&token);
+                }
+                if let Some(value) = self.constant(&token.text)? {
+                    return self.emit_value(value, &token);
+                }
+                if is_name(&token.text) {
+                    return self.address(0x2000, &token);
+                }
+                token.error(format!("unexpected '{}'", token.text))
+            }
+        }
+    }
+
+    fn i_statement(&mut self, token: &Token) -> Result<(), AsmError> {
+        let op = self.next_token()?;
+        match op.text.as_str() {
+            ":=" => {
+                match self.peek() {
+                    Some("hex") => {
+                        self.next_token()?;
+                        return self.register_op(0xf029, token);
+                    }
+                    Some("bighex") => {
+                        self.next_token()?;
+                        return self.register_op(0xf030, token);
+                    }
+                    Some("long") => {
+                        self.next_token()?;
+                        return self.long_address(token);
+                    }
+                    _ => {}
+                }
+                let target = self.next_token()?;
+                self.address(0xa000, &target)
+            }
+            "+=" => self.register_op(0xf01e, token),
+            _ => op.error(format!("expected := or += after i, found '{}'", op.text)),
+        }
+    }
+
+    // `save vx` and `load vx` or, with a range, XO-CHIP's `save vx - vy`
+    fn save_or_load(&mut self, opcode: u16, range_opcode: u16, token: &Token) -> Result<(), AsmError> {
+        let x = self.next_register()?;
+        if self.peek() != Some("-") {
+            return self.emit(opcode | (x as u16) << 8, token);
+        }
+        self.next_token()?;
+        let y = self.next_register()?;
+        self.emit(range_opcode | (x as u16) << 8 | (y as u16) << 4, token)
+    }
+
+    // `i := long nnnn`, the only instruction taking a full 16 bit address
+    fn long_address(&mut self, token: &Token) -> Result<(), AsmError> {
+        let target = self.next_token()?;
+        let addr = self.resolve(&target)?;
+        // A :next label points at the address word so it can be rewritten
+        let next = self.next.take();
+        self.emit(0xf000, token)?;
+        if let Some(name) = next {
+            let here = self.here as u16;
+            self.define_label(name, here)?;
+        }
+        if addr.is_none() {
+            self.fixups.push(Fixup { at: self.here as u16, kind: FixupKind::Long, token: target.clone() });
+        }
+        self.emit(addr.unwrap_or(0), &target)
+    }
+
+    fn if_statement(&mut self, token: &Token) -> Result<(), AsmError> {
+        let condition = self.condition()?;
+        let keyword = self.next_token()?;
+        match keyword.text.as_str() {
+            "then" => self.emit(condition.negate().skip_opcode(), token),
+            "begin" => {
+                self.emit(condition.skip_opcode(), token)?;
+                let jump = self.here as u16;
+                self.emit(0x1000, token)?;
+                self.branches.push((token.clone(), jump));
+                Ok(())
+            }
+            _ => keyword.error(format!("expected then or begin, found '{}'", keyword.text)),
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let x = self.next_register()?;
+        let op = self.next_token()?;
+        match op.text.as_str() {
+            "key" => Ok(Condition::Key(x)),
+            "-key" => Ok(Condition::NotKey(x)),
+            "==" => Ok(Condition::Equals(x, self.operand()?)),
+            "!=" => Ok(Condition::NotEquals(x, self.operand()?)),
+            _ => op.error(format!("unsupported comparison '{}'", op.text)),
+        }
+    }
+
+    fn register_statement(&mut self, x: u8, token: &Token) -> Result<(), AsmError> {
+        let op = self.next_token()?;
+        let vx = (x as u16) << 8;
+
+        if op.text == ":=" {
+            match self.peek() {
+                Some("key") => {
+                    self.next_token()?;
+                    return self.emit(0xf00a | vx, token);
+                }
+                Some("delay") => {
+                    self.next_token()?;
+                    return self.emit(0xf007 | vx, token);
+                }
+                Some("random") => {
+                    self.next_token()?;
+                    let mask = self.next_token()?;
+                    let byte = self.byte(&mask)?;
+                    return self.emit(0xc000 | vx | byte as u16, token);
+                }
+                _ => {}
+            }
+        }
+
+        let rhs = self.next_token()?;
+        let operand = match self.register_index(&rhs.text) {
+            Some(y) => Operand::Register(y),
+            None => Operand::Value(self.byte(&rhs)? as i64),
+        };
+
+        let opcode = match (op.text.as_str(), operand) {
+            (":=", Operand::Value(byte)) => 0x6000 | vx | byte as u16,
+            ("+=", Operand::Value(byte)) => 0x7000 | vx | byte as u16,
+            ("-=", Operand::Value(byte)) => 0x7000 | vx | (byte as u8).wrapping_neg() as u16,
+            (op_text, Operand::Register(y)) => {
+                let n = match op_text {
+                    ":=" => 0x0,
+                    "|=" => 0x1,
+                    "&=" => 0x2,
+                    "^=" => 0x3,
+                    "+=" => 0x4,
+                    "-=" => 0x5,
+                    ">>=" => 0x6,
+                    "=-" => 0x7,
+                    "<<=" => 0xe,
+                    _ => return op.error(format!("unexpected '{}' after v{:x}", op.text, x)),
+                };
+                0x8000 | vx | (y as u16) << 4 | n
+            }
+            _ => return rhs.error(format!("'{}' needs a register on the right", op.text)),
+        };
+
+        self.emit(opcode, token)
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.name()?;
+        self.check_unused(&name)?;
+
+        let mut params = Vec::new();
+        loop {
+            let token = self.next_token()?;
+            if token.text == "{" {
+                break;
+            }
+            params.push(token.text);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self.next_token()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => break,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name.text, Macro { params, body });
+        Ok(())
+    }
+
+    fn expand(&mut self, token: &Token) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return token.error(format!("too many macro expansions, is '{}' recursive?", token.text));
+        }
+
+        let params = self.macros[&token.text].params.clone();
+        let mut args = HashMap::new();
+        for param in params {
+            let arg = self.next_token()?;
+            args.insert(param, arg);
+        }
+
+        let body: Vec<Token> = self.macros[&token.text]
+            .body
+            .iter()
+            .map(|t| args.get(&t.text).cloned().unwrap_or_else(|| t.clone()))
+            .collect();
+        self.tokens.splice(self.pos..self.pos, body);
+        Ok(())
+    }
+
+    // Evaluates a `:calc` expression up to its closing brace. Like Octo there is
+    // no operator precedence, expressions are evaluated right to left.
+    fn calc(&mut self) -> Result<f64, AsmError> {
+        let value = self.calc_expression()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    fn calc_expression(&mut self) -> Result<f64, AsmError> {
+        let lhs = self.calc_term()?;
+        match self.peek() {
+            Some("}") | Some(")") | None => return Ok(lhs),
+            _ => {}
+        }
+
+        let op = self.next_token()?;
+        let rhs = self.calc_expression()?;
+        let bool_value = |b: bool| if b { 1.0 } else { 0.0 };
+        Ok(match op.text.as_str() {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" => lhs / rhs,
+            "%" => lhs % rhs,
+            "&" => (lhs as i64 & rhs as i64) as f64,
+            "|" => (lhs as i64 | rhs as i64) as f64,
+            "^" => (lhs as i64 ^ rhs as i64) as f64,
+            "<<" => ((lhs as i64) << (rhs as i64 & 63)) as f64,
+            ">>" => ((lhs as i64) >> (rhs as i64 & 63)) as f64,
+            "pow" => lhs.powf(rhs),
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            "<" => bool_value(lhs < rhs),
+            ">" => bool_value(lhs > rhs),
+            "<=" => bool_value(lhs <= rhs),
+            ">=" => bool_value(lhs >= rhs),
+            "==" => bool_value((lhs - rhs).abs() < f64::EPSILON),
+            "!=" => bool_value((lhs - rhs).abs() >= f64::EPSILON),
+            _ => return op.error(format!("unknown operator '{}'", op.text)),
+        })
+    }
+
+    fn calc_term(&mut self) -> Result<f64, AsmError> {
+        let token = self.next_token()?;
+        let value = match token.text.as_str() {
+            "(" => {
+                let value = self.calc_expression()?;
+                self.expect(")")?;
+                value
+            }
+            "-" => -self.calc_term()?,
+            "~" => !(self.calc_term()? as i64) as f64,
+            "!" => if self.calc_term()? == 0.0 { 1.0 } else { 0.0 },
+            "abs" => self.calc_term()?.abs(),
+            "floor" => self.calc_term()?.floor(),
+            "ceil" => self.calc_term()?.ceil(),
+            "sqrt" => self.calc_term()?.sqrt(),
+            "sin" => self.calc_term()?.sin(),
+            "cos" => self.calc_term()?.cos(),
+            "@" => {
+                let addr = self.calc_term()? as usize;
+                match self.memory.get(addr) {
+                    Some(&byte) => byte as f64,
+                    None => return token.error(format!("{:#X} is outside of memory", addr)),
+                }
+            }
+            "HERE" => self.here as f64,
+            "PI" => std::f64::consts::PI,
+            "E" => std::f64::consts::E,
+            text => match self.constants.get(text) {
+                Some(&value) => value,
+                None => match self.constant(text)? {
+                    Some(value) => value as f64,
+                    None => match self.labels.get(text) {
+                        Some(&addr) => addr as f64,
+                        None => return token.error(format!("undefined name '{}'", text)),
+                    },
+                },
+            },
+        };
+        Ok(value)
+    }
+
+    fn define_label(&mut self, name: Token, addr: u16) -> Result<(), AsmError> {
+        self.check_unused(&name)?;
+        self.labels.insert(name.text, addr);
+        Ok(())
+    }
+
+    fn define_constant(&mut self, name: Token, value: f64) -> Result<(), AsmError> {
+        if self.labels.contains_key(&name.text) || self.aliases.contains_key(&name.text) || self.macros.contains_key(&name.text) {
+            return name.error(format!("'{}' is already defined", name.text));
+        }
+        // Unlike labels, constants may be redefined by a later :calc
+        self.constants.insert(name.text, value);
+        Ok(())
+    }
+
+    fn check_unused(&self, name: &Token) -> Result<(), AsmError> {
+        let text = &name.text;
+        if self.labels.contains_key(text) || self.constants.contains_key(text) || self.aliases.contains_key(text) || self.macros.contains_key(text) {
+            return name.error(format!("'{}' is already defined", text));
+        }
+        if self.next.as_ref().is_some_and(|next| &next.text == text) {
+            return name.error(format!("'{}' is already defined", text));
+        }
+        Ok(())
+    }
+
+    // Emits `opcode` with a 12 bit address taken from a number, constant or
+    // label, deferring labels that haven't been defined yet.
+    fn address(&mut self, opcode: u16, target: &Token) -> Result<(), AsmError> {
+        match self.resolve(target)? {
+            Some(addr) if addr > 0xfff => target.error(format!("address {:#X} doesn't fit in 12 bits", addr)),
+            Some(addr) => self.emit(opcode | addr, target),
+            None => self.emit_addr(opcode, target),
+        }
+    }
+
+    fn emit_addr(&mut self, opcode: u16, target: &Token) -> Result<(), AsmError> {
+        self.fixups.push(Fixup { at: self.here as u16, kind: FixupKind::Addr, token: target.clone() });
+        self.emit(opcode, target)
+    }
+
+    // The address a token refers to if it's known, `None` for a label that
+    // might be defined later
+    fn resolve(&self, token: &Token) -> Result<Option<u16>, AsmError> {
+        if let Some(value) = self.constant(&token.text)? {
+            if !(0..=0xffff).contains(&value) {
+                return token.error(format!("{} isn't an address", value));
+            }
+            return Ok(Some(value as u16));
+        }
+        if let Some(&addr) = self.labels.get(&token.text) {
+            return Ok(Some(addr));
+        }
+        if !is_name(&token.text) || self.register_index(&token.text).is_some() {
+            return token.error(format!("expected an address, found '{}'", token.text));
+        }
+        Ok(None)
+    }
+
+    fn emit(&mut self, opcode: u16, token: &Token) -> Result<(), AsmError> {
+        if let Some(name) = self.next.take() {
+            let addr = self.here as u16 + 1;
+            self.define_label(name, addr)?;
+        }
+        self.emit_byte((opcode >> 8) as u8, token)?;
+        self.emit_byte(opcode as u8, token)
+    }
+
+    fn emit_value(&mut self, value: i64, token: &Token) -> Result<(), AsmError> {
+        let byte = self.check_byte(value, token)?;
+        self.emit_byte(byte, token)
+    }
+
+    fn emit_byte(&mut self, byte: u8, token: &Token) -> Result<(), AsmError> {
+        if self.here >= self.memory.len() {
+            return token.error("program doesn't fit in memory".to_string());
+        }
+        self.memory[self.here] = byte;
+        self.here += 1;
+        self.end = self.end.max(self.here);
+        Ok(())
+    }
+
+    fn patch(&mut self, at: u16, target: u16) {
+        let at = at as usize;
+        self.memory[at] = (self.memory[at] & 0xf0) | (target >> 8) as u8;
+        self.memory[at + 1] = target as u8;
+    }
+
+    fn register_op(&mut self, opcode: u16, token: &Token) -> Result<(), AsmError> {
+        let x = self.next_register()?;
+        self.emit(opcode | (x as u16) << 8, token)
+    }
+
+    fn next_register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next_token()?;
+        self.register(&token)
+    }
+
+    fn register(&self, token: &Token) -> Result<u8, AsmError> {
+        match self.register_index(&token.text) {
+            Some(x) => Ok(x),
+            None => token.error(format!("expected a register, found '{}'", token.text)),
+        }
+    }
+
+    fn register_index(&self, text: &str) -> Option<u8> {
+        if let Some(&x) = self.aliases.get(text) {
+            return Some(x);
+        }
+        let mut chars = text.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => digit.to_digit(16).map(|x| x as u8),
+            _ => None,
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, AsmError> {
+        let token = self.next_token()?;
+        match self.register_index(&token.text) {
+            Some(y) => Ok(Operand::Register(y)),
+            None => Ok(Operand::Value(self.byte(&token)? as i64)),
+        }
+    }
+
+    fn value(&mut self) -> Result<i64, AsmError> {
+        let token = self.next_token()?;
+        match self.constant(&token.text)? {
+            Some(value) => Ok(value),
+            None => token.error(format!("expected a number, found '{}'", token.text)),
+        }
+    }
+
+    fn byte(&self, token: &Token) -> Result<u8, AsmError> {
+        match self.constant(&token.text)? {
+            Some(value) => self.check_byte(value, token),
+            None => token.error(format!("expected a number, found '{}'", token.text)),
+        }
+    }
+
+    fn check_byte(&self, value: i64, token: &Token) -> Result<u8, AsmError> {
+        if !(-128..=255).contains(&value) {
+            return token.error(format!("{} doesn't fit in a byte", value));
+        }
+        Ok(value as u8)
+    }
+
+    // A numeric literal or a :const/:calc value
+    fn constant(&self, text: &str) -> Result<Option<i64>, AsmError> {
+        if let Some(&value) = self.constants.get(text) {
+            return Ok(Some(value.floor() as i64));
+        }
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let parsed = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+            i64::from_str_radix(hex, 16).ok()
+        } else if let Some(binary) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+            i64::from_str_radix(binary, 2).ok()
+        } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+            digits.parse::<i64>().ok()
+        } else {
+            return Ok(None);
+        };
+        Ok(parsed.map(|value| if negative { -value } else { value }))
+    }
+
+    fn name(&mut self) -> Result<Token, AsmError> {
+        let token = self.next_token()?;
+        if !is_name(&token.text) || self.register_index(&token.text).is_some() {
+            return token.error(format!("'{}' isn't a valid name", token.text));
+        }
+        Ok(token)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token, AsmError> {
+        let token = self.next_token()?;
+        if token.text != text {
+            return token.error(format!("expected '{}', found '{}'", text, token.text));
+        }
+        Ok(token)
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|token| token.text.as_str())
+    }
+
+    fn next_token(&mut self) -> Result<Token, AsmError> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => {
+                let (line, column) = self.tokens.last().map_or((1, 1), |last| (last.line, last.column + last.text.len()));
+                Err(AsmError { line, column, message: "unexpected end of input".to_string() })
+            }
+        }
+    }
+}
+
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(source: &str) -> Vec<u8> {
+        assemble(source).unwrap().rom
+    }
+
+    fn error(source: &str) -> (usize, usize) {
+        let err = assemble(source).unwrap_err();
+        (err.line, err.column)
+    }
+
+    #[test]
+    fn should_assemble_instructions() {
+        assert_eq!(
+            rom(": main clear v0 := 5 v1 += 0x10 v2 := v3 v4 <<= v4 i := 0x300 sprite v0 v1 5 delay := v0 v5 := key ;"),
+            [0x00, 0xe0, 0x60, 0x05, 0x71, 0x10, 0x82, 0x30, 0x84, 0x4e, 0xa3, 0x00, 0xd0, 0x15, 0xf0, 0x15, 0xf5, 0x0a, 0x00, 0xee]
+        );
+    }
+
+    #[test]
+    fn should_assemble_superchip_instructions() {
+        assert_eq!(
+            rom(": main hires scroll-down 3 scroll-left scroll-right i := bighex v1 saveflags v2 loadflags v2 lores exit"),
+            [0x00, 0xff, 0x00, 0xc3, 0x00, 0xfc, 0x00, 0xfb, 0xf1, 0x30, 0xf2, 0x75, 0xf2, 0x85, 0x00, 0xfe, 0x00, 0xfd]
+        );
+    }
+
+    #[test]
+    fn should_assemble_xochip_instructions() {
+        assert_eq!(
+            rom(": main save v1 - v3 load v4 - v2 plane 3 scroll-up 2 i := long 0x1234 audio pitch := v5"),
+            [0x51, 0x32, 0x54, 0x23, 0xf3, 0x01, 0x00, 0xd2, 0xf0, 0x00, 0x12, 0x34, 0xf0, 0x02, 0xf5, 0x3a]
+        );
+        let program = assemble(": main i := long data :org 0x2000 : data 0xff").unwrap();
+        assert_eq!(program.rom[..4], [0xf0, 0x00, 0x20, 0x00]);
+        assert_eq!(program.rom.len(), 0x1e01);
+        assert_eq!(error(": main jump data :org 0x2000 : data"), (1, 13));
+    }
+
+    #[test]
+    fn should_jump_to_main() {
+        assert_eq!(rom("0xff : main jump main"), [0x12, 0x03, 0xff, 0x12, 0x03]);
+    }
+
+    #[test]
+    fn should_resolve_forward_references() {
+        assert_eq!(rom(": main i := data sub jump main : sub return : data 1 2"), [0xa2, 0x08, 0x22, 0x06, 0x12, 0x00, 0x00, 0xee, 1, 2]);
+    }
+
+    #[test]
+    fn should_use_constants_and_aliases() {
+        assert_eq!(rom(": main :const speed 3 :alias px v1 px += speed"), [0x71, 0x03]);
+    }
+
+    #[test]
+    fn should_expand_macros() {
+        assert_eq!(rom(": main :macro twice reg { reg += 1 reg += 1 } twice v2"), [0x72, 0x01, 0x72, 0x01]);
+    }
+
+    #[test]
+    fn should_calculate() {
+        assert_eq!(rom(": main :calc size { 2 * 3 + 1 } :calc half { ( 2 * 3 ) / 2 } size half"), [8, 3]);
+    }
+
+    #[test]
+    fn should_assemble_loops() {
+        assert_eq!(
+            rom(": main loop v0 += 1 while v0 != 5 again"),
+            [0x70, 0x01, 0x40, 0x05, 0x12, 0x08, 0x12, 0x00]
+        );
+    }
+
+    #[test]
+    fn should_assemble_conditionals() {
+        assert_eq!(rom(": main if v0 == 1 then v1 := 2"), [0x40, 0x01, 0x61, 0x02]);
+        assert_eq!(rom(": main if v0 key then v1 := 2"), [0xe0, 0xa1, 0x61, 0x02]);
+        assert_eq!(
+            rom(": main if v0 != v1 begin v2 := 1 else v2 := 2 end"),
+            [0x90, 0x10, 0x12, 0x08, 0x62, 0x01, 0x12, 0x0a, 0x62, 0x02]
+        );
+    }
+
+    #[test]
+    fn should_assemble_next_and_unpack() {
+        assert_eq!(rom(": main :next target v0 := 0 :unpack 0xa main"), [0x60, 0x00, 0x60, 0xa2, 0x61, 0x00]);
+        assert_eq!(rom(": main :unpack 0xa data : data 0x12"), [0x60, 0xa2, 0x61, 0x04, 0x12]);
+        assert_eq!(assemble(": main :next target v0 := 0").unwrap().symbols["target"], 0x201);
+    }
+
+    #[test]
+    fn should_write_symbols() {
+        let program = assemble(": main jump later : later return").unwrap();
+        assert_eq!(program.symbol_file(), "main 0x200\nlater 0x202\n");
+    }
+
+    #[test]
+    fn should_report_errors_with_position() {
+        assert_eq!(error(": main\n  v0 := 300"), (2, 9));
+        assert_eq!(error(": main\n  jump nowhere"), (2, 8));
+        assert_eq!(error(": main\nloop\n v0 += 1"), (2, 1));
+        assert_eq!(error(": main : main"), (1, 10));
+        assert_eq!(error(": main\n\tv0 ?= v1"), (2, 5));
+        assert_eq!(error("clear"), (1, 1));
+    }
+
+    #[test]
+    fn should_ignore_comments() {
+        assert_eq!(rom(": main # the start\n clear # v0 := 1"), [0x00, 0xe0]);
+    }
+}
@@ -0,0 +1,111 @@
+/// Length of the XO-CHIP audio pattern buffer in bytes.
+pub const PATTERN_SIZE: usize = 16;
+
+/// The pattern played until a ROM loads its own with `f002`. At the default
+/// pitch it's a 500 Hz square wave, standing in for the CHIP-8 buzzer.
+pub const DEFAULT_PATTERN: [u8; PATTERN_SIZE] = [0xf0; PATTERN_SIZE];
+
+/// The pitch register's initial value, playing the pattern at 4000 bits per
+/// second.
+pub const DEFAULT_PITCH: u8 = 64;
+
+/// The tone, in Hz, of [`DEFAULT_PATTERN`] at [`DEFAULT_PITCH`].
+pub const DEFAULT_FREQUENCY: f64 = 500.0;
+
+/// Bits in one period of [`DEFAULT_PATTERN`]'s square wave.
+pub const DEFAULT_PERIOD_BITS: f64 = 8.0;
+
+// Number of 1 bit samples in a pattern
+const PATTERN_BITS: f64 = (PATTERN_SIZE * 8) as f64;
+
+/// The rate, in pattern bits per second, at which `pitch` plays the pattern.
+pub fn playback_rate(pitch: u8) -> f64 {
+    4000.0 * 2f64.powf((pitch as f64 - 64.0) / 48.0)
+}
+
+/// An audio pattern playing at a pitch, and how far into it playback is.
+///
+/// [`Chip8::fill_audio`](crate::Chip8::fill_audio) renders the machine's
+/// own. A frontend playing sound on another thread can keep a copy, updated
+/// from [`Chip8::audio_pattern`](crate::Chip8::audio_pattern) and
+/// [`Chip8::pitch`](crate::Chip8::pitch), to render the same samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Voice {
+    pub pattern: [u8; PATTERN_SIZE],
+    pub pitch: u8,
+    /// The bit playing, from 0 up to the pattern's length.
+    pub position: f64,
+}
+
+impl Default for Voice {
+    fn default() -> Voice {
+        Voice::new()
+    }
+}
+
+impl Voice {
+    /// The default pattern at the default pitch, from the start.
+    pub fn new() -> Voice {
+        Voice { pattern: DEFAULT_PATTERN, pitch: DEFAULT_PITCH, position: 0.0 }
+    }
+
+    /// Writes the next samples of the pattern at `sample_rate`, each bit
+    /// becoming 1.0 when set and -1.0 when clear.
+    pub fn render(&mut self, out: &mut [f32], sample_rate: u32) {
+        self.render_at(out, sample_rate, playback_rate(self.pitch));
+    }
+
+    /// Like [`Voice::render`], but playing `rate` bits a second whatever the
+    /// pitch.
+    pub fn render_at(&mut self, out: &mut [f32], sample_rate: u32, rate: f64) {
+        let step = rate / sample_rate as f64;
+        for sample in out.iter_mut() {
+            let bit = self.position as usize;
+            let set = self.pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+            *sample = if set { 1.0 } else { -1.0 };
+            self.position = (self.position + step) % PATTERN_BITS;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_double_rate_every_48_steps() {
+        assert_eq!(playback_rate(64), 4000.0);
+        assert_eq!(playback_rate(112), 8000.0);
+        assert_eq!(playback_rate(16), 2000.0);
+        assert_eq!(playback_rate(DEFAULT_PITCH) / DEFAULT_PERIOD_BITS, DEFAULT_FREQUENCY);
+    }
+
+    #[test]
+    fn should_render_pattern_bits() {
+        let mut voice = Voice::new();
+        voice.pattern = [0; PATTERN_SIZE];
+        voice.pattern[0] = 0xa0;
+        voice.pattern[15] = 0x01;
+
+        let mut out = [0.0; 4];
+        voice.render(&mut out, 4000);
+        assert_eq!(out, [1.0, -1.0, 1.0, -1.0]);
+
+        // Playback carries on where it stopped and loops round the pattern
+        let mut out = [0.0; 126];
+        voice.render(&mut out, 4000);
+        assert_eq!(out[123], 1.0);
+        let mut out = [0.0; 1];
+        voice.render(&mut out, 4000);
+        assert_eq!(out, [1.0]);
+    }
+
+    #[test]
+    fn should_resample_to_output_rate() {
+        let mut voice = Voice::new();
+        let mut out = [0.0; 16];
+        voice.render(&mut out, 8000);
+        assert_eq!(out[..8], [1.0; 8]);
+        assert_eq!(out[8..], [-1.0; 8]);
+    }
+}
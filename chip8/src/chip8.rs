@@ -0,0 +1,2349 @@
+use crate::audio::{Voice, PATTERN_SIZE};
+use crate::error::{Chip8Error, PokeError};
+use crate::instruction::{decode_long, Instruction};
+use crate::platform::Platform;
+use crate::quirks::{IndexIncrement, Quirks};
+use crate::rng::{self, RandomSource, XorShift};
+use crate::state::{self, StateError, StateReader, StateWriter, STATE_MAGIC, STATE_VERSION};
+use crate::timing::{self, Timing, VIP_CYCLES_PER_FRAME, VIP_FRAME_OVERHEAD};
+use std::ops::Range;
+
+static FONT_SPRITES: [u8; 16 * 5] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80 // F
+];
+
+// SUPER-CHIP's 8x10 digits, stored straight after FONT_SPRITES
+static BIG_FONT_SPRITES: [u8; 16 * 10] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0 // F
+];
+
+/// Width of the framebuffer in pixels.
+pub const DISPLAY_WIDTH: usize = 64;
+/// Height of the framebuffer in pixels.
+pub const DISPLAY_HEIGHT: usize = 32;
+/// Width of the SUPER-CHIP high resolution framebuffer in pixels.
+pub const HIRES_WIDTH: usize = 128;
+/// Height of the SUPER-CHIP high resolution framebuffer in pixels.
+pub const HIRES_HEIGHT: usize = 64;
+/// Size of addressable RAM in bytes.
+pub const MEMORY_SIZE: usize = 4096;
+/// Size of XO-CHIP's addressable RAM in bytes.
+pub const XO_MEMORY_SIZE: usize = 0x10000;
+/// Address programs are loaded at and execution starts from.
+pub const PROGRAM_START: u16 = 0x200;
+/// How many instructions [`Chip8::run_frame`] executes unless told otherwise.
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 10;
+
+/// A CHIP-8 machine: RAM, registers, timers, keypad and framebuffer.
+///
+/// `R` is where `cxkk` gets random numbers from, [`XorShift`] unless a
+/// machine is made with [`Chip8::with_random_source`].
+#[allow(non_snake_case)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chip8<R = XorShift> {
+    I: u16, // Memory address register
+    M: Vec<u8>, // RAM, sized for the platform
+    S: [u16; 16], // Stack
+    V: [u8; 16], // Registers
+    pc: u16, // Program counter
+    sp: u8, // Stack pointer
+    dt: u8, // Display timer
+    st: u8, // Sound timer
+    voice: Voice, // XO-CHIP audio pattern and pitch
+    input: [bool; 16], // Input buffer
+    display: [[u128; 64]; 2], // Framebuffer planes, one row per entry with x = 0 in the top bit of the current width
+    planes: u8, // Bitmask of the planes drawing, scrolling and clearing affect
+    hires: bool, // Whether the 128x64 SUPER-CHIP display is in use
+    flags: [u8; 16], // SUPER-CHIP RPL user flags
+    exited: bool, // Whether 00fd has been executed
+    wait: bool, // Whether the chip is halted for input
+    store_input_at: u8, // Where to store input after halt
+    rng: R, // Random numbers for cxkk
+    seed: u64, // What rng was last seeded with
+    quirks: Quirks, // How ambiguous opcodes behave
+    platform: Platform, // Which instructions are available
+    instructions_per_frame: u32, // How many instructions run_frame executes
+    timing: Timing, // How run_frame decides how much to execute
+    cycle_budget: i64, // VIP machine cycles left this frame, negative if the last instruction overran
+    rom_hash: u64, // Hash of the loaded program, identifying it in save states
+}
+
+/// The memory an instruction reads and writes as it executes, not counting
+/// fetching the instruction itself. A range is empty if nothing is read or
+/// written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub reads: Range<usize>,
+    pub writes: Range<usize>,
+}
+
+impl Chip8 {
+    /// Creates a machine with the font loaded and the pc at `PROGRAM_START`.
+    pub fn new() -> Chip8 {
+        Chip8::with_quirks(Quirks::default())
+    }
+
+    /// Like [`Chip8::new`], but with opcodes behaving as `quirks` says.
+    pub fn with_quirks(quirks: Quirks) -> Chip8 {
+        Chip8::with_platform(Platform::Chip8, quirks)
+    }
+
+    /// Creates a machine that runs the instruction set of `platform`.
+    pub fn with_platform(platform: Platform, quirks: Quirks) -> Chip8 {
+        Chip8::with_random_source(platform, quirks, XorShift::new(0))
+    }
+}
+
+impl<R: RandomSource> Chip8<R> {
+    /// Like [`Chip8::with_platform`], but drawing random numbers from `rng`.
+    pub fn with_random_source(platform: Platform, quirks: Quirks, rng: R) -> Chip8<R> {
+        let mut chip = blank_chip8(rng);
+        chip.platform = platform;
+        chip.quirks = quirks;
+        chip.M = vec![0; platform.memory_size()];
+        chip.init();
+        chip
+    }
+
+    /// Loads the font into memory and seeds the RNG with entropy.
+    #[allow(clippy::needless_range_loop)]
+    pub fn init(&mut self) {
+        // Load fonts into memory starting at 0x0
+        for index in 0..FONT_SPRITES.len() {
+            self.M[index] = FONT_SPRITES[index];
+        }
+        self.M[FONT_SPRITES.len()..FONT_SPRITES.len() + BIG_FONT_SPRITES.len()].copy_from_slice(&BIG_FONT_SPRITES);
+
+        // Create the rng
+        self.seed_rng(rng::entropy_seed());
+    }
+
+    /// Restarts the random number generator behind `cxkk` from `seed`, so
+    /// the same inputs give the same game every time.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng.reseed(seed);
+    }
+
+    /// The seed the random number generator was last started from.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Loads a program into RAM at `PROGRAM_START`.
+    ///
+    /// Anything that doesn't fit in RAM is ignored.
+    pub fn load(&mut self, program: &[u8]) {
+        let start = PROGRAM_START as usize;
+        let len = program.len().min(self.M.len() - start);
+        self.M[start..start + len].copy_from_slice(&program[..len]);
+        self.rom_hash = state::rom_hash(program);
+    }
+
+    /// The [`rom_hash`](crate::rom_hash) of the last program loaded.
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    /// Serializes the whole machine: registers, memory, stack, timers,
+    /// display, keypad, audio and the RNG.
+    ///
+    /// The state starts with [`STATE_MAGIC`], the format version and the hash
+    /// of the loaded ROM. Quirks and timing are settings rather than state, so
+    /// they aren't included.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.slice(&STATE_MAGIC);
+        w.u16(STATE_VERSION);
+        w.u64(self.rom_hash);
+        w.u8(self.platform.id());
+
+        w.u16(self.I);
+        w.u32(self.M.len() as u32);
+        w.slice(&self.M);
+        for &addr in &self.S {
+            w.u16(addr);
+        }
+        w.slice(&self.V);
+        w.u16(self.pc);
+        w.u8(self.sp);
+        w.u8(self.dt);
+        w.u8(self.st);
+
+        for plane in &self.display {
+            for &row in plane {
+                w.u128(row);
+            }
+        }
+        w.u8(self.planes);
+        w.bool(self.hires);
+        for &pressed in &self.input {
+            w.bool(pressed);
+        }
+        w.bool(self.wait);
+        w.u8(self.store_input_at);
+        w.slice(&self.flags);
+        w.bool(self.exited);
+
+        w.slice(&self.voice.pattern);
+        w.u8(self.voice.pitch);
+        w.u64(self.voice.position.to_bits());
+        w.u64(self.rng.state());
+        w.u64(self.cycle_budget as u64);
+        w.bytes
+    }
+
+    /// Restores a state written by [`Chip8::save_state`].
+    ///
+    /// The state has to come from this format version, the same platform and
+    /// the ROM currently loaded. If anything is wrong the machine is left as
+    /// it was.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), StateError> {
+        let mut r = StateReader::new(bytes);
+        if r.slice(STATE_MAGIC.len()).map_err(|_| StateError::NotAState)? != STATE_MAGIC {
+            return Err(StateError::NotAState);
+        }
+        let version = r.u16()?;
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion { version });
+        }
+        let found = r.u64()?;
+        if found != self.rom_hash {
+            return Err(StateError::RomMismatch { expected: self.rom_hash, found });
+        }
+        if r.u8()? != self.platform.id() {
+            return Err(StateError::PlatformMismatch);
+        }
+
+        let i = r.u16()?;
+        let memory_size = r.u32()? as usize;
+        if memory_size != self.M.len() {
+            return Err(StateError::Corrupt);
+        }
+        let memory = r.slice(memory_size)?;
+        let mut stack = [0; 16];
+        for addr in &mut stack {
+            *addr = r.u16()?;
+        }
+        let mut registers = [0; 16];
+        registers.copy_from_slice(r.slice(16)?);
+        let pc = r.u16()?;
+        let sp = r.u8()?;
+        if sp as usize > stack.len() {
+            return Err(StateError::Corrupt);
+        }
+        let dt = r.u8()?;
+        let st = r.u8()?;
+
+        let mut display = [[0; 64]; 2];
+        for plane in &mut display {
+            for row in plane.iter_mut() {
+                *row = r.u128()?;
+            }
+        }
+        let planes = r.u8()?;
+        let hires = r.bool()?;
+        let mut input = [false; 16];
+        for pressed in &mut input {
+            *pressed = r.bool()?;
+        }
+        let wait = r.bool()?;
+        let store_input_at = r.u8()?;
+        if store_input_at > 0xf {
+            return Err(StateError::Corrupt);
+        }
+        let mut flags = [0; 16];
+        flags.copy_from_slice(r.slice(16)?);
+        let exited = r.bool()?;
+
+        let mut pattern = [0; PATTERN_SIZE];
+        pattern.copy_from_slice(r.slice(PATTERN_SIZE)?);
+        let pitch = r.u8()?;
+        let position = f64::from_bits(r.u64()?);
+        if !(0.0..(PATTERN_SIZE * 8) as f64).contains(&position) {
+            return Err(StateError::Corrupt);
+        }
+        let rng_state = r.u64()?;
+        let cycle_budget = r.u64()? as i64;
+
+        self.I = i;
+        self.M.copy_from_slice(memory);
+        self.S = stack;
+        self.V = registers;
+        self.pc = pc;
+        self.sp = sp;
+        self.dt = dt;
+        self.st = st;
+        self.display = display;
+        self.planes = planes;
+        self.hires = hires;
+        self.input = input;
+        self.wait = wait;
+        self.store_input_at = store_input_at;
+        self.flags = flags;
+        self.exited = exited;
+        self.voice.pattern = pattern;
+        self.voice.pitch = pitch;
+        self.voice.position = position;
+        self.rng.set_state(rng_state);
+        self.cycle_budget = cycle_budget;
+        Ok(())
+    }
+
+    /// Updates the state of the hex keypad, `true` meaning the key is held.
+    ///
+    /// If the machine is blocked on `fx0a` the first newly pressed key is
+    /// stored and execution resumes.
+    #[allow(clippy::needless_range_loop)]
+    pub fn update_input(&mut self, new_input: [bool; 16]) {
+        for key in 0..16 {
+            if self.input[key] != new_input[key] {
+                self.input[key] = new_input[key];
+
+                // If waiting for input update register with key pressed and continue execution
+                if new_input[key] && self.wait {
+                    self.V[self.store_input_at as usize] = key as u8;
+                    self.store_input_at = 0;
+                    self.wait = false;
+                }
+            }
+        }
+    }
+
+    /// Fetches, decodes and executes a single instruction.
+    ///
+    /// The timers aren't touched, they count down in [`Chip8::tick_timers`].
+    ///
+    /// If the instruction can't be executed the machine is left untouched and
+    /// the error is returned; calling `cycle` again will fail the same way.
+    pub fn cycle(&mut self) -> Result<(), Chip8Error> {
+        self.step().map(|_| ())
+    }
+
+    // Executes one instruction and returns it, or `None` if the machine is
+    // blocked and nothing ran
+    fn step(&mut self) -> Result<Option<Instruction>, Chip8Error> {
+        if self.wait || self.exited { return Ok(None) }
+
+        if self.pc & 1 != 0 {
+            return Err(Chip8Error::MisalignedPc { addr: self.pc });
+        }
+        self.check_memory(self.pc as usize, 2)?;
+        // The pc has to be able to move past the longest instruction without overflowing
+        if self.pc as usize + 4 > XO_MEMORY_SIZE {
+            return Err(Chip8Error::MemoryOutOfBounds { addr: self.pc, access: XO_MEMORY_SIZE });
+        }
+
+        let opcode = self.word(self.pc as usize);
+        // Only f000 is followed by a second word, which has to be in memory too
+        let next = if opcode == 0xf000 {
+            self.check_memory(self.pc as usize + 2, 2)?;
+            self.word(self.pc as usize + 2)
+        } else {
+            0
+        };
+        let instruction = match decode_long(opcode, next) {
+            Ok(instruction) if self.platform.supports(instruction) => instruction,
+            _ => return Err(Chip8Error::UnknownOpcode { addr: self.pc, opcode }),
+        };
+        if self.pc as usize + self.furthest_advance(instruction) >= XO_MEMORY_SIZE {
+            return Err(Chip8Error::MemoryOutOfBounds { addr: self.pc, access: XO_MEMORY_SIZE });
+        }
+
+        self.execute(instruction)?;
+        Ok(Some(instruction))
+    }
+
+    /// Counts the delay and sound timers down by one. Call this at 60 Hz.
+    pub fn tick_timers(&mut self) {
+        if self.dt > 0 {
+            self.dt -= 1;
+        }
+
+        if self.st > 0 {
+            self.st -= 1;
+        }
+    }
+
+    /// Runs one 60 Hz frame of instructions, then a timer tick.
+    ///
+    /// How many instructions make up a frame depends on [`Chip8::timing`]:
+    /// `instructions_per_frame` of them, or as many as fit in the COSMAC
+    /// VIP's frame. The frame ends early while the machine is waiting for a
+    /// key or has exited. If an instruction fails the error is returned
+    /// straight away and the timers are left alone.
+    pub fn run_frame(&mut self) -> Result<(), Chip8Error> {
+        match self.timing {
+            Timing::InstructionCount => {
+                for _ in 0..self.instructions_per_frame {
+                    if self.step()?.is_none() {
+                        break;
+                    }
+                }
+            }
+            Timing::CosmacVip => self.run_vip_frame()?,
+        }
+        // The VIP counts the timers down in its vertical blank interrupt, once a frame
+        self.tick_timers();
+        Ok(())
+    }
+
+    fn run_vip_frame(&mut self) -> Result<(), Chip8Error> {
+        self.cycle_budget += (VIP_CYCLES_PER_FRAME - VIP_FRAME_OVERHEAD) as i64;
+        while self.cycle_budget > 0 {
+            let pc = self.pc;
+            let instruction = match self.step()? {
+                Some(instruction) => instruction,
+                None => {
+                    // Nothing runs while blocked, so nothing carries over either
+                    self.cycle_budget = 0;
+                    break;
+                }
+            };
+            let skipped = self.pc.wrapping_sub(pc) > instruction.size() && !matches!(
+                instruction,
+                Instruction::Jump { .. } | Instruction::JumpPlusV0 { .. } | Instruction::Call { .. } | Instruction::Return
+            );
+            self.cycle_budget -= timing::vip_cycles(instruction, skipped) as i64;
+            if let Instruction::Draw { .. } = instruction {
+                // The sprite is drawn once the vertical blank interrupt arrives, so the frame is over
+                self.cycle_budget = self.cycle_budget.min(0);
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// How [`Chip8::run_frame`] decides how much to execute in a frame.
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    /// Switches between a fixed number of instructions per frame and the
+    /// COSMAC VIP's instruction timings.
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.cycle_budget = 0;
+    }
+
+    /// How many instructions [`Chip8::run_frame`] executes.
+    pub fn instructions_per_frame(&self) -> u32 {
+        self.instructions_per_frame
+    }
+
+    /// Sets how many instructions [`Chip8::run_frame`] executes, which sets
+    /// the speed of the game. 10 is about right for most CHIP-8 games, while
+    /// SUPER-CHIP and XO-CHIP games often want far more.
+    pub fn set_instructions_per_frame(&mut self, instructions: u32) {
+        self.instructions_per_frame = instructions;
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> Result<(), Chip8Error> {
+        use crate::instruction::Instruction::*;
+
+        match instruction {
+            ClearScreen => self.clear_screen(),
+            Return => self.return_from_sub()?,
+            ScrollUp { n } => self.scroll_up(n),
+            ScrollDown { n } => self.scroll_down(n),
+            ScrollRight => self.scroll_right(),
+            ScrollLeft => self.scroll_left(),
+            Exit => self.exit(),
+            Lores => self.set_hires(false),
+            Hires => self.set_hires(true),
+            Jump { addr } => self.jump(addr),
+            Call { addr } => self.call(addr)?,
+            SkipIfRegEqualsByte { x, byte } => self.skip_if_reg_equals_byte(x, byte),
+            SkipIfRegNotEqualsByte { x, byte } => self.skip_if_reg_not_equals_byte(x, byte),
+            SkipIfRegEqualsReg { x, y } => self.skip_if_reg_equals_reg(x, y),
+            SetRegister { x, byte } => self.set_register(x, byte),
+            AddToRegister { x, byte } => self.add_to_register(x, byte),
+            CopyToRegister { x, y } => self.copy_to_register(x, y),
+            OrWithRegister { x, y } => self.or_with_register(x, y),
+            AndWithRegister { x, y } => self.and_with_register(x, y),
+            XorWithRegister { x, y } => self.xor_with_register(x, y),
+            AddRegisters { x, y } => self.add_registers(x, y),
+            Sub { x, y } => self.sub(x, y),
+            ShiftRight { x, y } => self.shift_right(x, y),
+            SubReverse { x, y } => self.sub_reverse(x, y),
+            ShiftLeft { x, y } => self.shift_left(x, y),
+            StoreRegRange { x, y } => self.store_reg_range(x, y)?,
+            ReadRegRange { x, y } => self.read_reg_range(x, y)?,
+            SkipIfRegNotEqualsReg { x, y } => self.skip_if_reg_not_equals_reg(x, y),
+            SetI { addr } => self.set_i(addr),
+            JumpPlusV0 { addr } => self.jump_with_offset(addr),
+            Random { x, byte } => self.random(x, byte),
+            Draw { x, y, n } => self.update_display(x, y, n)?,
+            SkipIfPressed { x } => self.skip_if_pressed(x),
+            SkipIfNotPressed { x } => self.skip_if_not_pressed(x),
+            SetILong { addr } => self.set_i_long(addr),
+            SelectPlanes { n } => self.select_planes(n),
+            LoadAudio => self.load_audio()?,
+            SetRegToDt { x } => self.set_reg_to_dt(x),
+            WaitForInput { x } => self.wait_for_input(x),
+            SetDt { x } => self.set_dt(x),
+            SetSt { x } => self.set_st(x),
+            IPlusReg { x } => self.i_plus_reg(x),
+            SetIDigitSprite { x } => self.set_i_digit_sprite(x),
+            SetIBigDigitSprite { x } => self.set_i_big_digit_sprite(x),
+            Bcd { x } => self.bcd(x)?,
+            SetPitch { x } => self.set_pitch(x),
+            StoreRegsThrough { x } => self.store_regs_through(x)?,
+            ReadToRegs { x } => self.read_to_regs(x)?,
+            SaveFlags { x } => self.save_flags(x),
+            LoadFlags { x } => self.load_flags(x),
+        }
+
+        Ok(())
+    }
+
+    /// The instruction set this machine runs.
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// How ambiguous opcodes behave on this machine.
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// The general purpose registers `V0` through `VF`.
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.V
+    }
+
+    /// Sets register `Vx`, for debuggers and tools poking at a machine. An
+    /// `x` of 16 or more fails with [`PokeError::NoSuchRegister`].
+    pub fn set_v(&mut self, x: usize, value: u8) -> Result<(), PokeError> {
+        let register = self.V.get_mut(x).ok_or(PokeError::NoSuchRegister { x })?;
+        *register = value;
+        Ok(())
+    }
+
+    /// The memory address register `I`.
+    pub fn i(&self) -> u16 {
+        self.I
+    }
+
+    /// Sets the memory address register `I`.
+    pub fn set_index(&mut self, addr: u16) {
+        self.I = addr;
+    }
+
+    /// The program counter.
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// Moves the program counter, so execution carries on from `addr`. An
+    /// `addr` outside memory fails on the next instruction, as a jump there
+    /// would.
+    pub fn set_pc(&mut self, addr: u16) {
+        self.pc = addr;
+    }
+
+    /// The stack pointer, i.e. the number of return addresses on the stack.
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    /// Sets how many return addresses are on the stack, up to 16. The
+    /// addresses themselves are left as they are. More than 16 fails with
+    /// [`PokeError::StackTooDeep`] and leaves the stack pointer alone.
+    pub fn set_sp(&mut self, sp: u8) -> Result<(), PokeError> {
+        if sp as usize > self.S.len() {
+            return Err(PokeError::StackTooDeep { sp });
+        }
+        self.sp = sp;
+        Ok(())
+    }
+
+    /// The call stack of return addresses.
+    pub fn stack(&self) -> &[u16; 16] {
+        &self.S
+    }
+
+    /// The delay timer.
+    pub fn dt(&self) -> u8 {
+        self.dt
+    }
+
+    /// Sets the delay timer.
+    pub fn set_delay_timer(&mut self, dt: u8) {
+        self.dt = dt;
+    }
+
+    /// The sound timer.
+    pub fn st(&self) -> u8 {
+        self.st
+    }
+
+    /// Sets the sound timer.
+    pub fn set_sound_timer(&mut self, st: u8) {
+        self.st = st;
+    }
+
+    /// The SUPER-CHIP RPL user flags written by `fx75`.
+    pub fn flags(&self) -> &[u8; 16] {
+        &self.flags
+    }
+
+    /// All of RAM, including the font at `0x000`. This is 4 KiB, or 64 KiB on
+    /// XO-CHIP.
+    pub fn memory(&self) -> &[u8] {
+        &self.M
+    }
+
+    /// The memory `instruction` would read and write if this machine executed
+    /// it now, for debuggers watching memory. The ranges can run past the
+    /// end of memory, in which case executing it fails.
+    pub fn memory_access(&self, instruction: Instruction) -> MemoryAccess {
+        use crate::instruction::Instruction::*;
+        let (reads, writes) = match instruction {
+            Draw { n, .. } => {
+                let (rows, sprite_width) = self.sprite_size(n);
+                (rows * sprite_width / 8 * self.selected_planes().len(), 0)
+            }
+            ReadRegRange { x, y } => (register_range(x, y).len(), 0),
+            StoreRegRange { x, y } => (0, register_range(x, y).len()),
+            ReadToRegs { x } => (x as usize + 1, 0),
+            StoreRegsThrough { x } => (0, x as usize + 1),
+            LoadAudio => (PATTERN_SIZE, 0),
+            Bcd { .. } => (0, 3),
+            _ => (0, 0),
+        };
+        let i = self.I as usize;
+        MemoryAccess { reads: i..i + reads, writes: i..i + writes }
+    }
+
+    /// Copies `bytes` into memory starting at `addr`. Nothing is written if
+    /// they don't all fit, failing with [`PokeError::OutOfMemory`].
+    pub fn write_memory(&mut self, addr: u16, bytes: &[u8]) -> Result<(), PokeError> {
+        self.check_memory(addr as usize, bytes.len())
+            .map_err(|_| PokeError::OutOfMemory { addr, len: bytes.len(), size: self.M.len() })?;
+        self.M[addr as usize..addr as usize + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// The raw framebuffer planes, one `u128` per row. Only the first
+    /// `height()` rows and the low `width()` bits of each are used, with
+    /// `x = 0` in the most significant of those bits. Plane 1 is only drawn
+    /// to on XO-CHIP.
+    pub fn display(&self) -> &[[u128; 64]; 2] {
+        &self.display
+    }
+
+    /// Whether the pixel at (`x`, `y`) is lit on any plane.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.pixel_color(x, y) != 0
+    }
+
+    /// The color index of the pixel at (`x`, `y`): bit 0 is set if it's lit
+    /// on plane 0 and bit 1 if it's lit on plane 1.
+    pub fn pixel_color(&self, x: usize, y: usize) -> u8 {
+        let shift = self.width() - 1 - x;
+        ((self.display[0][y] >> shift) & 1 | ((self.display[1][y] >> shift) & 1) << 1) as u8
+    }
+
+    /// The bitmask of planes selected with `fn01`.
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// Width of the display in its current mode.
+    pub fn width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { DISPLAY_WIDTH }
+    }
+
+    /// Height of the display in its current mode.
+    pub fn height(&self) -> usize {
+        if self.hires { HIRES_HEIGHT } else { DISPLAY_HEIGHT }
+    }
+
+    /// Whether the SUPER-CHIP 128x64 display is in use.
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Whether the program has stopped itself with `00fd`.
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// The current state of the hex keypad.
+    pub fn input(&self) -> &[bool; 16] {
+        &self.input
+    }
+
+    /// Whether execution is blocked on `fx0a` until a key is pressed.
+    pub fn is_waiting(&self) -> bool {
+        self.wait
+    }
+
+    /// The 1 bit audio pattern, as last loaded by `f002`.
+    pub fn audio_pattern(&self) -> &[u8; PATTERN_SIZE] {
+        &self.voice.pattern
+    }
+
+    /// The audio pitch register, as last set by `fx3a`.
+    pub fn pitch(&self) -> u8 {
+        self.voice.pitch
+    }
+
+    /// Whether sound is playing, which it does for as long as the sound timer
+    /// is above 0.
+    pub fn is_sound_playing(&self) -> bool {
+        self.st > 0
+    }
+
+    /// Fills `out` with mono samples at `sample_rate` for a frontend to play
+    /// or record.
+    ///
+    /// While the sound timer is running the audio pattern loops at the rate
+    /// set by the pitch register, bits becoming samples of 1.0 or -1.0.
+    /// Otherwise `out` is silent. Each call carries on from where the last
+    /// one stopped, so call this with one frame's worth of samples per frame.
+    pub fn fill_audio(&mut self, out: &mut [f32], sample_rate: u32) {
+        if self.is_sound_playing() {
+            self.voice.render(out, sample_rate);
+        } else {
+            for sample in out.iter_mut() {
+                *sample = 0.0;
+            }
+        }
+    }
+
+    fn add_registers(&mut self, x: u8, y: u8) {
+        let (value, carry) = self.V[x as usize].overflowing_add(self.V[y as usize]);
+        if carry { self.V[0xf] = 1; }
+        self.V[x as usize] = value;
+        self.pc += 2;
+    }
+    
+    fn add_to_register(&mut self, x: u8, byte: u8) {
+        let (value, _c) = self.V[x as usize].overflowing_add(byte);
+        self.V[x as usize] = value;
+        self.pc += 2;
+    }
+
+    #[allow(clippy::assign_op_pattern)]
+    fn and_with_register(&mut self, x: u8, y: u8) {
+        self.V[x as usize] = self.V[x as usize] & self.V[y as usize];
+        self.logic_quirk();
+        self.pc += 2;
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    fn bcd(&mut self, x: u8) -> Result<(), Chip8Error> {
+        self.check_memory(self.I as usize, 3)?;
+        let digit = self.V[x as usize];
+        let hundreds = digit / 100;
+        let i = self.I as usize;
+        self.M[i] = hundreds as u8;
+        self.M[i + 1] = ((digit / 10) - (hundreds * 10)) as u8;
+        self.M[i + 2] = (digit % 10) as u8;
+        self.pc += 2;
+        Ok(())
+    }
+
+    fn call(&mut self, loc: u16) -> Result<(), Chip8Error> {
+        if self.sp as usize >= self.S.len() {
+            return Err(Chip8Error::StackOverflow { addr: self.pc });
+        }
+        self.S[self.sp as usize] = self.pc + 2;
+        self.sp += 1;
+        self.pc = loc;
+        Ok(())
+    }
+
+    // Fails if `len` bytes starting at `start` don't all fit in RAM
+    fn check_memory(&self, start: usize, len: usize) -> Result<(), Chip8Error> {
+        if start + len > self.M.len() {
+            let access = start.max(self.M.len());
+            return Err(Chip8Error::MemoryOutOfBounds { addr: self.pc, access });
+        }
+        Ok(())
+    }
+
+    fn clear_screen(&mut self) {
+        for plane in self.selected_planes() {
+            self.display[plane] = [0; 64];
+        }
+        self.pc += 2;
+    }
+
+    fn copy_to_register(&mut self, x: u8, y: u8) {
+        self.V[x as usize] = self.V[y as usize];
+        self.pc += 2;
+    }
+    
+    fn exit(&mut self) {
+        self.exited = true;
+    }
+
+    fn i_plus_reg(&mut self, x:u8) {
+        self.I = self.I.wrapping_add(self.V[x as usize] as u16);
+        self.pc += 2;
+    }
+
+    fn jump(&mut self, loc: u16) {
+        self.pc = loc;
+    }
+
+    fn jump_with_offset(&mut self, loc: u16) {
+        let x = if self.quirks.jump_uses_vx { (loc >> 8) & 0xf } else { 0 };
+        self.pc = loc + self.V[x as usize] as u16;
+    }
+
+    fn load_audio(&mut self) -> Result<(), Chip8Error> {
+        self.check_memory(self.I as usize, PATTERN_SIZE)?;
+        let start = self.I as usize;
+        self.voice.pattern.copy_from_slice(&self.M[start..start + PATTERN_SIZE]);
+        self.pc += 2;
+        Ok(())
+    }
+
+    fn load_flags(&mut self, x: u8) {
+        self.V[..=x as usize].copy_from_slice(&self.flags[..=x as usize]);
+        self.pc += 2;
+    }
+
+    fn logic_quirk(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.V[0xf] = 0;
+        }
+    }
+
+    fn index_quirk(&mut self, x: u8) {
+        match self.quirks.index_increment {
+            IndexIncrement::Unchanged => {}
+            IndexIncrement::X => self.I = self.I.wrapping_add(x as u16),
+            IndexIncrement::XPlusOne => self.I = self.I.wrapping_add(x as u16 + 1),
+        }
+    }
+
+    #[allow(clippy::assign_op_pattern)]
+    fn or_with_register(&mut self, x: u8, y: u8) {
+        self.V[x as usize] = self.V[x as usize] | self.V[y as usize];
+        self.logic_quirk();
+        self.pc += 2;
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    fn random(&mut self, x: u8, byte: u8) {
+        self.V[x as usize] = self.rng.next_u8() & byte as u8;
+        self.pc += 2;
+    }
+
+    fn read_to_regs(&mut self, x: u8) -> Result<(), Chip8Error> {
+        self.check_memory(self.I as usize, x as usize + 1)?;
+        for r in 0..(x as usize + 1) {
+            self.V[r] = self.M[self.I as usize + r]
+        }
+        self.index_quirk(x);
+        self.pc += 2;
+        Ok(())
+    }
+
+    fn read_reg_range(&mut self, x: u8, y: u8) -> Result<(), Chip8Error> {
+        let registers = register_range(x, y);
+        self.check_memory(self.I as usize, registers.len())?;
+        for (offset, r) in registers.into_iter().enumerate() {
+            self.V[r] = self.M[self.I as usize + offset];
+        }
+        self.pc += 2;
+        Ok(())
+    }
+
+    fn return_from_sub(&mut self) -> Result<(), Chip8Error> {
+        if self.sp == 0 {
+            return Err(Chip8Error::StackUnderflow { addr: self.pc });
+        }
+        self.sp -= 1;
+        self.pc = self.S[self.sp as usize];
+        self.S[self.sp as usize] = 0;
+        Ok(())
+    }
+
+    fn save_flags(&mut self, x: u8) {
+        self.flags[..=x as usize].copy_from_slice(&self.V[..=x as usize]);
+        self.pc += 2;
+    }
+
+    fn scroll_down(&mut self, n: u8) {
+        let height = self.height();
+        let n = (n as usize).min(height);
+        for plane in self.selected_planes() {
+            let display = &mut self.display[plane];
+            display.copy_within(0..height - n, n);
+            for row in &mut display[..n] {
+                *row = 0;
+            }
+        }
+        self.pc += 2;
+    }
+
+    fn scroll_left(&mut self) {
+        let mask = self.row_mask();
+        for plane in self.selected_planes() {
+            for row in &mut self.display[plane] {
+                *row = (*row << 4) & mask;
+            }
+        }
+        self.pc += 2;
+    }
+
+    fn scroll_right(&mut self) {
+        for plane in self.selected_planes() {
+            for row in &mut self.display[plane] {
+                *row >>= 4;
+            }
+        }
+        self.pc += 2;
+    }
+
+    fn scroll_up(&mut self, n: u8) {
+        let height = self.height();
+        let n = (n as usize).min(height);
+        for plane in self.selected_planes() {
+            let display = &mut self.display[plane];
+            display.copy_within(n..height, 0);
+            for row in &mut display[height - n..height] {
+                *row = 0;
+            }
+        }
+        self.pc += 2;
+    }
+
+    fn select_planes(&mut self, n: u8) {
+        self.planes = n & 0b11;
+        self.pc += 2;
+    }
+
+    // Indices of the planes selected by fn01
+    fn selected_planes(&self) -> Vec<usize> {
+        (0..2).filter(|plane| self.planes & (1 << plane) != 0).collect()
+    }
+
+    // The bits of a display row that are on screen in the current mode
+    fn row_mask(&self) -> u128 {
+        if self.hires { u128::MAX } else { u64::MAX as u128 }
+    }
+
+    fn set_dt(&mut self, x: u8) {
+        self.dt = self.V[x as usize];
+        self.pc += 2;
+    }
+
+    fn set_i(&mut self, byte: u16) {
+        self.I = byte;
+        self.pc += 2;
+    }
+
+    fn set_i_digit_sprite(&mut self, x: u8) {
+        self.I = self.V[x as usize] as u16 * 5;
+        self.pc += 2;
+    }
+
+    fn set_i_big_digit_sprite(&mut self, x: u8) {
+        self.I = FONT_SPRITES.len() as u16 + (self.V[x as usize] & 0xf) as u16 * 10;
+        self.pc += 2;
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.display = [[0; 64]; 2];
+        self.pc += 2;
+    }
+
+    fn set_i_long(&mut self, addr: u16) {
+        self.I = addr;
+        self.pc += 4;
+    }
+
+    fn set_register(&mut self, x: u8, byte: u8) {
+        self.V[x as usize] = byte;
+        self.pc += 2;
+    }
+
+    fn set_pitch(&mut self, x: u8) {
+        self.voice.pitch = self.V[x as usize];
+        self.pc += 2;
+    }
+
+    fn set_reg_to_dt(&mut self, x: u8) {
+        self.V[x as usize] = self.dt;
+        self.pc += 2;
+    }
+
+    fn set_st(&mut self, x: u8) {
+        self.st = self.V[x as usize];
+        self.pc += 2;
+    }
+
+    fn shift_left(&mut self, x: u8, y: u8) {
+        let vx = self.V[if self.quirks.shift_uses_vy { y } else { x } as usize];
+        self.V[x as usize] = vx << 1;
+        self.V[0xf] = vx >> 7;
+        self.pc += 2;
+    }
+
+    fn shift_right(&mut self, x: u8, y: u8) {
+        let vx = self.V[if self.quirks.shift_uses_vy { y } else { x } as usize];
+        self.V[x as usize] = vx >> 1;
+        self.V[0xf] = vx & 0x01;
+        self.pc += 2;
+    }
+
+    // The most bytes `instruction` can move the pc on by, which mustn't take it
+    // past the end of the address space
+    fn furthest_advance(&self, instruction: Instruction) -> usize {
+        use crate::instruction::Instruction::*;
+
+        match instruction {
+            SkipIfRegEqualsByte { .. }
+            | SkipIfRegNotEqualsByte { .. }
+            | SkipIfRegEqualsReg { .. }
+            | SkipIfRegNotEqualsReg { .. }
+            | SkipIfPressed { .. }
+            | SkipIfNotPressed { .. } => {
+                if self.platform == Platform::XoChip { 6 } else { 4 }
+            }
+            SetILong { .. } => 4,
+            _ => 2,
+        }
+    }
+
+    fn skip_if(&mut self, condition: bool) {
+        if !condition {
+            self.pc += 2;
+            return;
+        }
+        // XO-CHIP skips both words of f000 nnnn
+        let next = self.pc as usize + 2;
+        let long = self.platform == Platform::XoChip && next + 1 < self.M.len() && self.word(next) == 0xf000;
+        self.pc += if long { 6 } else { 4 };
+    }
+
+    fn skip_if_pressed(&mut self, x: u8) {
+        self.skip_if(self.input[(self.V[x as usize] & 0xf) as usize]);
+    }
+
+    fn skip_if_not_pressed(&mut self, x: u8) {
+        self.skip_if(!self.input[(self.V[x as usize] & 0xf) as usize]);
+    }
+
+    fn skip_if_reg_equals_byte(&mut self, x: u8, byte: u8) {
+        self.skip_if(self.V[x as usize] == byte);
+    }
+
+    fn skip_if_reg_not_equals_byte(&mut self, x: u8, byte: u8) {
+        self.skip_if(self.V[x as usize] != byte);
+    }
+
+    fn skip_if_reg_equals_reg(&mut self, x: u8, y: u8) {
+        self.skip_if(self.V[x as usize] == self.V[y as usize]);
+    }
+
+    fn skip_if_reg_not_equals_reg(&mut self, x: u8, y: u8) {
+        self.skip_if(self.V[x as usize] != self.V[y as usize]);
+    }
+
+    fn store_reg_range(&mut self, x: u8, y: u8) -> Result<(), Chip8Error> {
+        let registers = register_range(x, y);
+        self.check_memory(self.I as usize, registers.len())?;
+        for (offset, r) in registers.into_iter().enumerate() {
+            self.M[self.I as usize + offset] = self.V[r];
+        }
+        self.pc += 2;
+        Ok(())
+    }
+
+    fn store_regs_through(&mut self, x: u8) -> Result<(), Chip8Error> {
+        self.check_memory(self.I as usize, x as usize + 1)?;
+        for r in 0..(x as usize + 1) {
+            self.M[self.I as usize + r] = self.V[r];
+        }
+        self.index_quirk(x);
+        self.pc += 2;
+        Ok(())
+    }
+
+    fn sub(&mut self, x: u8, y: u8) {
+        let vx = self.V[x as usize];
+        let vy = self.V[y as usize];
+        self.V[0xf] = if vx > vy { 1 } else { 0 };
+        self.V[x as usize] = vx.wrapping_sub(vy);
+        self.pc += 2;
+    }
+
+    fn sub_reverse(&mut self, x: u8, y: u8) {
+        let vx = self.V[x as usize];
+        let vy = self.V[y as usize];
+        self.V[0xf] = if vy > vx { 1 } else { 0 };
+        self.V[x as usize] = vy.wrapping_sub(vx);
+        self.pc += 2;
+    }
+
+    fn update_display(&mut self, x: u8, y: u8, n: u8) -> Result<(), Chip8Error> {
+        let (rows, sprite_width) = self.sprite_size(n);
+        let sprite_len = rows * sprite_width / 8;
+        let planes = self.selected_planes();
+        self.check_memory(self.I as usize, sprite_len * planes.len())?;
+
+        let width = self.width();
+        let height = self.height();
+        let mask = self.row_mask();
+        let vx = self.V[x as usize] as usize % width;
+        let vy = self.V[y as usize] as usize % height;
+        let mut erased = false;
+
+        // With both planes selected the sprite for plane 1 follows the one for plane 0
+        for (sprite_index, plane) in planes.into_iter().enumerate() {
+            let start = self.I as usize + sprite_index * sprite_len;
+            for index in 0..rows {
+                let sprite = if sprite_width == 16 {
+                    (self.M[start + index * 2] as u128) << 8 | self.M[start + index * 2 + 1] as u128
+                } else {
+                    self.M[start + index] as u128
+                };
+                if self.quirks.clip_sprites && vy + index >= height {
+                    break;
+                }
+                let y_offset = (vy + index) % height;
+                let row = self.display[plane][y_offset];
+
+                let new_row = if vx + sprite_width > width {
+                    let overflow = vx + sprite_width - width;
+                    let wrapped = if self.quirks.clip_sprites { 0 } else { (sprite << (width - overflow)) & mask };
+                    wrapped | sprite >> overflow
+                } else {
+                    sprite << (width - sprite_width - vx)
+                };
+                if row & new_row > 0 {
+                    erased = true;
+                }
+                self.display[plane][y_offset] = row ^ new_row;
+            }
+        }
+
+        self.V[0xf] = if erased { 1 } else { 0 };
+
+        self.pc += 2;
+        Ok(())
+    }
+
+    // The rows and width in pixels of the sprite dxyn draws
+    fn sprite_size(&self, n: u8) -> (usize, usize) {
+        // SUPER-CHIP draws a 16x16 sprite, two bytes per row, for dxy0
+        if n == 0 && self.platform != Platform::Chip8 { (16, 16) } else { (n as usize, 8) }
+    }
+
+    fn word(&self, addr: usize) -> u16 {
+        (self.M[addr] as u16) << 8 | self.M[addr + 1] as u16
+    }
+
+    fn wait_for_input(&mut self, x: u8) {
+        self.wait = true;
+        self.store_input_at = x;
+        self.pc += 2;
+    }
+    
+    #[allow(clippy::assign_op_pattern)]
+    fn xor_with_register(&mut self, x: u8, y: u8) {
+        self.V[x as usize] = self.V[x as usize] ^ self.V[y as usize];
+        self.logic_quirk();
+        self.pc += 2;
+    }
+}
+
+impl Default for Chip8 {
+    fn default() -> Chip8 {
+        Chip8::new()
+    }
+}
+
+// The registers from Vx to Vy, counting down if x > y
+fn register_range(x: u8, y: u8) -> Vec<usize> {
+    let (x, y) = (x as usize, y as usize);
+    if x <= y { (x..=y).collect() } else { (y..=x).rev().collect() }
+}
+
+/// Creates a machine with zeroed memory. Call [`Chip8::init`] before running
+/// anything that needs the font.
+pub fn new_chip8() -> Chip8 {
+    blank_chip8(XorShift::new(0))
+}
+
+fn blank_chip8<R>(rng: R) -> Chip8<R> {
+    Chip8 {
+        I: 0,
+        M: vec![0; MEMORY_SIZE],
+        S: [0; 16],
+        V: [0; 16],
+        pc: 0x200,
+        sp: 0,
+        dt: 0,
+        st: 0,
+        voice: Voice::new(),
+        display: [[0; 64]; 2],
+        planes: 1,
+        hires: false,
+        flags: [0; 16],
+        exited: false,
+        input: [false; 16],
+        wait: false,
+        store_input_at: 0,
+        rng,
+        seed: 0,
+        quirks: Quirks::default(),
+        platform: Platform::Chip8,
+        instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+        timing: Timing::InstructionCount,
+        cycle_budget: 0,
+        rom_hash: state::rom_hash(&[]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init() -> Chip8 {
+        let mut chip = new_chip8();
+        chip.init();
+        chip
+    }
+
+    fn init_with(quirks: Quirks) -> Chip8 {
+        Chip8::with_quirks(quirks)
+    }
+
+    fn init_superchip() -> Chip8 {
+        Chip8::with_platform(Platform::SuperChip, Quirks::superchip())
+    }
+
+    fn init_xochip() -> Chip8 {
+        Chip8::with_platform(Platform::XoChip, Quirks::xochip())
+    }
+
+    #[test]
+    fn should_clear_screen() {
+        let mut chip = init();
+        chip.clear_screen();
+        assert_eq!(chip.display[0], [0; 64]);
+    }
+
+    #[test]
+    fn shouldnt_return_from_subroutine() {
+        let mut chip = init();
+        let pc = chip.pc;
+        assert_eq!(chip.return_from_sub(), Err(Chip8Error::StackUnderflow { addr: pc }));
+        assert_eq!(chip.pc, pc);
+        assert_eq!(chip.sp, 0);
+        assert_eq!(chip.S, [0; 16]);
+    }
+
+    #[test]
+    fn should_return_from_subroutine() {
+        let mut chip = init();
+        chip.S[0] = 0x201;
+        chip.sp = 1;
+        chip.return_from_sub().unwrap();
+        assert_eq!(chip.pc, 0x0201);
+        assert_eq!(chip.sp, 0);
+        assert_eq!(chip.S, [0; 16]);
+    }
+
+    #[test]
+    fn should_jump() {
+        let mut chip = init();
+        chip.jump(0x201);
+        assert_eq!(chip.pc, 0x201);
+    }
+
+    #[test]
+    fn should_call() {
+        let mut chip = init();
+        chip.call(0x201).unwrap();
+        assert_eq!(chip.pc, 0x201);
+        assert_eq!(chip.S[0], 0x202);
+        assert_eq!(chip.sp, 1);
+    }
+
+    #[test]
+    fn should_skip_when_vx_and_byte_equal() {
+        let mut chip = init();
+        chip.pc = 0;
+        chip.V[0] = 1;
+        chip.skip_if_reg_equals_byte(0, 1);
+        assert_eq!(chip.pc, 4);
+    }
+
+    #[test]
+    fn shouldnt_skip_when_vx_and_byte_not_equal() {
+        let mut chip = init();
+        chip.pc = 0;
+        chip.V[0] = 1;
+        chip.skip_if_reg_equals_byte(0, 0);
+        assert_eq!(chip.pc, 2);
+    }
+
+    #[test]
+    fn should_skip_when_vx_and_byte_not_equal() {
+        let mut chip = init();
+        chip.pc = 0;
+        chip.V[0] = 1;
+        chip.skip_if_reg_not_equals_byte(0, 0);
+        assert_eq!(chip.pc, 4);
+    }
+
+    #[test]
+    fn shouldnt_skip_when_vx_and_byte_equal() {
+        let mut chip = init();
+        chip.pc = 0;
+        chip.V[0] = 1;
+        chip.skip_if_reg_not_equals_byte(0, 1);
+        assert_eq!(chip.pc, 2);
+    }
+
+    #[test]
+    fn should_skip_when_vx_and_vy_not_equal() {
+        let mut chip = init();
+        chip.pc = 0;
+        chip.V[0] = 1;
+        chip.skip_if_reg_not_equals_reg(0, 1);
+        assert_eq!(chip.pc, 4);
+    }
+
+    #[test]
+    fn shouldnt_skip_when_vx_and_vy_equal() {
+        let mut chip = init();
+        chip.pc = 0;
+        chip.skip_if_reg_not_equals_reg(0, 1);
+        assert_eq!(chip.pc, 2);
+    }
+
+    #[test]
+    fn should_skip_when_vx_and_vy_equal() {
+        let mut chip = init();
+        chip.pc = 0;
+        chip.skip_if_reg_equals_reg(0, 1);
+        assert_eq!(chip.pc, 4);
+    }
+
+    #[test]
+    fn shouldnt_skip_when_vx_and_vy_not_equal() {
+        let mut chip = init();
+        chip.pc = 0;
+        chip.V[1] = 1;
+        chip.skip_if_reg_equals_reg(0, 1);
+        assert_eq!(chip.pc, 2);
+    }
+
+    #[test]
+    fn should_set_register() {
+        let mut chip = init();
+        chip.set_register(0, 1);
+        assert_eq!(chip.V[0], 1);
+    }
+
+    #[test]
+    fn should_add_to_register() {
+        let mut chip = init();
+        chip.add_to_register(0, 1);
+        assert_eq!(chip.V[0], 1);
+    }
+
+    #[test]
+    fn should_copy_to_register() {
+        let mut chip = init();
+        chip.V[1] = 1;
+        chip.copy_to_register(0, 1);
+        assert_eq!(chip.V[0], 1);
+    }
+
+    #[test]
+    fn should_or_with_register() {
+        let mut chip = init();
+        chip.V[0] = 0xf0;
+        chip.V[1] = 0xf;
+        chip.or_with_register(0, 1);
+        assert_eq!(chip.V[0], 0xff);
+    }
+
+    #[test]
+    fn should_and_with_register() {
+        let mut chip = init();
+        chip.V[0] = 0xf0;
+        chip.V[1] = 0xf;
+        chip.and_with_register(0, 1);
+        assert_eq!(chip.V[0], 0x0);
+    }
+
+    #[test]
+    fn should_xor_with_register() {
+        let mut chip = init();
+        chip.V[0] = 0xf8;
+        chip.V[1] = 0xf;
+        chip.xor_with_register(0, 1);
+        assert_eq!(chip.V[0], 0xf7);
+    }
+
+    #[test]
+    fn should_add_registers() {
+        let mut chip = init();
+        chip.V[1] = 4;
+        chip.add_registers(0, 1);
+        assert_eq!(chip.V[0], 4);
+    }
+
+    #[test]
+    fn should_add_registers_and_carry() {
+        let mut chip = init();
+        chip.V[0] = 0xff;
+        chip.V[1] = 4;
+        chip.add_registers(0, 1);
+        assert_eq!(chip.V[0], 3);
+        assert_eq!(chip.V[0xf], 1);
+    }
+
+    #[test]
+    fn should_set_i() {
+        let mut chip = init();
+        chip.set_i(1);
+        assert_eq!(chip.I, 1);
+    }
+
+    #[test]
+    fn should_set_register_to_random() {
+        let mut chip = init();
+        chip.seed_rng(7);
+        let mut rng = XorShift::new(7);
+        chip.random(0, 0xff);
+        assert_eq!(chip.V[0], rng.next_u8());
+        chip.random(1, 0x0f);
+        assert_eq!(chip.V[1], rng.next_u8() & 0x0f);
+        assert_eq!(chip.pc, 0x204);
+    }
+
+    // Counts up from the seed, so tests know exactly what cxkk gets
+    #[derive(Clone, Debug, PartialEq)]
+    struct Counter(u64);
+
+    impl RandomSource for Counter {
+        fn reseed(&mut self, seed: u64) {
+            self.0 = seed;
+        }
+
+        fn next_u8(&mut self) -> u8 {
+            self.0 += 1;
+            self.0 as u8
+        }
+
+        fn state(&self) -> u64 {
+            self.0
+        }
+
+        fn set_state(&mut self, state: u64) {
+            self.0 = state;
+        }
+    }
+
+    #[test]
+    fn should_draw_from_injected_random_source() {
+        let mut chip = Chip8::with_random_source(Platform::Chip8, Quirks::default(), Counter(0));
+        chip.seed_rng(0x40);
+        chip.load(&[0xc0, 0xff, 0xc1, 0xff]);
+        chip.cycle().unwrap();
+        let state = chip.save_state();
+        chip.cycle().unwrap();
+        assert_eq!(chip.V[..2], [0x41, 0x42]);
+
+        // The source's state goes in save states
+        chip.load_state(&state).unwrap();
+        chip.cycle().unwrap();
+        assert_eq!(chip.V[1], 0x42);
+    }
+
+    #[test]
+    fn should_repeat_runs_with_the_same_seed() {
+        let rom = [0xc0, 0xff, 0xa2, 0x00, 0xd0, 0x05, 0x12, 0x00];
+        let mut first = Chip8::new();
+        first.load(&rom);
+        first.seed_rng(1234);
+        let mut second = first.clone();
+        for _ in 0..20 {
+            first.run_frame().unwrap();
+            second.run_frame().unwrap();
+        }
+        assert_eq!(first, second);
+
+        second.seed_rng(4321);
+        second.run_frame().unwrap();
+        first.run_frame().unwrap();
+        assert_ne!(first.display(), second.display());
+    }
+
+    #[test]
+    fn should_update_display() {
+        let mut chip = init();
+        chip.V[0] = 2;
+        chip.I = 2048;
+        chip.M[2048] = 0x0f;
+        chip.M[2049] = 0xf0;
+        chip.M[2050] = 0x0f;
+        chip.M[2051] = 0xf0;
+        chip.update_display(0, 0, 4).unwrap();
+        assert_eq!((chip.display[0][0]), 0);
+        assert_eq!((chip.display[0][1]), 0);
+        assert_eq!((chip.display[0][2] >> 54), 0x0f);
+        assert_eq!((chip.display[0][3] >> 54), 0xf0);
+        assert_eq!((chip.display[0][4] >> 54), 0x0f);
+        assert_eq!((chip.display[0][5] >> 54), 0xf0);
+        assert_eq!(chip.V[0xf], 0);
+    }
+
+    #[test]
+    fn should_display_digit() {
+        let mut chip = init();
+        chip.update_display(0, 0, 5).unwrap();
+        assert_eq!((chip.display[0][0] >> 56), 0xf0);
+        assert_eq!((chip.display[0][1] >> 56), 0x90);
+        assert_eq!((chip.display[0][2] >> 56), 0x90);
+        assert_eq!((chip.display[0][3] >> 56), 0x90);
+        assert_eq!((chip.display[0][4] >> 56), 0xf0);
+    }
+
+    #[test]
+    fn should_update_display_and_wrap() {
+        let mut chip = init();
+        chip.V[0] = 60;
+        chip.V[1] = 30;
+        chip.I = 2048;
+        chip.M[2048] = 0x0f;
+        chip.M[2049] = 0xf0;
+        chip.M[2050] = 0x0f;
+        chip.M[2051] = 0xf0;
+        chip.update_display(0, 1, 4).unwrap();
+        assert_eq!(chip.display[0][0], 0xf000000000000000);
+        assert_eq!(chip.display[0][1], 0x000000000000000f);
+        assert_eq!(chip.display[0][30], 0xf000000000000000);
+        assert_eq!(chip.display[0][31], 0x000000000000000f);
+        assert_eq!(chip.V[0xf], 0);
+    }
+
+    #[test]
+    fn should_update_display_and_set_erased() {
+        let mut chip = init();
+        chip.I = 2048;
+        chip.M[2048] = 0x0f;
+        chip.M[2049] = 0xf0;
+        chip.M[2050] = 0x0f;
+        chip.M[2051] = 0xf0;
+        chip.display[0][0] = 0x0f00000000000000;
+        chip.update_display(0, 0, 4).unwrap();
+        assert_eq!((chip.display[0][0] >> 56), 0x00);
+        assert_eq!((chip.display[0][1] >> 56), 0xf0);
+        assert_eq!((chip.display[0][2] >> 56), 0x0f);
+        assert_eq!((chip.display[0][3] >> 56), 0xf0);
+        assert_eq!(chip.V[0xf], 1);
+    }
+
+    #[test]
+    fn should_skip_if_pressed() {
+        let mut chip = init();
+        chip.pc = 0;
+        chip.V[0] = 2;
+        chip.input[2] = true;
+        chip.skip_if_pressed(0);
+        assert_eq!(chip.pc, 4);
+    }
+
+    #[test]
+    fn shouldnt_skip_if_not_pressed() {
+        let mut chip = init();
+        chip.pc = 0;
+        chip.V[0] = 2;
+        chip.input[2] = false;
+        chip.skip_if_pressed(0);
+        assert_eq!(chip.pc, 2);
+    }
+
+    #[test]
+    fn shouldnt_skip_if_pressed() {
+        let mut chip = init();
+        chip.pc = 0;
+        chip.V[0] = 2;
+        chip.input[2] = true;
+        chip.skip_if_not_pressed(0);
+        assert_eq!(chip.pc, 2);
+    }
+
+    #[test]
+    fn should_skip_if_not_pressed() {
+        let mut chip = init();
+        chip.pc = 0;
+        chip.V[0] = 2;
+        chip.input[2] = false;
+        chip.skip_if_not_pressed(0);
+        assert_eq!(chip.pc, 4);
+    }
+
+    #[test]
+    #[allow(clippy::bool_assert_comparison)]
+    fn should_wait_for_input() {
+        let mut chip = init();
+        let mut new_input = [false; 16];
+        new_input[6] = true;
+        chip.wait_for_input(1);
+        assert_eq!(chip.wait, true);
+        assert_eq!(chip.store_input_at, 1);
+        chip.update_input(new_input);
+        assert_eq!(chip.V[1], 6);
+        assert_eq!(chip.wait, false);
+        assert_eq!(chip.store_input_at, 0);
+    }
+
+    #[test]
+    fn should_set_dt() {
+        let mut chip = init();
+        chip.V[0] = 10;
+        chip.set_dt(0);
+        assert_eq!(chip.dt, 10);
+    }
+
+    #[test]
+    fn should_set_vx_to_dt() {
+        let mut chip = init();
+        chip.dt = 10;
+        chip.set_reg_to_dt(0);
+        assert_eq!(chip.V[0], 10);
+    }
+
+    #[test]
+    fn should_set_st() {
+        let mut chip = init();
+        chip.V[0] = 5;
+        chip.set_st(0);
+        assert_eq!(chip.st, 5);
+    }
+
+    #[test]
+    fn should_set_i_plus_vx() {
+        let mut chip = init();
+        chip.I = 5;
+        chip.V[0] = 5;
+        chip.i_plus_reg(0);
+        assert_eq!(chip.I, 10);
+    }
+
+    #[test]
+    fn should_set_i_to_digit_locations() {
+        let mut chip = init();
+        chip.V[0] = 2;
+        chip.V[1] = 0xa;
+        chip.set_i_digit_sprite(0);
+        assert_eq!(chip.I, 2 * 5);
+        chip.set_i_digit_sprite(1);
+        assert_eq!(chip.I, 0xa * 5);
+    }
+
+    #[test]
+    fn should_store_registers() {
+        let mut chip = init();
+        chip.V[0] = 1;
+        chip.V[1] = 2;
+        chip.V[2] = 3;
+        chip.I = 1000;
+        chip.store_regs_through(2).unwrap();
+        assert_eq!(chip.M[1000], 1);
+        assert_eq!(chip.M[1001], 2);
+        assert_eq!(chip.M[1002], 3);
+    }
+
+    #[test]
+    fn should_read_registers() {
+        let mut chip = init();
+        chip.M[1000] = 1;
+        chip.M[1001] = 2;
+        chip.M[1002] = 3;
+        chip.I = 1000;
+        chip.read_to_regs(2).unwrap();
+        assert_eq!(chip.V[0], 1);
+        assert_eq!(chip.V[1], 2);
+        assert_eq!(chip.V[2], 3);
+    }
+
+    #[test]
+    fn should_bcd() {
+        let mut chip = init();
+        chip.V[0] = 245;
+        chip.I = 1000;
+        chip.bcd(0).unwrap();
+        assert_eq!(chip.M[1000], 2);
+        assert_eq!(chip.M[1001], 4);
+        assert_eq!(chip.M[1002], 5);
+    }
+
+    #[test]
+    fn should_cycle() {
+        let mut chip = init();
+        chip.load(&[0x60, 0x2a, 0x71, 0x01]);
+        chip.cycle().unwrap();
+        chip.cycle().unwrap();
+        assert_eq!(chip.V[0], 0x2a);
+        assert_eq!(chip.V[1], 0x01);
+        assert_eq!(chip.pc, 0x204);
+    }
+
+    #[test]
+    fn should_fail_on_unknown_opcode() {
+        let mut chip = init();
+        chip.load(&[0xf0, 0xff]);
+        assert_eq!(chip.cycle(), Err(Chip8Error::UnknownOpcode { addr: 0x200, opcode: 0xf0ff }));
+        assert_eq!(chip.pc, 0x200);
+
+        let mut chip = init();
+        chip.load(&[0x80, 0x18]);
+        assert_eq!(chip.cycle(), Err(Chip8Error::UnknownOpcode { addr: 0x200, opcode: 0x8018 }));
+    }
+
+    #[test]
+    fn should_fail_on_stack_overflow() {
+        let mut chip = init();
+        chip.load(&[0x22, 0x00]);
+        for _ in 0..16 {
+            chip.cycle().unwrap();
+        }
+        assert_eq!(chip.cycle(), Err(Chip8Error::StackOverflow { addr: 0x200 }));
+        assert_eq!(chip.sp, 16);
+    }
+
+    #[test]
+    fn should_fail_on_out_of_bounds_memory() {
+        let mut chip = init();
+        chip.I = 0xffe;
+        chip.load(&[0xf0, 0x33]);
+        assert_eq!(chip.cycle(), Err(Chip8Error::MemoryOutOfBounds { addr: 0x200, access: 0x1000 }));
+        assert_eq!(chip.update_display(0, 0, 5), Err(Chip8Error::MemoryOutOfBounds { addr: 0x200, access: 0x1000 }));
+        chip.I = 0xff8;
+        assert_eq!(chip.store_regs_through(0xf), Err(Chip8Error::MemoryOutOfBounds { addr: 0x200, access: 0x1000 }));
+        assert_eq!(chip.read_to_regs(0xf), Err(Chip8Error::MemoryOutOfBounds { addr: 0x200, access: 0x1000 }));
+        assert_eq!(chip.M[0xff8..], [0; 8]);
+    }
+
+    #[test]
+    fn should_poke_at_registers_and_memory() {
+        let mut chip = init();
+        chip.set_v(0xa, 0x42).unwrap();
+        chip.set_index(0x300);
+        chip.set_delay_timer(9);
+        chip.set_sound_timer(4);
+        assert_eq!((chip.registers()[0xa], chip.i(), chip.dt(), chip.st()), (0x42, 0x300, 9, 4));
+
+        // v0 := va, then an i that a debugger moved the pc past
+        chip.load(&[0x80, 0xa0, 0xa1, 0x23, 0x00, 0xe0]);
+        chip.write_memory(0x204, &[0x60, 0x07]).unwrap();
+        chip.set_pc(0x204);
+        chip.cycle().unwrap();
+        assert_eq!(chip.registers()[0], 0x07);
+        assert_eq!(chip.i(), 0x300);
+
+        assert_eq!(chip.write_memory(0xfff, &[1, 2]), Err(PokeError::OutOfMemory { addr: 0xfff, len: 2, size: 0x1000 }));
+        assert_eq!(chip.memory()[0xfff], 0);
+        assert_eq!(chip.set_v(16, 1), Err(PokeError::NoSuchRegister { x: 16 }));
+        assert_eq!(chip.set_sp(17), Err(PokeError::StackTooDeep { sp: 17 }));
+        assert_eq!(chip.sp(), 0);
+        chip.S[0] = 0x204;
+        chip.set_sp(1).unwrap();
+        chip.load(&[0x00, 0xee]);
+        chip.set_pc(0x200);
+        chip.cycle().unwrap();
+        assert_eq!((chip.pc(), chip.sp()), (0x204, 0));
+    }
+
+    #[test]
+    fn should_report_memory_accesses() {
+        let mut chip = init_xochip();
+        chip.I = 0x300;
+        let access = |chip: &Chip8, instruction| {
+            let MemoryAccess { reads, writes } = chip.memory_access(instruction);
+            (reads.len(), writes.len(), reads.start.min(writes.start))
+        };
+        assert_eq!(access(&chip, Instruction::Draw { x: 0, y: 0, n: 5 }), (5, 0, 0x300));
+        assert_eq!(access(&chip, Instruction::Draw { x: 0, y: 0, n: 0 }), (32, 0, 0x300));
+        assert_eq!(access(&chip, Instruction::StoreRegsThrough { x: 3 }), (0, 4, 0x300));
+        assert_eq!(access(&chip, Instruction::ReadRegRange { x: 5, y: 2 }), (4, 0, 0x300));
+        assert_eq!(access(&chip, Instruction::Bcd { x: 0 }), (0, 3, 0x300));
+        assert_eq!(access(&chip, Instruction::LoadAudio), (16, 0, 0x300));
+        assert_eq!(access(&chip, Instruction::Jump { addr: 0x300 }), (0, 0, 0x300));
+        // Both planes draw a sprite each
+        chip.planes = 3;
+        assert_eq!(access(&chip, Instruction::Draw { x: 0, y: 0, n: 5 }), (10, 0, 0x300));
+    }
+
+    #[test]
+    fn should_fail_when_pc_leaves_memory() {
+        let mut chip = init();
+        chip.pc = 0xfff;
+        assert_eq!(chip.cycle(), Err(Chip8Error::MisalignedPc { addr: 0xfff }));
+        chip.pc = 0x1000;
+        assert_eq!(chip.cycle(), Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000, access: 0x1000 }));
+    }
+
+    #[test]
+    fn should_shift_vx() {
+        let mut chip = init();
+        chip.V[0] = 0x81;
+        chip.V[1] = 0x02;
+        chip.shift_left(0, 1);
+        assert_eq!(chip.V[0], 0x02);
+        assert_eq!(chip.V[0xf], 1);
+        chip.shift_right(0, 1);
+        assert_eq!(chip.V[0], 0x01);
+        assert_eq!(chip.V[0xf], 0);
+    }
+
+    #[test]
+    fn should_shift_vy_with_quirk() {
+        let mut chip = init_with(Quirks::cosmac_vip());
+        chip.V[0] = 0xff;
+        chip.V[1] = 0x03;
+        chip.shift_right(0, 1);
+        assert_eq!(chip.V[0], 0x01);
+        assert_eq!(chip.V[1], 0x03);
+        assert_eq!(chip.V[0xf], 1);
+        chip.shift_left(0, 1);
+        assert_eq!(chip.V[0], 0x06);
+        assert_eq!(chip.V[0xf], 0);
+    }
+
+    #[test]
+    fn should_increment_i_with_quirk() {
+        let mut chip = init_with(Quirks::cosmac_vip());
+        chip.I = 1000;
+        chip.store_regs_through(2).unwrap();
+        assert_eq!(chip.I, 1003);
+        chip.read_to_regs(2).unwrap();
+        assert_eq!(chip.I, 1006);
+
+        let mut chip = init_with(Quirks::chip48());
+        chip.I = 1000;
+        chip.store_regs_through(2).unwrap();
+        assert_eq!(chip.I, 1002);
+
+        let mut chip = init_with(Quirks::superchip());
+        chip.I = 1000;
+        chip.read_to_regs(2).unwrap();
+        assert_eq!(chip.I, 1000);
+    }
+
+    #[test]
+    fn should_reset_vf_on_logic_with_quirk() {
+        let mut chip = init();
+        chip.V[0xf] = 1;
+        chip.or_with_register(0, 1);
+        assert_eq!(chip.V[0xf], 1);
+
+        let mut chip = init_with(Quirks::cosmac_vip());
+        chip.V[0xf] = 1;
+        chip.or_with_register(0, 1);
+        assert_eq!(chip.V[0xf], 0);
+        chip.V[0xf] = 1;
+        chip.and_with_register(0, 1);
+        assert_eq!(chip.V[0xf], 0);
+        chip.V[0xf] = 1;
+        chip.xor_with_register(0, 1);
+        assert_eq!(chip.V[0xf], 0);
+    }
+
+    #[test]
+    fn should_update_display_and_clip_with_quirk() {
+        let mut chip = init_with(Quirks::cosmac_vip());
+        chip.V[0] = 60;
+        chip.V[1] = 30;
+        chip.I = 2048;
+        chip.M[2048] = 0x0f;
+        chip.M[2049] = 0xf0;
+        chip.M[2050] = 0x0f;
+        chip.M[2051] = 0xf0;
+        chip.update_display(0, 1, 4).unwrap();
+        assert_eq!(chip.display[0][0], 0);
+        assert_eq!(chip.display[0][1], 0);
+        assert_eq!(chip.display[0][30], 0x0000000000000000);
+        assert_eq!(chip.display[0][31], 0x000000000000000f);
+    }
+
+    #[test]
+    fn should_wrap_sprite_start_when_clipping() {
+        let mut chip = init_with(Quirks::cosmac_vip());
+        chip.V[0] = 64 + 2;
+        chip.V[1] = 32 + 1;
+        chip.I = 2048;
+        chip.M[2048] = 0xff;
+        chip.update_display(0, 1, 1).unwrap();
+        assert_eq!(chip.display[0][1], 0x3fc0000000000000);
+    }
+
+    #[test]
+    fn should_jump_with_offset() {
+        let mut chip = init();
+        chip.V[0] = 1;
+        chip.V[2] = 2;
+        chip.jump_with_offset(0x234);
+        assert_eq!(chip.pc, 0x235);
+
+        let mut chip = init_with(Quirks::superchip());
+        chip.V[0] = 1;
+        chip.V[2] = 2;
+        chip.jump_with_offset(0x234);
+        assert_eq!(chip.pc, 0x236);
+    }
+
+    #[test]
+    fn should_reject_superchip_opcodes_on_chip8() {
+        let mut chip = init();
+        chip.load(&[0x00, 0xff]);
+        assert_eq!(chip.cycle(), Err(Chip8Error::UnknownOpcode { addr: 0x200, opcode: 0x00ff }));
+        let mut chip = init_superchip();
+        chip.load(&[0x00, 0xff]);
+        chip.cycle().unwrap();
+        assert!(chip.is_hires());
+    }
+
+    #[test]
+    fn should_switch_resolution() {
+        let mut chip = init_superchip();
+        chip.display[0][0] = 1;
+        chip.display[1][0] = 1;
+        chip.set_hires(true);
+        assert_eq!((chip.width(), chip.height()), (128, 64));
+        assert_eq!(chip.display[0][0], 0);
+        assert_eq!(chip.display[1][0], 0);
+        chip.set_hires(false);
+        assert_eq!((chip.width(), chip.height()), (64, 32));
+    }
+
+    #[test]
+    fn should_update_hires_display() {
+        let mut chip = init_superchip();
+        chip.set_hires(true);
+        chip.V[0] = 120;
+        chip.V[1] = 63;
+        chip.I = 2048;
+        chip.M[2048] = 0xff;
+        chip.update_display(0, 1, 1).unwrap();
+        assert_eq!(chip.display[0][63], 0xff);
+        assert!(chip.pixel(120, 63));
+        assert!(chip.pixel(127, 63));
+        assert!(!chip.pixel(119, 63));
+    }
+
+    #[test]
+    fn should_draw_large_sprite() {
+        let mut chip = init_superchip();
+        chip.set_hires(true);
+        chip.I = 2048;
+        for index in 0..32 {
+            chip.M[2048 + index] = if index % 2 == 0 { 0x80 } else { 0x01 };
+        }
+        chip.update_display(0, 0, 0).unwrap();
+        for y in 0..16 {
+            assert_eq!(chip.display[0][y], 0x8001 << 112);
+        }
+        assert_eq!(chip.display[0][16], 0);
+        chip.update_display(0, 0, 0).unwrap();
+        assert_eq!(chip.V[0xf], 1);
+    }
+
+    #[test]
+    fn shouldnt_draw_large_sprite_on_chip8() {
+        let mut chip = init();
+        chip.update_display(0, 0, 0).unwrap();
+        assert_eq!(chip.display[0], [0; 64]);
+    }
+
+    #[test]
+    fn should_scroll() {
+        let mut chip = init_superchip();
+        chip.display[0][0] = 0x0f00000000000000;
+        chip.scroll_down(2);
+        assert_eq!(chip.display[0][0], 0);
+        assert_eq!(chip.display[0][2], 0x0f00000000000000);
+        chip.scroll_left();
+        assert_eq!(chip.display[0][2], 0xf000000000000000);
+        chip.scroll_left();
+        assert_eq!(chip.display[0][2], 0);
+        chip.display[0][2] = 0x1f;
+        chip.scroll_right();
+        assert_eq!(chip.display[0][2], 0x1);
+        chip.scroll_down(31);
+        assert_eq!(chip.display[0][2], 0);
+    }
+
+    #[test]
+    fn should_set_i_to_big_digit_locations() {
+        let mut chip = init_superchip();
+        chip.V[0] = 8;
+        chip.set_i_big_digit_sprite(0);
+        assert_eq!(chip.I, 80 + 8 * 10);
+        assert_eq!(chip.M[chip.I as usize..chip.I as usize + 10], BIG_FONT_SPRITES[80..90]);
+    }
+
+    #[test]
+    fn should_save_and_load_flags() {
+        let mut chip = init_superchip();
+        chip.V[0] = 1;
+        chip.V[1] = 2;
+        chip.V[2] = 3;
+        chip.save_flags(1);
+        assert_eq!(chip.flags[..3], [1, 2, 0]);
+        chip.V = [0; 16];
+        chip.load_flags(2);
+        assert_eq!(chip.V[..3], [1, 2, 0]);
+    }
+
+    #[test]
+    fn should_exit() {
+        let mut chip = init_superchip();
+        chip.load(&[0x00, 0xfd, 0x60, 0x01]);
+        chip.cycle().unwrap();
+        chip.cycle().unwrap();
+        assert!(chip.has_exited());
+        assert_eq!(chip.pc, 0x200);
+        assert_eq!(chip.V[0], 0);
+    }
+
+    #[test]
+    fn should_address_64k_on_xochip() {
+        let mut chip = init_xochip();
+        assert_eq!(chip.memory().len(), 0x10000);
+        chip.load(&[0xf0, 0x00, 0xff, 0xf0, 0xf0, 0x33]);
+        chip.V[0] = 123;
+        chip.cycle().unwrap();
+        assert_eq!(chip.I, 0xfff0);
+        assert_eq!(chip.pc, 0x204);
+        chip.cycle().unwrap();
+        assert_eq!(chip.M[0xfff0..0xfff3], [1, 2, 3]);
+
+        let mut chip = init_superchip();
+        chip.load(&[0xf0, 0x00, 0xff, 0xf0]);
+        assert_eq!(chip.cycle(), Err(Chip8Error::UnknownOpcode { addr: 0x200, opcode: 0xf000 }));
+    }
+
+    #[test]
+    fn should_skip_long_instruction() {
+        let mut chip = init_xochip();
+        chip.load(&[0x30, 0x00, 0xf0, 0x00, 0x12, 0x34]);
+        chip.cycle().unwrap();
+        assert_eq!(chip.pc, 0x206);
+
+        // 9xy0 skips it the same way
+        let mut chip = init_xochip();
+        chip.load(&[0x90, 0x10, 0xf0, 0x00, 0x12, 0x34]);
+        chip.V[0] = 1;
+        chip.cycle().unwrap();
+        assert_eq!(chip.pc, 0x206);
+    }
+
+    #[test]
+    fn shouldnt_move_the_pc_past_the_end_of_memory() {
+        // A skip over f000 nnnn from fffa would land on 10000
+        let mut chip = init_xochip();
+        chip.M[0xfffa..].copy_from_slice(&[0x30, 0x00, 0xf0, 0x00, 0x12, 0x34]);
+        chip.pc = 0xfffa;
+        assert_eq!(chip.cycle(), Err(Chip8Error::MemoryOutOfBounds { addr: 0xfffa, access: 0x10000 }));
+        assert_eq!(chip.pc, 0xfffa);
+
+        // As would a skip or a long load from fffc
+        chip.M[0xfffc..].copy_from_slice(&[0x30, 0x00, 0x12, 0x34]);
+        chip.pc = 0xfffc;
+        assert_eq!(chip.cycle(), Err(Chip8Error::MemoryOutOfBounds { addr: 0xfffc, access: 0x10000 }));
+        chip.M[0xfffc..].copy_from_slice(&[0xf0, 0x00, 0x12, 0x34]);
+        assert_eq!(chip.cycle(), Err(Chip8Error::MemoryOutOfBounds { addr: 0xfffc, access: 0x10000 }));
+        assert_eq!((chip.pc, chip.I), (0xfffc, 0));
+
+        // Anything else still runs there
+        chip.M[0xfffc..].copy_from_slice(&[0x60, 0x2a, 0x12, 0x34]);
+        chip.cycle().unwrap();
+        assert_eq!((chip.pc, chip.V[0]), (0xfffe, 0x2a));
+    }
+
+    #[test]
+    fn shouldnt_run_off_the_end_of_memory() {
+        let mut chip = init_xochip();
+        chip.pc = 0xfffe;
+        assert_eq!(chip.cycle(), Err(Chip8Error::MemoryOutOfBounds { addr: 0xfffe, access: 0x10000 }));
+    }
+
+    #[test]
+    fn should_execute_the_last_word_of_memory() {
+        let mut chip = init();
+        chip.M[0xffe..].copy_from_slice(&[0x60, 0x2a]);
+        chip.pc = 0xffe;
+        chip.cycle().unwrap();
+        assert_eq!(chip.V[0], 0x2a);
+
+        // f000 there has nowhere to keep its address
+        chip.M[0xffe..].copy_from_slice(&[0xf0, 0x00]);
+        chip.pc = 0xffe;
+        assert_eq!(chip.cycle(), Err(Chip8Error::MemoryOutOfBounds { addr: 0xffe, access: 0x1000 }));
+    }
+
+    #[test]
+    fn should_store_and_read_register_ranges() {
+        let mut chip = init_xochip();
+        chip.V[1] = 1;
+        chip.V[2] = 2;
+        chip.V[3] = 3;
+        chip.I = 1000;
+        chip.store_reg_range(1, 3).unwrap();
+        assert_eq!(chip.M[1000..1003], [1, 2, 3]);
+        assert_eq!(chip.I, 1000);
+        chip.store_reg_range(3, 1).unwrap();
+        assert_eq!(chip.M[1000..1003], [3, 2, 1]);
+        chip.read_reg_range(4, 6).unwrap();
+        assert_eq!(chip.V[4..7], [3, 2, 1]);
+        chip.I = 0xffff;
+        assert!(chip.read_reg_range(0, 1).is_err());
+    }
+
+    #[test]
+    fn should_draw_to_selected_planes() {
+        let mut chip = init_xochip();
+        chip.I = 2048;
+        chip.M[2048] = 0x80;
+        chip.M[2049] = 0xc0;
+        chip.select_planes(2);
+        chip.update_display(0, 0, 1).unwrap();
+        assert_eq!(chip.pixel_color(0, 0), 2);
+        chip.select_planes(3);
+        chip.V[1] = 1;
+        chip.update_display(0, 1, 1).unwrap();
+        assert_eq!(chip.pixel_color(0, 1), 3);
+        assert_eq!(chip.pixel_color(1, 1), 2);
+        assert_eq!(chip.V[0xf], 0);
+        chip.select_planes(1);
+        chip.clear_screen();
+        assert_eq!(chip.pixel_color(0, 1), 2);
+        chip.select_planes(0);
+        chip.update_display(0, 1, 1).unwrap();
+        assert_eq!(chip.pixel_color(0, 1), 2);
+    }
+
+    #[test]
+    fn should_scroll_up_selected_planes() {
+        let mut chip = init_xochip();
+        chip.display[0][5] = 1;
+        chip.display[1][5] = 1;
+        chip.select_planes(2);
+        chip.scroll_up(5);
+        assert_eq!(chip.display[0][5], 1);
+        assert_eq!(chip.display[1][0], 1);
+        assert_eq!(chip.display[1][5], 0);
+    }
+
+    #[test]
+    fn should_load_audio_pattern_and_pitch() {
+        let mut chip = init_xochip();
+        chip.I = 0x1000;
+        chip.M[0x1000..0x1010].copy_from_slice(&[0xaa; 16]);
+        chip.load_audio().unwrap();
+        assert_eq!(chip.audio_pattern(), &[0xaa; 16]);
+        chip.V[2] = 112;
+        chip.set_pitch(2);
+        assert_eq!(chip.pitch(), 112);
+        chip.I = 0xfff8;
+        assert!(chip.load_audio().is_err());
+    }
+
+    #[test]
+    fn should_only_play_audio_while_sound_timer_runs() {
+        let mut chip = init_xochip();
+        chip.voice.pattern = [0xff; 16];
+        let mut out = [1.0; 4];
+        chip.fill_audio(&mut out, 44100);
+        assert_eq!(out, [0.0; 4]);
+        chip.st = 2;
+        chip.fill_audio(&mut out, 44100);
+        assert_eq!(out, [1.0; 4]);
+    }
+
+    #[test]
+    fn shouldnt_tick_timers_when_cycling() {
+        let mut chip = init();
+        chip.load(&[0x60, 0x00, 0x60, 0x00]);
+        chip.dt = 5;
+        chip.st = 1;
+        chip.cycle().unwrap();
+        assert_eq!(chip.dt, 5);
+        chip.tick_timers();
+        assert_eq!(chip.dt, 4);
+        assert_eq!(chip.st, 0);
+        chip.tick_timers();
+        assert_eq!(chip.st, 0);
+    }
+
+    #[test]
+    fn should_run_a_frame() {
+        let mut chip = init();
+        // loop: v0 += 1, jump loop
+        chip.load(&[0x70, 0x01, 0x12, 0x00]);
+        chip.dt = 5;
+        chip.set_instructions_per_frame(6);
+        chip.run_frame().unwrap();
+        assert_eq!(chip.V[0], 3);
+        assert_eq!(chip.dt, 4);
+    }
+
+    #[test]
+    fn should_tick_timers_while_waiting_for_input() {
+        let mut chip = init();
+        chip.load(&[0xf0, 0x0a]);
+        chip.dt = 5;
+        chip.run_frame().unwrap();
+        chip.run_frame().unwrap();
+        assert!(chip.is_waiting());
+        assert_eq!(chip.pc, 0x202);
+        assert_eq!(chip.dt, 3);
+    }
+
+    #[test]
+    fn shouldnt_tick_timers_after_a_failed_frame() {
+        let mut chip = init();
+        chip.load(&[0xff, 0xff]);
+        chip.dt = 5;
+        assert!(chip.run_frame().is_err());
+        assert_eq!(chip.dt, 5);
+    }
+
+    #[test]
+    fn should_run_vip_frames_by_cycle_cost() {
+        let mut chip = init();
+        // loop: v0 += 1, jump loop
+        chip.load(&[0x70, 0x01, 0x12, 0x00]);
+        chip.set_timing(Timing::CosmacVip);
+        chip.run_frame().unwrap();
+        // 2598 cycles at 50 for v0 += 1 and 52 for the jump
+        assert_eq!(chip.V[0], 26);
+        assert_eq!(chip.cycle_budget, -2);
+        chip.run_frame().unwrap();
+        assert_eq!(chip.V[0], 51);
+    }
+
+    #[test]
+    fn should_wait_for_vblank_after_drawing() {
+        let mut chip = init();
+        // loop: sprite v0 v0 1, v1 += 1, jump loop
+        chip.load(&[0xd0, 0x01, 0x71, 0x01, 0x12, 0x00]);
+        chip.set_timing(Timing::CosmacVip);
+        chip.dt = 3;
+        chip.run_frame().unwrap();
+        assert_eq!(chip.pc, 0x202);
+        assert_eq!(chip.V[1], 0);
+        assert_eq!(chip.dt, 2);
+        chip.run_frame().unwrap();
+        assert_eq!(chip.V[1], 1);
+        assert_eq!(chip.pc, 0x202);
+    }
+
+    #[test]
+    fn should_carry_long_instructions_into_the_next_frame() {
+        let mut chip = init();
+        chip.load(&[0x00, 0xe0, 0x00, 0xe0, 0x60, 0x01, 0x12, 0x06]);
+        chip.set_timing(Timing::CosmacVip);
+        chip.run_frame().unwrap();
+        assert_eq!(chip.pc, 0x202);
+        chip.run_frame().unwrap();
+        assert_eq!(chip.pc, 0x204);
+        chip.run_frame().unwrap();
+        assert_eq!(chip.V[0], 1);
+    }
+
+    #[test]
+    fn should_restore_saved_state() {
+        let mut chip = init_xochip();
+        // v0 := random 0xff, sprite v0 v0 1, loop
+        chip.load(&[0xc0, 0xff, 0xd0, 0x01, 0x12, 0x04]);
+        chip.I = 0x300;
+        chip.M[0x300] = 0xff;
+        chip.dt = 7;
+        chip.voice.pitch = 100;
+        chip.update_input([true; 16]);
+        let saved = chip.save_state();
+        chip.cycle().unwrap();
+        let random = chip.V[0];
+        chip.cycle().unwrap();
+        let display = chip.display;
+
+        let mut other = init_xochip();
+        other.load(&[0xc0, 0xff, 0xd0, 0x01, 0x12, 0x04]);
+        other.load_state(&saved).unwrap();
+        assert_eq!(other.I, 0x300);
+        assert_eq!(other.dt, 7);
+        assert_eq!(other.pitch(), 100);
+        assert_eq!(other.input, [true; 16]);
+        // The RNG carries on from the same place
+        other.cycle().unwrap();
+        assert_eq!(other.V[0], random);
+        other.cycle().unwrap();
+        assert_eq!(other.display, display);
+        assert_eq!(other.save_state(), chip.save_state());
+    }
+
+    #[test]
+    fn should_reject_mismatched_states() {
+        let mut chip = init();
+        chip.load(&[0x12, 0x00]);
+        let saved = chip.save_state();
+
+        let mut other = init();
+        other.load(&[0x12, 0x02]);
+        assert!(matches!(other.load_state(&saved), Err(StateError::RomMismatch { .. })));
+
+        let mut other = init_xochip();
+        other.load(&[0x12, 0x00]);
+        assert_eq!(other.load_state(&saved), Err(StateError::PlatformMismatch));
+
+        let mut other = init();
+        other.load(&[0x12, 0x00]);
+        assert_eq!(other.load_state(b"nope"), Err(StateError::NotAState));
+        assert_eq!(other.load_state(&saved[..saved.len() - 1]), Err(StateError::Truncated));
+        let mut newer = saved.clone();
+        newer[4] = 2;
+        assert_eq!(other.load_state(&newer), Err(StateError::UnsupportedVersion { version: 2 }));
+        assert_eq!(other.pc, 0x200);
+        assert_eq!(other.M[0x202], 0);
+    }
+}
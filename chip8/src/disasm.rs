@@ -0,0 +1,182 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::chip8::PROGRAM_START;
+use crate::instruction::{decode_long, Instruction};
+
+/// One line of a disassembly listing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    /// Address of the first byte.
+    pub addr: u16,
+    /// The raw bytes, two or four for an instruction or one for data.
+    pub bytes: Vec<u8>,
+    /// The label generated for this address if something jumps or calls here.
+    pub label: Option<String>,
+    /// The decoded instruction, `None` for data.
+    pub instruction: Option<Instruction>,
+    /// The mnemonic, with jump and call targets replaced by their labels.
+    pub text: String,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(label) = &self.label {
+            writeln!(f, ": {}", label)?;
+        }
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(f, "{:03X}  {:<5}  {}", self.addr, bytes.join(" "), self.text)
+    }
+}
+
+/// Disassembles `rom` as if it was loaded at `origin`.
+///
+/// The ROM is swept one instruction at a time the same way [`Chip8::cycle`](crate::Chip8::cycle)
+/// fetches opcodes, so XO-CHIP's `f000 nnnn` takes up four bytes. Anything that doesn't decode, and a trailing odd byte,
+/// comes out as `db` data. Targets of `jump`, `jump0` and `:call` that land
+/// on the start of a line get a label named after their address.
+pub fn disassemble(rom: &[u8], origin: u16) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut index = 0;
+
+    while index < rom.len() {
+        let addr = origin.wrapping_add(index as u16);
+        let word = |at: usize| Some((*rom.get(at)? as u16) << 8 | *rom.get(at + 1)? as u16);
+        let decoded = match (word(index), word(index + 2)) {
+            (Some(opcode), next) => decode_long(opcode, next.unwrap_or(0))
+                .ok()
+                .filter(|instruction| next.is_some() || instruction.size() == 2),
+            _ => None,
+        };
+
+        match decoded {
+            Some(instruction) => {
+                let size = instruction.size() as usize;
+                lines.push(Line {
+                    addr,
+                    bytes: rom[index..index + size].to_vec(),
+                    label: None,
+                    instruction: Some(instruction),
+                    text: instruction.to_string(),
+                });
+                index += size;
+            }
+            None => {
+                for (offset, &byte) in rom[index..rom.len().min(index + 2)].iter().enumerate() {
+                    lines.push(Line {
+                        addr: addr.wrapping_add(offset as u16),
+                        bytes: vec![byte],
+                        label: None,
+                        instruction: None,
+                        text: format!("db 0x{:02X}", byte),
+                    });
+                }
+                index += 2;
+            }
+        }
+    }
+
+    let starts: BTreeSet<u16> = lines.iter().map(|line| line.addr).collect();
+    let targets: BTreeSet<u16> = lines
+        .iter()
+        .filter_map(|line| target(line.instruction?))
+        .filter(|addr| starts.contains(addr))
+        .collect();
+
+    for line in &mut lines {
+        if targets.contains(&line.addr) {
+            line.label = Some(label(line.addr));
+        }
+        if let Some(instruction) = line.instruction {
+            if let Some(addr) = target(instruction).filter(|addr| targets.contains(addr)) {
+                line.text = match instruction {
+                    Instruction::Jump { .. } => format!("jump {}", label(addr)),
+                    Instruction::JumpPlusV0 { .. } => format!("jump0 {}", label(addr)),
+                    _ => label(addr),
+                };
+            }
+        }
+    }
+
+    lines
+}
+
+/// Disassembles a ROM loaded at `PROGRAM_START` into a printable listing.
+pub fn listing(rom: &[u8]) -> String {
+    disassemble(rom, PROGRAM_START)
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+fn label(addr: u16) -> String {
+    format!("L{:03X}", addr)
+}
+
+fn target(instruction: Instruction) -> Option<u16> {
+    match instruction {
+        Instruction::Jump { addr } | Instruction::JumpPlusV0 { addr } | Instruction::Call { addr } => Some(addr),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_disassemble_instructions() {
+        let lines = disassemble(&[0x60, 0x05, 0xf0, 0x29], 0x200);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].addr, 0x200);
+        assert_eq!(lines[0].bytes, [0x60, 0x05]);
+        assert_eq!(lines[0].text, "v0 := 0x05");
+        assert_eq!(lines[1].addr, 0x202);
+        assert_eq!(lines[1].text, "i := hex v0");
+    }
+
+    #[test]
+    fn should_label_jump_and_call_targets() {
+        let lines = disassemble(&[0x22, 0x04, 0x12, 0x00, 0x00, 0xee], 0x200);
+        assert_eq!(lines[0].label.as_deref(), Some("L200"));
+        assert_eq!(lines[0].text, "L204");
+        assert_eq!(lines[1].label, None);
+        assert_eq!(lines[1].text, "jump L200");
+        assert_eq!(lines[2].label.as_deref(), Some("L204"));
+        assert_eq!(lines[2].text, "return");
+    }
+
+    #[test]
+    fn shouldnt_label_targets_outside_the_rom() {
+        let lines = disassemble(&[0x13, 0x00], 0x200);
+        assert_eq!(lines[0].label, None);
+        assert_eq!(lines[0].text, "jump 0x300");
+    }
+
+    #[test]
+    fn should_fall_back_to_bytes() {
+        let lines = disassemble(&[0xff, 0xff, 0x00, 0xe0, 0x12], 0x200);
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0].text, "db 0xFF");
+        assert_eq!(lines[1].addr, 0x201);
+        assert_eq!(lines[2].text, "clear");
+        assert_eq!(lines[3].addr, 0x204);
+        assert_eq!(lines[3].text, "db 0x12");
+    }
+
+    #[test]
+    fn should_list() {
+        assert_eq!(listing(&[0x12, 0x00, 0xff]), ": L200\n200  12 00  jump L200\n202  FF     db 0xFF\n");
+    }
+
+    #[test]
+    fn should_disassemble_long_instructions() {
+        let lines = disassemble(&[0xf0, 0x00, 0x12, 0x34, 0x00, 0xe0, 0xf0, 0x00], 0x200);
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0].bytes, [0xf0, 0x00, 0x12, 0x34]);
+        assert_eq!(lines[0].text, "i := long 0x1234");
+        assert_eq!(lines[1].addr, 0x204);
+        assert_eq!(lines[2].text, "db 0xF0");
+        assert_eq!(lines[3].addr, 0x207);
+    }
+}
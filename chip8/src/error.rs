@@ -0,0 +1,80 @@
+use std::error::Error;
+use std::fmt;
+
+/// Why [`Chip8::cycle`](crate::Chip8::cycle) couldn't execute an instruction.
+///
+/// Every variant carries `addr`, the address of the faulting instruction. The
+/// machine is left as it was before that instruction, so a frontend can show
+/// the error and stop without anything having been half executed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// The opcode at `addr` isn't a known instruction.
+    UnknownOpcode { addr: u16, opcode: u16 },
+    /// A `2nnn` call at `addr` with all 16 stack slots in use.
+    StackOverflow { addr: u16 },
+    /// A `00ee` return at `addr` with nothing on the stack.
+    StackUnderflow { addr: u16 },
+    /// The instruction at `addr` touches memory at `access`, past the end of RAM.
+    MemoryOutOfBounds { addr: u16, access: usize },
+    /// The program counter `addr` isn't on an instruction boundary.
+    MisalignedPc { addr: u16 },
+}
+
+impl Chip8Error {
+    /// The address of the instruction that faulted.
+    pub fn addr(&self) -> u16 {
+        match *self {
+            Chip8Error::UnknownOpcode { addr, .. } => addr,
+            Chip8Error::StackOverflow { addr } => addr,
+            Chip8Error::StackUnderflow { addr } => addr,
+            Chip8Error::MemoryOutOfBounds { addr, .. } => addr,
+            Chip8Error::MisalignedPc { addr } => addr,
+        }
+    }
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Chip8Error::UnknownOpcode { addr, opcode } => {
+                write!(f, "unknown opcode {:04X} at {:03X}", opcode, addr)
+            }
+            Chip8Error::StackOverflow { addr } => write!(f, "stack overflow at {:03X}", addr),
+            Chip8Error::StackUnderflow { addr } => write!(f, "stack underflow at {:03X}", addr),
+            Chip8Error::MemoryOutOfBounds { addr, access } => {
+                write!(f, "out of bounds memory access to {:X} at {:03X}", access, addr)
+            }
+            Chip8Error::MisalignedPc { addr } => write!(f, "misaligned program counter {:03X}", addr),
+        }
+    }
+}
+
+impl Error for Chip8Error {}
+
+/// Why a change a debugger or tool asked for, such as
+/// [`Chip8::set_sp`](crate::Chip8::set_sp), couldn't be made. The machine is
+/// left as it was.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PokeError {
+    /// There's no register `Vx`, as `x` is 16 or more.
+    NoSuchRegister { x: usize },
+    /// A stack pointer of `sp` is past the 16 slots on the stack.
+    StackTooDeep { sp: u8 },
+    /// Writing `len` bytes at `addr` runs past the end of memory, which is
+    /// `size` bytes.
+    OutOfMemory { addr: u16, len: usize, size: usize },
+}
+
+impl fmt::Display for PokeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PokeError::NoSuchRegister { x } => write!(f, "no register V{}, only V0 to VF", x),
+            PokeError::StackTooDeep { sp } => write!(f, "stack pointer {} is past the 16 stack slots", sp),
+            PokeError::OutOfMemory { addr, len, size } => {
+                write!(f, "{} bytes at {:03X} run past the end of memory at {:X}", len, addr, size)
+            }
+        }
+    }
+}
+
+impl Error for PokeError {}
@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::chip8::{Chip8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::rng::RandomSource;
+
+// Frames per second of emulated time, which frame delays are counted in
+const FRAME_RATE: u64 = 60;
+
+// Shortest delay, in hundredths of a second, that viewers actually honour.
+// Most show anything quicker at a tenth of a second.
+const MIN_DELAY: u64 = 2;
+
+// Bits per pixel, the smallest LZW code size GIF allows, enough for four colors
+const MIN_CODE_SIZE: u8 = 2;
+
+// LZW codes are at most 12 bits
+const MAX_CODES: u16 = 1 << 12;
+
+/// Records the display to an endlessly looping animated GIF.
+///
+/// Like [`WavRecorder`](crate::WavRecorder), recording follows emulated
+/// frames: call [`GifRecorder::record_frame`] once per frame and the GIF
+/// plays back at the game's 60 Hz. A frame that looks the same as the one
+/// before only makes that one stay up longer, so a mostly still game makes a
+/// small file. Frames shown for less than a 50th of a second, which GIF
+/// viewers won't play that quickly, give way to the frame after them, or the
+/// one before at the end of the recording, so the delays always add up to
+/// the recording's length.
+pub struct GifRecorder<W: Write> {
+    writer: W,
+    width: usize,
+    height: usize,
+    frames: u64, // Frames recorded so far
+    pending: Option<(Vec<u8>, u64)>, // The image on screen and the frame it appeared on, finished once it changes
+    finished: Option<(Vec<u8>, u64)>, // The last finished image and its delay, written once the next one is
+    written: usize, // Images written to the file
+}
+
+impl<W: Write> GifRecorder<W> {
+    /// Starts a recording with `palette` as its colors. Lores pixels come out
+    /// as `scale` squares and hires pixels half that, so the GIF is 64 *
+    /// `scale` by 32 * `scale` whatever resolution the game uses. At odd
+    /// scales hires pixels alternate between a pixel narrower and wider.
+    pub fn new(mut writer: W, scale: usize, palette: &[[u8; 3]; 4]) -> io::Result<GifRecorder<W>> {
+        let scale = scale.max(1);
+        let (width, height) = (DISPLAY_WIDTH * scale, DISPLAY_HEIGHT * scale);
+        writer.write_all(b"GIF89a")?;
+        writer.write_all(&(width as u16).to_le_bytes())?;
+        writer.write_all(&(height as u16).to_le_bytes())?;
+        // A global color table of 2 ^ (1 + 1) colors, with no background color or aspect ratio
+        writer.write_all(&[0x91, 0, 0])?;
+        writer.write_all(&palette.concat())?;
+        // The Netscape extension, looping forever
+        writer.write_all(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+        Ok(GifRecorder { writer, width, height, frames: 0, pending: None, finished: None, written: 0 })
+    }
+
+    /// Adds the display as it is now. Call this once per frame, after
+    /// [`Chip8::run_frame`].
+    pub fn record_frame<R: RandomSource>(&mut self, chip: &Chip8<R>) -> io::Result<()> {
+        let mut pixels = Vec::with_capacity(self.width * self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                pixels.push(chip.pixel_color(x * chip.width() / self.width, y * chip.height() / self.height));
+            }
+        }
+        let frame = self.frames;
+        self.frames += 1;
+
+        self.pending = match self.pending.take() {
+            Some((image, start)) if image == pixels => Some((image, start)),
+            Some((image, start)) if centis(frame) - centis(start) >= MIN_DELAY => {
+                if let Some((before, delay)) = self.finished.replace((image, centis(frame) - centis(start))) {
+                    self.write_image(&before, delay)?;
+                }
+                Some((pixels, frame))
+            }
+            // Not up for long enough to see, the new image takes its place
+            Some((_, start)) => Some((pixels, start)),
+            None => Some((pixels, frame)),
+        };
+        Ok(())
+    }
+
+    /// How many distinct images have been recorded so far, not counting the
+    /// one on screen now.
+    pub fn images(&self) -> usize {
+        self.written + self.finished.is_some() as usize
+    }
+
+    /// Writes the last image and ends the GIF, handing back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some((image, start)) = self.pending.take() {
+            let delay = centis(self.frames) - centis(start);
+            match &mut self.finished {
+                // Too short to see, the image before stays up instead
+                Some((_, before)) if delay < MIN_DELAY => *before += delay,
+                _ => {
+                    if let Some((before, delay)) = self.finished.replace((image, delay)) {
+                        self.write_image(&before, delay)?;
+                    }
+                }
+            }
+        }
+        if let Some((image, delay)) = self.finished.take() {
+            self.write_image(&image, delay)?;
+        }
+        self.writer.write_all(b"\x3b")?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_image(&mut self, pixels: &[u8], delay: u64) -> io::Result<()> {
+        let (width, height) = (self.width as u16, self.height as u16);
+        // The graphic control extension, holding the delay in hundredths of a second
+        self.writer.write_all(&[0x21, 0xf9, 4, 0])?;
+        self.writer.write_all(&(delay.min(u16::MAX as u64) as u16).to_le_bytes())?;
+        self.writer.write_all(&[0, 0])?;
+
+        // An image covering the whole screen, using the global color table
+        self.writer.write_all(&[0x2c, 0, 0, 0, 0])?;
+        self.writer.write_all(&width.to_le_bytes())?;
+        self.writer.write_all(&height.to_le_bytes())?;
+        self.writer.write_all(&[0, MIN_CODE_SIZE])?;
+        for block in lzw_encode(pixels).chunks(255) {
+            self.writer.write_all(&[block.len() as u8])?;
+            self.writer.write_all(block)?;
+        }
+        self.writer.write_all(&[0])?;
+        self.written += 1;
+        Ok(())
+    }
+}
+
+// Hundredths of a second from the start of the recording to `frame`
+fn centis(frame: u64) -> u64 {
+    (frame * 100 + FRAME_RATE / 2) / FRAME_RATE
+}
+
+// Packs LZW codes into bytes least significant bit first
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+// Compresses color indices with GIF's variable width LZW, starting over with
+// a clear code whenever the code table fills up
+fn lzw_encode(pixels: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << MIN_CODE_SIZE;
+    let end = clear + 1;
+    let mut out = BitWriter { bytes: Vec::new(), buffer: 0, bits: 0 };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = end + 1;
+    let mut size = MIN_CODE_SIZE + 1;
+
+    out.write(clear, size);
+    let mut pixels = pixels.iter();
+    let mut prefix = match pixels.next() {
+        Some(&pixel) => pixel as u16,
+        None => {
+            out.write(end, size);
+            return out.finish();
+        }
+    };
+
+    for &pixel in pixels {
+        if let Some(&code) = table.get(&(prefix, pixel)) {
+            prefix = code;
+            continue;
+        }
+        out.write(prefix, size);
+        if next < MAX_CODES {
+            table.insert((prefix, pixel), next);
+            next += 1;
+            // The decoder only learns each code a step later, so it widens one code behind us
+            if next > 1 << size && size < 12 {
+                size += 1;
+            }
+        } else {
+            out.write(clear, size);
+            table.clear();
+            next = end + 1;
+            size = MIN_CODE_SIZE + 1;
+        }
+        prefix = pixel as u16;
+    }
+    out.write(prefix, size);
+    out.write(end, size);
+    out.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Platform;
+    use crate::quirks::Quirks;
+    use crate::screenshot::DEFAULT_PALETTE;
+    use gif::SetParameter;
+
+    // Decodes a GIF into its frames' delays and color indices
+    fn decode(bytes: &[u8]) -> Vec<(u16, Vec<u8>)> {
+        let mut decoder = gif::Decoder::new(bytes);
+        decoder.set(gif::ColorOutput::Indexed);
+        let mut reader = decoder.read_info().unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = reader.read_next_frame().unwrap() {
+            frames.push((frame.delay, frame.buffer.to_vec()));
+        }
+        frames
+    }
+
+    // Counts v0 up each frame and draws its digit, so the screen changes every frame
+    fn counter() -> Chip8 {
+        let mut chip = Chip8::new();
+        chip.load(&[0x00, 0xe0, 0x70, 0x01, 0xf0, 0x29, 0xd1, 0x15, 0x12, 0x00]);
+        chip.set_instructions_per_frame(5);
+        chip
+    }
+
+    #[test]
+    fn should_round_trip_through_lzw() {
+        let mut pixels: Vec<u8> = (0..20000u32).map(|i| (i * 7 / 3 % 4) as u8).collect();
+        // Noise fills the code table, making the encoder clear it
+        let mut state = 1u32;
+        pixels.extend((0..20000).map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8 & 3
+        }));
+        let mut gif = Vec::new();
+        gif.extend_from_slice(b"GIF89a\xc8\x00\xc8\x00\x91\x00\x00");
+        gif.extend_from_slice(&DEFAULT_PALETTE.concat());
+        gif.extend_from_slice(b"\x2c\x00\x00\x00\x00\xc8\x00\xc8\x00\x00\x02");
+        for block in lzw_encode(&pixels).chunks(255) {
+            gif.push(block.len() as u8);
+            gif.extend_from_slice(block);
+        }
+        gif.extend_from_slice(b"\x00\x3b");
+        assert_eq!(decode(&gif)[0].1, pixels);
+    }
+
+    #[test]
+    fn should_record_frames_at_60hz() {
+        let mut chip = counter();
+        let mut recorder = GifRecorder::new(Vec::new(), 2, &DEFAULT_PALETTE).unwrap();
+        for _ in 0..6 {
+            chip.run_frame().unwrap();
+            recorder.record_frame(&chip).unwrap();
+        }
+        let frames = decode(&recorder.finish().unwrap());
+
+        // Delays alternate to keep in step with 60 Hz in hundredths of a second
+        assert_eq!(frames.iter().map(|frame| frame.0).collect::<Vec<_>>(), vec![2, 3, 2, 3]);
+        let total: u16 = frames.iter().map(|frame| frame.0).sum();
+        assert_eq!(total, 10);
+        // Lores pixels are doubled at scale 2, the top of the 1 at x = 2 covering 4 and 5
+        assert_eq!(frames[0].1.len(), 128 * 64);
+        assert_eq!(frames[0].1[..8], [0, 0, 0, 0, 1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn should_give_a_short_last_frame_to_the_one_before() {
+        let mut chip = counter();
+        let mut recorder = GifRecorder::new(Vec::new(), 1, &DEFAULT_PALETTE).unwrap();
+        for _ in 0..5 {
+            chip.run_frame().unwrap();
+            recorder.record_frame(&chip).unwrap();
+        }
+        let frames = decode(&recorder.finish().unwrap());
+
+        // The fifth frame only lasts a hundredth of a second, which goes to the fourth
+        assert_eq!(frames.iter().map(|frame| frame.0).collect::<Vec<_>>(), vec![2, 3, 3]);
+        assert_eq!(frames.iter().map(|frame| frame.0 as u64).sum::<u64>(), centis(5));
+    }
+
+    #[test]
+    fn should_size_hires_frames_like_lores_ones() {
+        let mut chip = Chip8::with_platform(Platform::SuperChip, Quirks::superchip());
+        // hires, then the top row of the 0 at the top left
+        chip.load(&[0x00, 0xff, 0xd0, 0x01, 0x12, 0x04]);
+        let mut recorder = GifRecorder::new(Vec::new(), 3, &DEFAULT_PALETTE).unwrap();
+        chip.run_frame().unwrap();
+        recorder.record_frame(&chip).unwrap();
+        let frames = decode(&recorder.finish().unwrap());
+
+        assert_eq!(frames[0].1.len(), 192 * 96);
+        // Four hires pixels a pixel and a half wide each
+        assert_eq!(frames[0].1[..8], [1, 1, 1, 1, 1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn should_hold_unchanged_frames() {
+        let mut chip = Chip8::new();
+        chip.load(&[0xd0, 0x01, 0x12, 0x02]);
+        let mut recorder = GifRecorder::new(Vec::new(), 4, &DEFAULT_PALETTE).unwrap();
+        for _ in 0..120 {
+            chip.run_frame().unwrap();
+            recorder.record_frame(&chip).unwrap();
+        }
+        assert_eq!(recorder.images(), 0);
+        let frames = decode(&recorder.finish().unwrap());
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].0, 200);
+        assert_eq!(frames[0].1.len(), 256 * 128);
+    }
+}
@@ -0,0 +1,461 @@
+use std::error::Error;
+use std::fmt;
+
+/// A decoded CHIP-8 instruction.
+///
+/// `x` and `y` are register indices (`0x0` to `0xf`), `byte` is an 8 bit
+/// immediate, `addr` is a 12 bit address and `n` is a 4 bit sprite height.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    /// 00e0: Clear screen
+    ClearScreen,
+    /// 00ee: Return from subroutine
+    Return,
+    /// 00cn: Scroll the display down n pixels (SUPER-CHIP)
+    ScrollDown { n: u8 },
+    /// 00dn: Scroll the display up n pixels (XO-CHIP)
+    ScrollUp { n: u8 },
+    /// 00fb: Scroll the display right 4 pixels (SUPER-CHIP)
+    ScrollRight,
+    /// 00fc: Scroll the display left 4 pixels (SUPER-CHIP)
+    ScrollLeft,
+    /// 00fd: Exit the interpreter (SUPER-CHIP)
+    Exit,
+    /// 00fe: Switch to the 64x32 display (SUPER-CHIP)
+    Lores,
+    /// 00ff: Switch to the 128x64 display (SUPER-CHIP)
+    Hires,
+    /// 1nnn: Jump to location nnn
+    Jump { addr: u16 },
+    /// 2nnn: Call subroutine at nnn
+    Call { addr: u16 },
+    /// 3xkk: Skip next instruction if Vx == kk
+    SkipIfRegEqualsByte { x: u8, byte: u8 },
+    /// 4xkk: Skip next instruction if Vx != kk
+    SkipIfRegNotEqualsByte { x: u8, byte: u8 },
+    /// 5xy0: Skip next instruction if Vx == Vy
+    SkipIfRegEqualsReg { x: u8, y: u8 },
+    /// 6xkk: Set Vx to kk
+    SetRegister { x: u8, byte: u8 },
+    /// 7xkk: Set Vx = Vx + kk
+    AddToRegister { x: u8, byte: u8 },
+    /// 8xy0: Set Vx = Vy
+    CopyToRegister { x: u8, y: u8 },
+    /// 8xy1: Set Vx = Vx OR Vy
+    OrWithRegister { x: u8, y: u8 },
+    /// 8xy2: Set Vx = Vx AND Vy
+    AndWithRegister { x: u8, y: u8 },
+    /// 8xy3: Set Vx = Vx XOR Vy
+    XorWithRegister { x: u8, y: u8 },
+    /// 8xy4: Set Vx = Vx + Vy, set VF = carry
+    AddRegisters { x: u8, y: u8 },
+    /// 8xy5: Set Vx = Vx - Vy, set VF = NOT borrow
+    Sub { x: u8, y: u8 },
+    /// 8xy6: Set Vx = Vx SHR 1
+    ShiftRight { x: u8, y: u8 },
+    /// 8xy7: Set Vx = Vy - Vx, set VF = NOT borrow
+    SubReverse { x: u8, y: u8 },
+    /// 8xye: Set Vx = Vx SHL 1
+    ShiftLeft { x: u8, y: u8 },
+    /// 5xy2: Store registers Vx through Vy in memory starting at location I (XO-CHIP)
+    StoreRegRange { x: u8, y: u8 },
+    /// 5xy3: Read registers Vx through Vy from memory starting at location I (XO-CHIP)
+    ReadRegRange { x: u8, y: u8 },
+    /// 9xy0: Skip next instruction if Vx != Vy
+    SkipIfRegNotEqualsReg { x: u8, y: u8 },
+    /// annn: Set I to the address nnn
+    SetI { addr: u16 },
+    /// bnnn: Jump to location nnn + V0
+    JumpPlusV0 { addr: u16 },
+    /// cxkk: Set Vx = random byte AND kk
+    Random { x: u8, byte: u8 },
+    /// dxyn: Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision.
+    /// On SUPER-CHIP dxy0 draws a 16x16 sprite
+    Draw { x: u8, y: u8, n: u8 },
+    /// ex9e: Skip next instruction if key with the value of Vx is pressed
+    SkipIfPressed { x: u8 },
+    /// exa1: Skip next instruction if key with the value of Vx is not pressed
+    SkipIfNotPressed { x: u8 },
+    /// f000 nnnn: Set I to the 16 bit address nnnn (XO-CHIP)
+    SetILong { addr: u16 },
+    /// fn01: Select the display planes in bitmask n for drawing, scrolling and clearing (XO-CHIP)
+    SelectPlanes { n: u8 },
+    /// f002: Load the 16 byte audio pattern from memory starting at location I (XO-CHIP)
+    LoadAudio,
+    /// fx07: Set Vx = delay timer value
+    SetRegToDt { x: u8 },
+    /// fx0a: Wait for a key press, store the value of the key in Vx
+    WaitForInput { x: u8 },
+    /// fx15: Set delay timer = Vx
+    SetDt { x: u8 },
+    /// fx18: Set sound timer = Vx
+    SetSt { x: u8 },
+    /// fx1e: Set I = I + Vx
+    IPlusReg { x: u8 },
+    /// fx29: Set I = location of sprite for digit Vx
+    SetIDigitSprite { x: u8 },
+    /// fx30: Set I = location of the large sprite for digit Vx (SUPER-CHIP)
+    SetIBigDigitSprite { x: u8 },
+    /// fx33: Store BCD representation of Vx in memory locations I, I+1, and I+2
+    Bcd { x: u8 },
+    /// fx3a: Set the audio pitch register = Vx (XO-CHIP)
+    SetPitch { x: u8 },
+    /// fx55: Store registers V0 through Vx in memory starting at location I
+    StoreRegsThrough { x: u8 },
+    /// fx65: Read registers V0 through Vx from memory starting at location I
+    ReadToRegs { x: u8 },
+    /// fx75: Store registers V0 through Vx in the RPL flags (SUPER-CHIP)
+    SaveFlags { x: u8 },
+    /// fx85: Read registers V0 through Vx from the RPL flags (SUPER-CHIP)
+    LoadFlags { x: u8 },
+}
+
+impl Instruction {
+    /// Whether this instruction only exists on SUPER-CHIP and its descendants.
+    pub fn is_superchip(&self) -> bool {
+        use self::Instruction::*;
+
+        matches!(
+            *self,
+            ScrollDown { .. } | ScrollRight | ScrollLeft | Exit | Lores | Hires
+                | SetIBigDigitSprite { .. } | SaveFlags { .. } | LoadFlags { .. }
+        )
+    }
+
+    /// Whether this instruction only exists on XO-CHIP.
+    pub fn is_xochip(&self) -> bool {
+        use self::Instruction::*;
+
+        matches!(
+            *self,
+            ScrollUp { .. } | StoreRegRange { .. } | ReadRegRange { .. } | SetILong { .. } | SelectPlanes { .. }
+                | LoadAudio | SetPitch { .. }
+        )
+    }
+
+    /// The size of the instruction in bytes, 4 for `f000 nnnn` and 2 for
+    /// everything else.
+    pub fn size(&self) -> u16 {
+        match *self {
+            Instruction::SetILong { .. } => 4,
+            _ => 2,
+        }
+    }
+}
+
+/// An opcode that doesn't decode to any [`Instruction`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeError(pub u16);
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown opcode {:04X}", self.0)
+    }
+}
+
+impl Error for DecodeError {}
+
+impl fmt::Display for Instruction {
+    /// Formats the instruction using Octo's syntax, e.g. `v3 += 0x01`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Instruction::*;
+
+        match *self {
+            ClearScreen => write!(f, "clear"),
+            Return => write!(f, "return"),
+            ScrollUp { n } => write!(f, "scroll-up 0x{:X}", n),
+            ScrollDown { n } => write!(f, "scroll-down 0x{:X}", n),
+            ScrollRight => write!(f, "scroll-right"),
+            ScrollLeft => write!(f, "scroll-left"),
+            Exit => write!(f, "exit"),
+            Lores => write!(f, "lores"),
+            Hires => write!(f, "hires"),
+            Jump { addr } => write!(f, "jump 0x{:03X}", addr),
+            Call { addr } => write!(f, ":call 0x{:03X}", addr),
+            SkipIfRegEqualsByte { x, byte } => write!(f, "if v{:x} != 0x{:02X} then", x, byte),
+            SkipIfRegNotEqualsByte { x, byte } => write!(f, "if v{:x} == 0x{:02X} then", x, byte),
+            SkipIfRegEqualsReg { x, y } => write!(f, "if v{:x} != v{:x} then", x, y),
+            SetRegister { x, byte } => write!(f, "v{:x} := 0x{:02X}", x, byte),
+            AddToRegister { x, byte } => write!(f, "v{:x} += 0x{:02X}", x, byte),
+            CopyToRegister { x, y } => write!(f, "v{:x} := v{:x}", x, y),
+            OrWithRegister { x, y } => write!(f, "v{:x} |= v{:x}", x, y),
+            AndWithRegister { x, y } => write!(f, "v{:x} &= v{:x}", x, y),
+            XorWithRegister { x, y } => write!(f, "v{:x} ^= v{:x}", x, y),
+            AddRegisters { x, y } => write!(f, "v{:x} += v{:x}", x, y),
+            Sub { x, y } => write!(f, "v{:x} -= v{:x}", x, y),
+            ShiftRight { x, y } => write!(f, "v{:x} >>= v{:x}", x, y),
+            SubReverse { x, y } => write!(f, "v{:x} =- v{:x}", x, y),
+            ShiftLeft { x, y } => write!(f, "v{:x} <<= v{:x}", x, y),
+            StoreRegRange { x, y } => write!(f, "save v{:x} - v{:x}", x, y),
+            ReadRegRange { x, y } => write!(f, "load v{:x} - v{:x}", x, y),
+            SkipIfRegNotEqualsReg { x, y } => write!(f, "if v{:x} == v{:x} then", x, y),
+            SetI { addr } => write!(f, "i := 0x{:03X}", addr),
+            JumpPlusV0 { addr } => write!(f, "jump0 0x{:03X}", addr),
+            Random { x, byte } => write!(f, "v{:x} := random 0x{:02X}", x, byte),
+            Draw { x, y, n } => write!(f, "sprite v{:x} v{:x} 0x{:X}", x, y, n),
+            SkipIfPressed { x } => write!(f, "if v{:x} -key then", x),
+            SkipIfNotPressed { x } => write!(f, "if v{:x} key then", x),
+            SetILong { addr } => write!(f, "i := long 0x{:04X}", addr),
+            SelectPlanes { n } => write!(f, "plane {}", n),
+            LoadAudio => write!(f, "audio"),
+            SetRegToDt { x } => write!(f, "v{:x} := delay", x),
+            WaitForInput { x } => write!(f, "v{:x} := key", x),
+            SetDt { x } => write!(f, "delay := v{:x}", x),
+            SetSt { x } => write!(f, "buzzer := v{:x}", x),
+            IPlusReg { x } => write!(f, "i += v{:x}", x),
+            SetIDigitSprite { x } => write!(f, "i := hex v{:x}", x),
+            SetIBigDigitSprite { x } => write!(f, "i := bighex v{:x}", x),
+            Bcd { x } => write!(f, "bcd v{:x}", x),
+            SetPitch { x } => write!(f, "pitch := v{:x}", x),
+            StoreRegsThrough { x } => write!(f, "save v{:x}", x),
+            ReadToRegs { x } => write!(f, "load v{:x}", x),
+            SaveFlags { x } => write!(f, "saveflags v{:x}", x),
+            LoadFlags { x } => write!(f, "loadflags v{:x}", x),
+        }
+    }
+}
+
+/// Decodes a big endian opcode into an [`Instruction`].
+///
+/// XO-CHIP's `f000 nnnn` is two words long and fails to decode here, use
+/// [`decode_long`] when the following word is available.
+pub fn decode(opcode: u16) -> Result<Instruction, DecodeError> {
+    use self::Instruction::*;
+
+    let x = ((opcode & 0x0f00) >> 8) as u8;
+    let y = ((opcode & 0x00f0) >> 4) as u8;
+    let n = (opcode & 0x000f) as u8;
+    let byte = (opcode & 0x00ff) as u8;
+    let addr = opcode & 0x0fff;
+
+    let instruction = match opcode & 0xf000 {
+        0x0000 => match opcode {
+            0x00e0 => ClearScreen,
+            0x00ee => Return,
+            0x00fb => ScrollRight,
+            0x00fc => ScrollLeft,
+            0x00fd => Exit,
+            0x00fe => Lores,
+            0x00ff => Hires,
+            _ if opcode & 0xfff0 == 0x00c0 => ScrollDown { n },
+            _ if opcode & 0xfff0 == 0x00d0 => ScrollUp { n },
+            _ => return Err(DecodeError(opcode)),
+        },
+        0x1000 => Jump { addr },
+        0x2000 => Call { addr },
+        0x3000 => SkipIfRegEqualsByte { x, byte },
+        0x4000 => SkipIfRegNotEqualsByte { x, byte },
+        0x5000 => match n {
+            0x0 => SkipIfRegEqualsReg { x, y },
+            0x2 => StoreRegRange { x, y },
+            0x3 => ReadRegRange { x, y },
+            _ => return Err(DecodeError(opcode)),
+        },
+        0x6000 => SetRegister { x, byte },
+        0x7000 => AddToRegister { x, byte },
+        0x8000 => match n {
+            0x0 => CopyToRegister { x, y },
+            0x1 => OrWithRegister { x, y },
+            0x2 => AndWithRegister { x, y },
+            0x3 => XorWithRegister { x, y },
+            0x4 => AddRegisters { x, y },
+            0x5 => Sub { x, y },
+            0x6 => ShiftRight { x, y },
+            0x7 => SubReverse { x, y },
+            0xe => ShiftLeft { x, y },
+            _ => return Err(DecodeError(opcode)),
+        },
+        0x9000 if n == 0 => SkipIfRegNotEqualsReg { x, y },
+        0xa000 => SetI { addr },
+        0xb000 => JumpPlusV0 { addr },
+        0xc000 => Random { x, byte },
+        0xd000 => Draw { x, y, n },
+        0xe000 => match byte {
+            0x9e => SkipIfPressed { x },
+            0xa1 => SkipIfNotPressed { x },
+            _ => return Err(DecodeError(opcode)),
+        },
+        0xf000 => match byte {
+            0x01 => SelectPlanes { n: x },
+            0x02 if x == 0 => LoadAudio,
+            0x07 => SetRegToDt { x },
+            0x0a => WaitForInput { x },
+            0x15 => SetDt { x },
+            0x18 => SetSt { x },
+            0x1e => IPlusReg { x },
+            0x29 => SetIDigitSprite { x },
+            0x30 => SetIBigDigitSprite { x },
+            0x33 => Bcd { x },
+            0x3a => SetPitch { x },
+            0x55 => StoreRegsThrough { x },
+            0x65 => ReadToRegs { x },
+            0x75 => SaveFlags { x },
+            0x85 => LoadFlags { x },
+            _ => return Err(DecodeError(opcode)),
+        },
+        _ => return Err(DecodeError(opcode)),
+    };
+
+    Ok(instruction)
+}
+
+/// Decodes the instruction starting with `opcode`, taking the operand of
+/// `f000 nnnn` from `next`. Other instructions ignore `next`.
+pub fn decode_long(opcode: u16, next: u16) -> Result<Instruction, DecodeError> {
+    match opcode {
+        0xf000 => Ok(Instruction::SetILong { addr: next }),
+        _ => decode(opcode),
+    }
+}
+
+/// Encodes an [`Instruction`] back into its opcode, the inverse of [`decode`].
+///
+/// Operands are truncated to the width of their field. For `f000 nnnn` this is
+/// only the first word, see [`encode_bytes`].
+pub fn encode(instruction: Instruction) -> u16 {
+    use self::Instruction::*;
+
+    let xkk = |op: u16, x: u8, byte: u8| op | (x as u16 & 0xf) << 8 | byte as u16;
+    let xyn = |op: u16, x: u8, y: u8, n: u8| op | (x as u16 & 0xf) << 8 | (y as u16 & 0xf) << 4 | (n as u16 & 0xf);
+    let nnn = |op: u16, addr: u16| op | (addr & 0x0fff);
+
+    match instruction {
+        ClearScreen => 0x00e0,
+        Return => 0x00ee,
+        ScrollUp { n } => 0x00d0 | (n as u16 & 0xf),
+        ScrollDown { n } => 0x00c0 | (n as u16 & 0xf),
+        ScrollRight => 0x00fb,
+        ScrollLeft => 0x00fc,
+        Exit => 0x00fd,
+        Lores => 0x00fe,
+        Hires => 0x00ff,
+        Jump { addr } => nnn(0x1000, addr),
+        Call { addr } => nnn(0x2000, addr),
+        SkipIfRegEqualsByte { x, byte } => xkk(0x3000, x, byte),
+        SkipIfRegNotEqualsByte { x, byte } => xkk(0x4000, x, byte),
+        SkipIfRegEqualsReg { x, y } => xyn(0x5000, x, y, 0x0),
+        SetRegister { x, byte } => xkk(0x6000, x, byte),
+        AddToRegister { x, byte } => xkk(0x7000, x, byte),
+        CopyToRegister { x, y } => xyn(0x8000, x, y, 0x0),
+        OrWithRegister { x, y } => xyn(0x8000, x, y, 0x1),
+        AndWithRegister { x, y } => xyn(0x8000, x, y, 0x2),
+        XorWithRegister { x, y } => xyn(0x8000, x, y, 0x3),
+        AddRegisters { x, y } => xyn(0x8000, x, y, 0x4),
+        Sub { x, y } => xyn(0x8000, x, y, 0x5),
+        ShiftRight { x, y } => xyn(0x8000, x, y, 0x6),
+        SubReverse { x, y } => xyn(0x8000, x, y, 0x7),
+        ShiftLeft { x, y } => xyn(0x8000, x, y, 0xe),
+        StoreRegRange { x, y } => xyn(0x5000, x, y, 0x2),
+        ReadRegRange { x, y } => xyn(0x5000, x, y, 0x3),
+        SkipIfRegNotEqualsReg { x, y } => xyn(0x9000, x, y, 0x0),
+        SetI { addr } => nnn(0xa000, addr),
+        JumpPlusV0 { addr } => nnn(0xb000, addr),
+        Random { x, byte } => xkk(0xc000, x, byte),
+        Draw { x, y, n } => xyn(0xd000, x, y, n),
+        SkipIfPressed { x } => xkk(0xe000, x, 0x9e),
+        SkipIfNotPressed { x } => xkk(0xe000, x, 0xa1),
+        SetILong { .. } => 0xf000,
+        SelectPlanes { n } => xkk(0xf000, n, 0x01),
+        LoadAudio => 0xf002,
+        SetRegToDt { x } => xkk(0xf000, x, 0x07),
+        WaitForInput { x } => xkk(0xf000, x, 0x0a),
+        SetDt { x } => xkk(0xf000, x, 0x15),
+        SetSt { x } => xkk(0xf000, x, 0x18),
+        IPlusReg { x } => xkk(0xf000, x, 0x1e),
+        SetIDigitSprite { x } => xkk(0xf000, x, 0x29),
+        SetIBigDigitSprite { x } => xkk(0xf000, x, 0x30),
+        Bcd { x } => xkk(0xf000, x, 0x33),
+        SetPitch { x } => xkk(0xf000, x, 0x3a),
+        StoreRegsThrough { x } => xkk(0xf000, x, 0x55),
+        ReadToRegs { x } => xkk(0xf000, x, 0x65),
+        SaveFlags { x } => xkk(0xf000, x, 0x75),
+        LoadFlags { x } => xkk(0xf000, x, 0x85),
+    }
+}
+
+/// Encodes an [`Instruction`] into the big endian bytes that make it up.
+pub fn encode_bytes(instruction: Instruction) -> Vec<u8> {
+    let opcode = encode(instruction);
+    let mut bytes = vec![(opcode >> 8) as u8, opcode as u8];
+    if let Instruction::SetILong { addr } = instruction {
+        bytes.extend_from_slice(&[(addr >> 8) as u8, addr as u8]);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::Instruction::*;
+
+    #[test]
+    fn should_decode() {
+        assert_eq!(decode(0x00e0), Ok(ClearScreen));
+        assert_eq!(decode(0x1234), Ok(Jump { addr: 0x234 }));
+        assert_eq!(decode(0x6a2b), Ok(SetRegister { x: 0xa, byte: 0x2b }));
+        assert_eq!(decode(0x8ab6), Ok(ShiftRight { x: 0xa, y: 0xb }));
+        assert_eq!(decode(0xd125), Ok(Draw { x: 1, y: 2, n: 5 }));
+        assert_eq!(decode(0xe3a1), Ok(SkipIfNotPressed { x: 3 }));
+        assert_eq!(decode(0xf765), Ok(ReadToRegs { x: 7 }));
+    }
+
+    #[test]
+    fn should_decode_superchip() {
+        assert_eq!(decode(0x00c4), Ok(ScrollDown { n: 4 }));
+        assert_eq!(decode(0x00ff), Ok(Hires));
+        assert_eq!(decode(0xd120), Ok(Draw { x: 1, y: 2, n: 0 }));
+        assert_eq!(decode(0xf330), Ok(SetIBigDigitSprite { x: 3 }));
+        assert_eq!(decode(0xf785), Ok(LoadFlags { x: 7 }));
+        assert!(decode(0x00fb).unwrap().is_superchip());
+        assert!(!decode(0xd120).unwrap().is_superchip());
+    }
+
+    #[test]
+    fn shouldnt_decode_unknown_opcodes() {
+        assert_eq!(decode(0x0123), Err(DecodeError(0x0123)));
+        assert_eq!(decode(0x5121), Err(DecodeError(0x5121)));
+        assert_eq!(decode(0x812f), Err(DecodeError(0x812f)));
+        assert_eq!(decode(0xe100), Err(DecodeError(0xe100)));
+        assert_eq!(decode(0xf1ff), Err(DecodeError(0xf1ff)));
+    }
+
+    #[test]
+    fn should_decode_xochip() {
+        assert_eq!(decode(0x00d2), Ok(ScrollUp { n: 2 }));
+        assert_eq!(decode(0x5122), Ok(StoreRegRange { x: 1, y: 2 }));
+        assert_eq!(decode(0x5123), Ok(ReadRegRange { x: 1, y: 2 }));
+        assert_eq!(decode(0xf201), Ok(SelectPlanes { n: 2 }));
+        assert_eq!(decode(0xf002), Ok(LoadAudio));
+        assert_eq!(decode(0xf102), Err(DecodeError(0xf102)));
+        assert_eq!(decode(0xf53a), Ok(SetPitch { x: 5 }));
+        assert_eq!(decode(0xf000), Err(DecodeError(0xf000)));
+        assert_eq!(decode_long(0xf000, 0x1234), Ok(SetILong { addr: 0x1234 }));
+        assert_eq!(decode_long(0x00e0, 0x1234), Ok(ClearScreen));
+        assert_eq!(SetILong { addr: 0 }.size(), 4);
+        assert_eq!(encode_bytes(SetILong { addr: 0x1234 }), [0xf0, 0x00, 0x12, 0x34]);
+        assert_eq!(encode_bytes(ClearScreen), [0x00, 0xe0]);
+    }
+
+    #[test]
+    fn should_encode_what_was_decoded() {
+        for opcode in 0..=0xffff {
+            if let Ok(instruction) = decode(opcode) {
+                assert_eq!(encode(instruction), opcode);
+            }
+        }
+    }
+
+    #[test]
+    fn should_format_as_octo() {
+        assert_eq!(decode(0x3a05).unwrap().to_string(), "if va != 0x05 then");
+        assert_eq!(decode(0x8127).unwrap().to_string(), "v1 =- v2");
+        assert_eq!(decode(0xd01f).unwrap().to_string(), "sprite v0 v1 0xF");
+        assert_eq!(decode(0x2abc).unwrap().to_string(), ":call 0xABC");
+    }
+
+    #[test]
+    fn should_truncate_operands_when_encoding() {
+        assert_eq!(encode(Jump { addr: 0xf234 }), 0x1234);
+        assert_eq!(encode(Draw { x: 0x11, y: 0x12, n: 0x13 }), 0xd123);
+    }
+}
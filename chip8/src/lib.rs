@@ -0,0 +1,56 @@
+//! A CHIP-8 interpreter core.
+//!
+//! The interpreter has no opinion about windows, audio or input devices. A
+//! frontend creates a [`Chip8`], loads a program into it, feeds it the state of
+//! the hex keypad and calls [`Chip8::run_frame`] 60 times a second, reading
+//! the framebuffer back out to draw it and [`Chip8::fill_audio`] for sound.
+//!
+//! Random numbers for `cxkk` come from a [`RandomSource`], by default an
+//! [`XorShift`] seeded with entropy. [`Chip8::seed_rng`] makes a run
+//! repeatable, and [`Chip8::with_random_source`] plugs in another source.
+//!
+//! ```
+//! let mut chip = chip8::Chip8::new();
+//! chip.load(&[0x60, 0x2a]); // v0 := 0x2a
+//! chip.cycle().unwrap();
+//! assert_eq!(chip.registers()[0], 0x2a);
+//! ```
+
+mod assembler;
+mod audio;
+mod chip8;
+mod disasm;
+mod error;
+mod gif;
+mod instruction;
+mod movie;
+mod platform;
+mod quirks;
+mod rewind;
+mod rng;
+mod screenshot;
+mod state;
+mod timing;
+mod wav;
+
+pub use crate::assembler::{assemble, AsmError, Program};
+pub use crate::audio::{
+    playback_rate, Voice, DEFAULT_FREQUENCY, DEFAULT_PATTERN, DEFAULT_PERIOD_BITS, DEFAULT_PITCH, PATTERN_SIZE,
+};
+pub use crate::disasm::{disassemble, listing, Line};
+pub use crate::error::{Chip8Error, PokeError};
+pub use crate::gif::GifRecorder;
+pub use crate::instruction::{decode, decode_long, encode, encode_bytes, DecodeError, Instruction};
+pub use crate::movie::{InputEvent, Movie, MovieError, MoviePlayer, MovieRecorder, MOVIE_MAGIC, MOVIE_VERSION};
+pub use crate::platform::Platform;
+pub use crate::quirks::{IndexIncrement, Quirks};
+pub use crate::rewind::Rewind;
+pub use crate::rng::{RandomSource, XorShift};
+pub use crate::screenshot::{write_png, write_png_sized, DEFAULT_PALETTE};
+pub use crate::state::{rom_hash, StateError, STATE_MAGIC, STATE_VERSION};
+pub use crate::timing::{vip_cycles, Timing, VIP_CYCLES_PER_FRAME, VIP_FRAME_OVERHEAD};
+pub use crate::wav::{WavRecorder, MAX_SAMPLE_RATE, MIN_SAMPLE_RATE};
+pub use crate::chip8::{
+    new_chip8, Chip8, MemoryAccess, DEFAULT_INSTRUCTIONS_PER_FRAME, DISPLAY_HEIGHT, DISPLAY_WIDTH, HIRES_HEIGHT, HIRES_WIDTH, MEMORY_SIZE, PROGRAM_START,
+    XO_MEMORY_SIZE,
+};
@@ -0,0 +1,396 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use crate::platform::Platform;
+use crate::quirks::{IndexIncrement, Quirks};
+use crate::rng::RandomSource;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::timing::Timing;
+
+/// The bytes every movie file starts with.
+pub const MOVIE_MAGIC: [u8; 4] = *b"C8MV";
+
+/// The movie format version written by this build.
+pub const MOVIE_VERSION: u16 = 1;
+
+/// The keypad changing to `keys` just before frame `frame` runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub keys: [bool; 16],
+}
+
+/// A recorded session: everything needed to replay it exactly from power on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    /// The [`rom_hash`](crate::rom_hash) of the ROM played.
+    pub rom_hash: u64,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub timing: Timing,
+    pub instructions_per_frame: u32,
+    /// What the RNG was seeded with at the start.
+    pub seed: u64,
+    /// Every keypad change, in frame order.
+    pub events: Vec<InputEvent>,
+    /// How many frames were recorded.
+    pub frames: u64,
+}
+
+/// Why a movie couldn't be read or played.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovieError {
+    /// The data doesn't start with [`MOVIE_MAGIC`].
+    NotAMovie,
+    /// The movie was written in a format version this build can't read.
+    UnsupportedVersion { version: u16 },
+    /// The data ends before the movie does.
+    Truncated,
+    /// A field holds a value that can't be right.
+    Corrupt,
+    /// The movie was recorded on a different ROM.
+    RomMismatch { expected: u64, found: u64 },
+    /// The machine is set up differently to the one the movie was recorded
+    /// on. Names the setting that differs.
+    SettingsMismatch { setting: &'static str },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MovieError::NotAMovie => write!(f, "not a movie"),
+            MovieError::UnsupportedVersion { version } => {
+                write!(f, "movie version {} isn't supported, expected {}", version, MOVIE_VERSION)
+            }
+            MovieError::Truncated => write!(f, "movie is truncated"),
+            MovieError::Corrupt => write!(f, "movie is corrupt"),
+            MovieError::RomMismatch { expected, found } => {
+                write!(f, "movie is for rom {:016X}, not {:016X}", found, expected)
+            }
+            MovieError::SettingsMismatch { setting } => write!(f, "movie was recorded with a different {}", setting),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+impl From<StateError> for MovieError {
+    fn from(err: StateError) -> MovieError {
+        match err {
+            StateError::Truncated => MovieError::Truncated,
+            _ => MovieError::Corrupt,
+        }
+    }
+}
+
+impl Movie {
+    /// Serializes the movie: [`MOVIE_MAGIC`], the format version, the ROM
+    /// hash, the machine's settings and seed, then the input events.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.slice(&MOVIE_MAGIC);
+        w.u16(MOVIE_VERSION);
+        w.u64(self.rom_hash);
+        w.u8(self.platform.id());
+        w.bool(self.quirks.shift_uses_vy);
+        w.u8(index_increment_id(self.quirks.index_increment));
+        w.bool(self.quirks.logic_resets_vf);
+        w.bool(self.quirks.clip_sprites);
+        w.bool(self.quirks.jump_uses_vx);
+        w.u8(self.timing.id());
+        w.u32(self.instructions_per_frame);
+        w.u64(self.seed);
+        w.u64(self.frames);
+        w.u32(self.events.len() as u32);
+        for event in &self.events {
+            w.u64(event.frame);
+            w.u16(keys_to_bits(&event.keys));
+        }
+        w.bytes
+    }
+
+    /// Reads a movie written by [`Movie::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Movie, MovieError> {
+        let mut r = StateReader::new(bytes);
+        if r.slice(MOVIE_MAGIC.len()).map_err(|_| MovieError::NotAMovie)? != MOVIE_MAGIC {
+            return Err(MovieError::NotAMovie);
+        }
+        let version = r.u16()?;
+        if version != MOVIE_VERSION {
+            return Err(MovieError::UnsupportedVersion { version });
+        }
+
+        let rom_hash = r.u64()?;
+        let platform = Platform::from_id(r.u8()?).ok_or(MovieError::Corrupt)?;
+        let quirks = Quirks {
+            shift_uses_vy: r.bool()?,
+            index_increment: index_increment_from_id(r.u8()?).ok_or(MovieError::Corrupt)?,
+            logic_resets_vf: r.bool()?,
+            clip_sprites: r.bool()?,
+            jump_uses_vx: r.bool()?,
+        };
+        let timing = Timing::from_id(r.u8()?).ok_or(MovieError::Corrupt)?;
+        let instructions_per_frame = r.u32()?;
+        let seed = r.u64()?;
+        let frames = r.u64()?;
+
+        let count = r.u32()?;
+        let mut events = Vec::new();
+        for _ in 0..count {
+            let frame = r.u64()?;
+            let keys = bits_to_keys(r.u16()?);
+            if events.last().is_some_and(|last: &InputEvent| last.frame > frame) || frame > frames {
+                return Err(MovieError::Corrupt);
+            }
+            events.push(InputEvent { frame, keys });
+        }
+
+        Ok(Movie { rom_hash, platform, quirks, timing, instructions_per_frame, seed, events, frames })
+    }
+
+    /// Sets a machine up to play the movie back: a new machine with the
+    /// movie's platform, quirks and timing. Load the ROM into it, then hand it
+    /// to [`MoviePlayer::new`].
+    pub fn machine(&self) -> Chip8 {
+        let mut chip = Chip8::with_platform(self.platform, self.quirks);
+        chip.set_timing(self.timing);
+        chip.set_instructions_per_frame(self.instructions_per_frame);
+        chip
+    }
+
+    // Whether `chip` is running the ROM and settings the movie was recorded with
+    fn check<R: RandomSource>(&self, chip: &Chip8<R>) -> Result<(), MovieError> {
+        if chip.rom_hash() != self.rom_hash {
+            return Err(MovieError::RomMismatch { expected: chip.rom_hash(), found: self.rom_hash });
+        }
+        let setting = if chip.platform() != self.platform {
+            "platform"
+        } else if chip.quirks() != self.quirks {
+            "set of quirks"
+        } else if chip.timing() != self.timing {
+            "timing"
+        } else if chip.instructions_per_frame() != self.instructions_per_frame {
+            "number of instructions per frame"
+        } else {
+            return Ok(());
+        };
+        Err(MovieError::SettingsMismatch { setting })
+    }
+}
+
+/// Records a session into a [`Movie`].
+///
+/// Start recording straight after loading the ROM, then send every keypad
+/// change and frame through the recorder instead of straight to the machine.
+pub struct MovieRecorder {
+    movie: Movie,
+    keys: [bool; 16],
+}
+
+impl MovieRecorder {
+    /// Starts recording `chip`, restarting its RNG from its seed so the
+    /// movie begins from a known state.
+    pub fn start<R: RandomSource>(chip: &mut Chip8<R>) -> MovieRecorder {
+        let seed = chip.seed();
+        chip.seed_rng(seed);
+        let movie = Movie {
+            rom_hash: chip.rom_hash(),
+            platform: chip.platform(),
+            quirks: chip.quirks(),
+            timing: chip.timing(),
+            instructions_per_frame: chip.instructions_per_frame(),
+            seed,
+            events: Vec::new(),
+            frames: 0,
+        };
+        MovieRecorder { movie, keys: *chip.input() }
+    }
+
+    /// Passes the keypad state on to [`Chip8::update_input`], recording it if
+    /// it changed.
+    pub fn update_input<R: RandomSource>(&mut self, chip: &mut Chip8<R>, keys: [bool; 16]) {
+        if keys != self.keys {
+            self.keys = keys;
+            self.movie.events.push(InputEvent { frame: self.movie.frames, keys });
+        }
+        chip.update_input(keys);
+    }
+
+    /// Runs a frame with [`Chip8::run_frame`], counting it.
+    pub fn run_frame<R: RandomSource>(&mut self, chip: &mut Chip8<R>) -> Result<(), Chip8Error> {
+        self.movie.frames += 1;
+        chip.run_frame()
+    }
+
+    /// The number of the frame about to run.
+    pub fn frame(&self) -> u64 {
+        self.movie.frames
+    }
+
+    /// Stops recording.
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Plays a [`Movie`] back, feeding the recorded input in on the right frames.
+pub struct MoviePlayer {
+    movie: Movie,
+    frame: u64,
+    next: usize, // Index of the next event to apply
+}
+
+impl MoviePlayer {
+    /// Checks `chip` is running the movie's ROM with its settings and seeds
+    /// the RNG. `chip` should have only just had the ROM loaded.
+    pub fn new<R: RandomSource>(movie: Movie, chip: &mut Chip8<R>) -> Result<MoviePlayer, MovieError> {
+        movie.check(chip)?;
+        chip.seed_rng(movie.seed);
+        Ok(MoviePlayer { movie, frame: 0, next: 0 })
+    }
+
+    /// Applies the input recorded for this frame and runs it.
+    pub fn run_frame<R: RandomSource>(&mut self, chip: &mut Chip8<R>) -> Result<(), Chip8Error> {
+        while let Some(event) = self.movie.events.get(self.next).filter(|event| event.frame <= self.frame) {
+            chip.update_input(event.keys);
+            self.next += 1;
+        }
+        self.frame += 1;
+        chip.run_frame()
+    }
+
+    /// The number of the frame about to run.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Whether every recorded frame has been played.
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.movie.frames
+    }
+
+    /// The keypad state the movie last set.
+    pub fn keys(&self) -> [bool; 16] {
+        match self.next {
+            0 => [false; 16],
+            next => self.movie.events[next - 1].keys,
+        }
+    }
+}
+
+fn index_increment_id(increment: IndexIncrement) -> u8 {
+    match increment {
+        IndexIncrement::Unchanged => 0,
+        IndexIncrement::X => 1,
+        IndexIncrement::XPlusOne => 2,
+    }
+}
+
+fn index_increment_from_id(id: u8) -> Option<IndexIncrement> {
+    match id {
+        0 => Some(IndexIncrement::Unchanged),
+        1 => Some(IndexIncrement::X),
+        2 => Some(IndexIncrement::XPlusOne),
+        _ => None,
+    }
+}
+
+fn keys_to_bits(keys: &[bool; 16]) -> u16 {
+    keys.iter().enumerate().fold(0, |bits, (key, &pressed)| bits | (pressed as u16) << key)
+}
+
+fn bits_to_keys(bits: u16) -> [bool; 16] {
+    let mut keys = [false; 16];
+    for (key, pressed) in keys.iter_mut().enumerate() {
+        *pressed = bits & (1 << key) != 0;
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Waits for a key, then draws a random sprite at the key's position, forever
+    const ROM: [u8; 10] = [0xf0, 0x0a, 0xc1, 0xff, 0xa3, 0x00, 0xd0, 0x01, 0x12, 0x00];
+
+    fn key(index: usize) -> [bool; 16] {
+        let mut keys = [false; 16];
+        keys[index] = true;
+        keys
+    }
+
+    fn record() -> (Movie, Vec<u8>) {
+        let mut chip = Chip8::new();
+        chip.load(&ROM);
+        let mut recorder = MovieRecorder::start(&mut chip);
+        for frame in 0..30 {
+            let keys = if frame % 7 == 3 { key(frame % 16) } else { [false; 16] };
+            recorder.update_input(&mut chip, keys);
+            recorder.run_frame(&mut chip).unwrap();
+        }
+        (recorder.finish(), chip.save_state())
+    }
+
+    #[test]
+    fn should_replay_bit_for_bit() {
+        let (movie, recorded) = record();
+        assert_eq!(movie.frames, 30);
+        assert_eq!(movie.events.len(), 8);
+        assert_eq!(movie.events[0], InputEvent { frame: 3, keys: key(3) });
+
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        let mut chip = movie.machine();
+        chip.load(&ROM);
+        let mut player = MoviePlayer::new(movie, &mut chip).unwrap();
+        while !player.is_finished() {
+            player.run_frame(&mut chip).unwrap();
+        }
+        assert_eq!(chip.save_state(), recorded);
+    }
+
+    #[test]
+    fn should_detect_mismatched_replays() {
+        let (movie, _) = record();
+
+        let mut chip = movie.machine();
+        chip.load(&[0x12, 0x00]);
+        assert!(matches!(MoviePlayer::new(movie.clone(), &mut chip), Err(MovieError::RomMismatch { .. })));
+
+        let mut chip = Chip8::with_quirks(Quirks::cosmac_vip());
+        chip.load(&ROM);
+        assert_eq!(
+            MoviePlayer::new(movie.clone(), &mut chip).err(),
+            Some(MovieError::SettingsMismatch { setting: "set of quirks" })
+        );
+
+        let mut chip = movie.machine();
+        chip.set_timing(Timing::CosmacVip);
+        chip.load(&ROM);
+        assert_eq!(MoviePlayer::new(movie, &mut chip).err(), Some(MovieError::SettingsMismatch { setting: "timing" }));
+    }
+
+    #[test]
+    fn should_reject_bad_files() {
+        let (movie, _) = record();
+        let bytes = movie.to_bytes();
+        assert_eq!(Movie::from_bytes(b"C8ST"), Err(MovieError::NotAMovie));
+        assert_eq!(Movie::from_bytes(&bytes[..bytes.len() - 1]), Err(MovieError::Truncated));
+        let mut newer = bytes.clone();
+        newer[4] = 9;
+        assert_eq!(Movie::from_bytes(&newer), Err(MovieError::UnsupportedVersion { version: 9 }));
+    }
+
+    #[test]
+    fn should_pack_keys() {
+        assert_eq!(keys_to_bits(&key(0)), 1);
+        assert_eq!(keys_to_bits(&key(15)), 0x8000);
+        assert_eq!(bits_to_keys(0x8001), {
+            let mut keys = key(0);
+            keys[15] = true;
+            keys
+        });
+    }
+}
@@ -0,0 +1,63 @@
+use crate::chip8::{MEMORY_SIZE, XO_MEMORY_SIZE};
+use crate::instruction::Instruction;
+use crate::quirks::Quirks;
+
+/// The instruction set a machine understands.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Platform {
+    /// The original CHIP-8 instructions and 64x32 display.
+    #[default]
+    Chip8,
+    /// SUPER-CHIP 1.1, adding the 128x64 display, scrolling, the large font
+    /// and the RPL flags.
+    SuperChip,
+    /// XO-CHIP, adding 64 KiB of memory, a second display plane, register
+    /// range saves and loads and scrolling up.
+    XoChip,
+}
+
+impl Platform {
+    /// The quirks ROMs written for this platform usually expect.
+    pub fn default_quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::default(),
+            Platform::SuperChip => Quirks::superchip(),
+            Platform::XoChip => Quirks::xochip(),
+        }
+    }
+
+    /// Whether this platform can execute `instruction`.
+    pub fn supports(self, instruction: Instruction) -> bool {
+        match self {
+            Platform::Chip8 => !instruction.is_superchip() && !instruction.is_xochip(),
+            Platform::SuperChip => !instruction.is_xochip(),
+            Platform::XoChip => true,
+        }
+    }
+
+    // How the platform is identified in save states and movies
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Platform::Chip8 => 0,
+            Platform::SuperChip => 1,
+            Platform::XoChip => 2,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Platform> {
+        match id {
+            0 => Some(Platform::Chip8),
+            1 => Some(Platform::SuperChip),
+            2 => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    /// How many bytes of memory the platform can address.
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => MEMORY_SIZE,
+            Platform::XoChip => XO_MEMORY_SIZE,
+        }
+    }
+}
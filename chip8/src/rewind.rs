@@ -0,0 +1,234 @@
+use std::collections::VecDeque;
+
+use crate::chip8::Chip8;
+use crate::rng::RandomSource;
+
+/// A bounded history of snapshots to step back through.
+///
+/// Every `interval` frames [`Rewind::record`] takes a save state. The newest
+/// is kept whole and each older one only as the run-length encoded XOR
+/// against the one after it, which is mostly zeros since little of memory
+/// changes between snapshots. When the history outgrows its memory budget
+/// the oldest snapshots are dropped.
+pub struct Rewind {
+    interval: u32,
+    budget: usize,
+    frames: u32, // Frames recorded since the last snapshot
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>, // Oldest first, each turning a snapshot into the one before it
+    used: usize, // Bytes held by latest and deltas
+}
+
+impl Rewind {
+    /// Keeps a snapshot every `interval` frames in up to `budget` bytes.
+    pub fn new(interval: u32, budget: usize) -> Rewind {
+        Rewind { interval: interval.max(1), budget, frames: 0, latest: None, deltas: VecDeque::new(), used: 0 }
+    }
+
+    /// Call once per frame while running. Takes a snapshot every `interval`
+    /// calls.
+    pub fn record<R: RandomSource>(&mut self, chip: &Chip8<R>) {
+        if self.frames == 0 {
+            self.push(chip.save_state());
+        }
+        self.frames = (self.frames + 1) % self.interval;
+    }
+
+    /// Restores the newest snapshot and forgets it, so calling this again
+    /// goes further back. Returns `false` once the history is used up.
+    pub fn rewind<R: RandomSource>(&mut self, chip: &mut Chip8<R>) -> bool {
+        let state = match self.pop() {
+            Some(state) => state,
+            None => return false,
+        };
+        // Snapshot the next frame that runs, making it the point to come back to
+        self.frames = 0;
+        chip.load_state(&state).is_ok()
+    }
+
+    /// How many snapshots there are to go back through.
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.latest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// How many bytes the history takes up, which is kept within the budget
+    /// unless a single snapshot is bigger than it.
+    pub fn memory_used(&self) -> usize {
+        self.used
+    }
+
+    /// Forgets every snapshot, for when the machine is reset or another ROM
+    /// is loaded.
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.used = 0;
+        self.frames = 0;
+    }
+
+    fn push(&mut self, state: Vec<u8>) {
+        match self.latest.take() {
+            Some(previous) if previous.len() == state.len() => {
+                let delta = encode_delta(&previous, &state);
+                self.used += delta.len() + state.len() - previous.len();
+                self.deltas.push_back(delta);
+            }
+            _ => {
+                self.clear();
+                self.used = state.len();
+            }
+        }
+        self.latest = Some(state);
+
+        while self.used > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => self.used -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Option<Vec<u8>> {
+        let latest = self.latest.take()?;
+        self.used -= latest.len();
+        if let Some(delta) = self.deltas.pop_back() {
+            self.used += latest.len();
+            self.used -= delta.len();
+            self.latest = Some(apply_delta(&latest, &delta));
+        }
+        Some(latest)
+    }
+}
+
+// Run-length encodes old ^ new as pairs of a count of unchanged bytes and a
+// count of changed bytes followed by those changed bytes XORed
+fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    let mut index = 0;
+
+    while index < new.len() {
+        let same = old[index..].iter().zip(&new[index..]).take_while(|(a, b)| a == b).count();
+        index += same;
+        let changed = old[index..].iter().zip(&new[index..]).take_while(|(a, b)| a != b).count();
+        write_varint(&mut delta, same);
+        write_varint(&mut delta, changed);
+        delta.extend(old[index..index + changed].iter().zip(&new[index..]).map(|(a, b)| a ^ b));
+        index += changed;
+    }
+    delta
+}
+
+fn apply_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut out = base.to_vec();
+    let mut index = 0;
+    let mut pos = 0;
+
+    while pos < delta.len() {
+        let same = read_varint(delta, &mut pos);
+        let changed = read_varint(delta, &mut pos);
+        index += same;
+        for (byte, xor) in out[index..index + changed].iter_mut().zip(&delta[pos..pos + changed]) {
+            *byte ^= xor;
+        }
+        index += changed;
+        pos += changed;
+    }
+    out
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counts v0 up by one every frame
+    fn counter() -> Chip8 {
+        let mut chip = Chip8::new();
+        chip.load(&[0x70, 0x01, 0x12, 0x00]);
+        chip.set_instructions_per_frame(2);
+        chip
+    }
+
+    #[test]
+    fn should_round_trip_deltas() {
+        let old = vec![0, 1, 2, 3, 4, 5, 6, 7];
+        let new = vec![0, 1, 9, 9, 4, 5, 6, 8];
+        let delta = encode_delta(&old, &new);
+        assert_eq!(apply_delta(&new, &delta), old);
+        assert_eq!(apply_delta(&old, &delta), new);
+        assert!(encode_delta(&old, &old).len() <= 2);
+    }
+
+    #[test]
+    fn should_encode_long_runs() {
+        let mut value = Vec::new();
+        write_varint(&mut value, 300);
+        let mut pos = 0;
+        assert_eq!(read_varint(&value, &mut pos), 300);
+        assert_eq!(pos, 2);
+    }
+
+    #[test]
+    fn should_step_back_through_snapshots() {
+        let mut chip = counter();
+        let mut rewind = Rewind::new(2, 1 << 20);
+        for _ in 0..6 {
+            rewind.record(&chip);
+            chip.run_frame().unwrap();
+        }
+        assert_eq!(chip.registers()[0], 6);
+        assert_eq!(rewind.len(), 3);
+
+        assert!(rewind.rewind(&mut chip));
+        assert_eq!(chip.registers()[0], 4);
+        assert!(rewind.rewind(&mut chip));
+        assert_eq!(chip.registers()[0], 2);
+        assert!(rewind.rewind(&mut chip));
+        assert_eq!(chip.registers()[0], 0);
+        assert!(!rewind.rewind(&mut chip));
+        assert!(rewind.is_empty());
+        assert_eq!(rewind.memory_used(), 0);
+    }
+
+    #[test]
+    fn should_keep_within_budget() {
+        let mut chip = counter();
+        let size = chip.save_state().len();
+        let mut rewind = Rewind::new(1, size + 100);
+        for _ in 0..100 {
+            rewind.record(&chip);
+            chip.run_frame().unwrap();
+        }
+        assert!(rewind.memory_used() <= size + 100);
+        assert!(rewind.len() > 1 && rewind.len() < 100);
+
+        // The oldest snapshots went, the newest are still there
+        assert!(rewind.rewind(&mut chip));
+        assert_eq!(chip.registers()[0], 99);
+    }
+}
@@ -0,0 +1,98 @@
+extern crate rand;
+
+// Replaces a zero seed, which xorshift would never leave
+const NONZERO_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// A seed nobody chose, for machines that don't need to be reproducible
+pub(crate) fn entropy_seed() -> u64 {
+    rand::random()
+}
+
+/// Where `cxkk` gets its random numbers from.
+///
+/// A source has to be deterministic: reseeding with the same seed must give
+/// the same bytes every time, which is what makes movies replay and lets
+/// tests know what `cxkk` will do. Its whole state has to fit in a `u64` so
+/// it can go in a save state.
+pub trait RandomSource {
+    /// Restarts the sequence from `seed`.
+    fn reseed(&mut self, seed: u64);
+
+    /// The next byte of the sequence.
+    fn next_u8(&mut self) -> u8;
+
+    /// Everything needed to carry the sequence on from here.
+    fn state(&self) -> u64;
+
+    /// Carries the sequence on from a value [`RandomSource::state`] returned.
+    fn set_state(&mut self, state: u64);
+}
+
+/// The xorshift64* generator machines use unless given another source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    /// Starts a generator from `seed`.
+    pub fn new(seed: u64) -> XorShift {
+        let mut rng = XorShift { state: 0 };
+        rng.reseed(seed);
+        rng
+    }
+}
+
+impl RandomSource for XorShift {
+    fn reseed(&mut self, seed: u64) {
+        self.set_state(seed);
+    }
+
+    fn next_u8(&mut self) -> u8 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        (x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 56) as u8
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.state = if state == 0 { NONZERO_SEED } else { state };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_repeat_from_the_same_seed() {
+        let mut a = XorShift::new(42);
+        let mut b = XorShift::new(42);
+        let first: Vec<u8> = (0..16).map(|_| a.next_u8()).collect();
+        let second: Vec<u8> = (0..16).map(|_| b.next_u8()).collect();
+        assert_eq!(first, second);
+        assert!(first.iter().any(|&byte| byte != first[0]));
+    }
+
+    #[test]
+    fn should_carry_on_from_saved_state() {
+        let mut a = XorShift::new(42);
+        a.next_u8();
+        let mut b = XorShift::new(0);
+        b.set_state(a.state());
+        assert_eq!(a.next_u8(), b.next_u8());
+    }
+
+    #[test]
+    fn shouldnt_get_stuck_on_zero() {
+        let mut rng = XorShift::new(0);
+        rng.next_u8();
+        assert_ne!(rng.state(), 0);
+    }
+}
@@ -0,0 +1,224 @@
+use std::io::{self, Write};
+
+use crate::chip8::Chip8;
+use crate::rng::RandomSource;
+
+/// Black and white for plane 0, with greys for pixels set in plane 1, in the
+/// order [`Chip8::pixel_color`] indexes them.
+pub const DEFAULT_PALETTE: [[u8; 3]; 4] = [[0, 0, 0], [255, 255, 255], [153, 153, 153], [77, 77, 77]];
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+// Most bytes a stored deflate block can hold
+const STORED_BLOCK_SIZE: usize = 0xffff;
+
+/// Writes the framebuffer as a PNG, each display pixel becoming a `scale` by
+/// `scale` square colored from `palette` by its [`Chip8::pixel_color`]. The
+/// image is the size of the current resolution times `scale`, with x = 0 on
+/// the left, so a `scale` of 1 gives exactly one image pixel per display
+/// pixel.
+pub fn write_png<R: RandomSource, W: Write>(
+    chip: &Chip8<R>,
+    writer: W,
+    scale: usize,
+    palette: &[[u8; 3]; 4],
+) -> io::Result<()> {
+    let scale = scale.max(1);
+    write_png_sized(chip, writer, chip.width() * scale, chip.height() * scale, palette)
+}
+
+/// Writes the framebuffer as a `width` by `height` PNG, stretching the
+/// current resolution to fit like [`write_png`] does. Suits a window sized for
+/// the lores display, where hires pixels at an odd scale cover a fraction of
+/// a window pixel and can't be drawn with a whole number `scale`.
+pub fn write_png_sized<R: RandomSource, W: Write>(
+    chip: &Chip8<R>,
+    mut writer: W,
+    width: usize,
+    height: usize,
+    palette: &[[u8; 3]; 4],
+) -> io::Result<()> {
+    let (width, height) = (width.max(1), height.max(1));
+    let pixels = fitted_pixels(chip, width, height);
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per pixel, indexing the palette, with default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 3, 0, 0, 0]);
+
+    // Each row starts with its filter type, 0 for none
+    let mut rows = Vec::with_capacity((width + 1) * height);
+    for row in pixels.chunks(width) {
+        rows.push(0);
+        rows.extend_from_slice(row);
+    }
+
+    writer.write_all(&PNG_SIGNATURE)?;
+    write_chunk(&mut writer, b"IHDR", &header)?;
+    write_chunk(&mut writer, b"PLTE", &palette.concat())?;
+    write_chunk(&mut writer, b"IDAT", &zlib_stored(&rows))?;
+    write_chunk(&mut writer, b"IEND", &[])
+}
+
+// The framebuffer stretched to `width` by `height`, as a row major list of
+// pixel_color values
+fn fitted_pixels<R: RandomSource>(chip: &Chip8<R>, width: usize, height: usize) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            pixels.push(chip.pixel_color(x * chip.width() / width, y * chip.height() / height));
+        }
+    }
+    pixels
+}
+
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    let crc = crc32(kind.iter().chain(data));
+    writer.write_all(&crc.to_be_bytes())
+}
+
+// Wraps `data` in a zlib stream of uncompressed deflate blocks. Screenshots
+// are small enough that compressing them isn't worth the code.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32K window and no preset dictionary, the check bits making the header a multiple of 31
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(STORED_BLOCK_SIZE).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8); // BFINAL on the last block, BTYPE 00 for stored
+        out.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Platform;
+    use crate::quirks::Quirks;
+
+    // Decodes a PNG into its size and 8 bit RGB pixels
+    fn decode(bytes: &[u8]) -> (u32, u32, Vec<u8>) {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let (info, mut reader) = decoder.read_info().unwrap();
+        let mut pixels = vec![0; info.buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        (info.width, info.height, pixels)
+    }
+
+    fn rgb(pixels: &[u8], width: u32, x: u32, y: u32) -> [u8; 3] {
+        let index = ((y * width + x) * 3) as usize;
+        [pixels[index], pixels[index + 1], pixels[index + 2]]
+    }
+
+    #[test]
+    fn should_check_bytes_like_zlib() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn should_write_display_unmirrored() {
+        let mut chip = Chip8::new();
+        // The 1 digit sprite, which isn't symmetrical, in the top left corner
+        chip.load(&[0x60, 0x01, 0xf0, 0x29, 0xd1, 0x15]);
+        for _ in 0..3 {
+            chip.cycle().unwrap();
+        }
+
+        let mut out = Vec::new();
+        write_png(&chip, &mut out, 1, &DEFAULT_PALETTE).unwrap();
+        let (width, height, pixels) = decode(&out);
+        assert_eq!((width, height), (64, 32));
+        for y in 0..32 {
+            for x in 0..64 {
+                let expected = DEFAULT_PALETTE[chip.pixel(x as usize, y as usize) as usize];
+                assert_eq!(rgb(&pixels, width, x, y), expected);
+            }
+        }
+        // 0x20, the top row of the 1, is lit at x = 2 and nowhere else in the row
+        assert_eq!(rgb(&pixels, width, 2, 0), [255, 255, 255]);
+        assert_eq!(rgb(&pixels, width, 5, 0), [0, 0, 0]);
+    }
+
+    #[test]
+    fn should_scale_and_color_pixels() {
+        let mut chip = Chip8::with_platform(Platform::XoChip, Quirks::xochip());
+        // plane 2, then draw the 0 digit's top row at the origin
+        chip.load(&[0xf2, 0x01, 0xd0, 0x01]);
+        chip.cycle().unwrap();
+        chip.cycle().unwrap();
+
+        let palette = [[1, 2, 3], [4, 5, 6], [7, 8, 9], [10, 11, 12]];
+        let mut out = Vec::new();
+        write_png(&chip, &mut out, 3, &palette).unwrap();
+        let (width, height, pixels) = decode(&out);
+        assert_eq!((width, height), (192, 96));
+        assert_eq!(rgb(&pixels, width, 0, 0), [7, 8, 9]);
+        assert_eq!(rgb(&pixels, width, 11, 2), [7, 8, 9]);
+        assert_eq!(rgb(&pixels, width, 12, 0), [1, 2, 3]);
+        assert_eq!(rgb(&pixels, width, 0, 3), [1, 2, 3]);
+    }
+
+    #[test]
+    fn should_stretch_hires_to_fractional_pixels() {
+        let mut chip = Chip8::with_platform(Platform::SuperChip, Quirks::superchip());
+        // Hires, then draw the 0 digit's top row at (1, 0)
+        chip.load(&[0x00, 0xff, 0x60, 0x01, 0xd0, 0x11]);
+        for _ in 0..3 {
+            chip.cycle().unwrap();
+        }
+
+        // A scale 3 window, where each of the 128 hires pixels is 1.5 wide
+        let mut out = Vec::new();
+        write_png_sized(&chip, &mut out, 192, 96, &DEFAULT_PALETTE).unwrap();
+        let (width, height, pixels) = decode(&out);
+        assert_eq!((width, height), (192, 96));
+        assert_eq!(rgb(&pixels, width, 1, 0), [0, 0, 0]);
+        assert_eq!(rgb(&pixels, width, 2, 0), [255, 255, 255]);
+        assert_eq!(rgb(&pixels, width, 7, 0), [255, 255, 255]);
+        assert_eq!(rgb(&pixels, width, 8, 0), [0, 0, 0]);
+        assert_eq!(rgb(&pixels, width, 2, 1), [255, 255, 255]);
+        assert_eq!(rgb(&pixels, width, 2, 2), [0, 0, 0]);
+    }
+
+    #[test]
+    fn should_split_large_images_into_blocks() {
+        let data = vec![7; STORED_BLOCK_SIZE + 10];
+        let stream = zlib_stored(&data);
+        // Header, two block headers, the data and the checksum
+        assert_eq!(stream.len(), 2 + 5 + 5 + data.len() + 4);
+        assert_eq!(stream[2], 0);
+        assert_eq!(stream[2 + 5 + STORED_BLOCK_SIZE], 1);
+    }
+}
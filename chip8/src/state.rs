@@ -0,0 +1,178 @@
+use std::error::Error;
+use std::fmt;
+
+/// The bytes every save state starts with.
+pub const STATE_MAGIC: [u8; 4] = *b"C8ST";
+
+/// The save state format version written by this build. Loading accepts
+/// this version only; older versions will get upgraded here as the format
+/// changes.
+pub const STATE_VERSION: u16 = 1;
+
+/// Why a save state couldn't be loaded. The machine is left untouched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateError {
+    /// The data doesn't start with [`STATE_MAGIC`].
+    NotAState,
+    /// The state was written in a format version this build can't read.
+    UnsupportedVersion { version: u16 },
+    /// The state was saved while running a different ROM.
+    RomMismatch { expected: u64, found: u64 },
+    /// The state was saved on a machine emulating a different platform.
+    PlatformMismatch,
+    /// The data ends before the state does.
+    Truncated,
+    /// A field holds a value the machine can't be in, such as a stack pointer
+    /// past the end of the stack.
+    Corrupt,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StateError::NotAState => write!(f, "not a save state"),
+            StateError::UnsupportedVersion { version } => {
+                write!(f, "save state version {} isn't supported, expected {}", version, STATE_VERSION)
+            }
+            StateError::RomMismatch { expected, found } => {
+                write!(f, "save state is for rom {:016X}, not {:016X}", found, expected)
+            }
+            StateError::PlatformMismatch => write!(f, "save state is for a different platform"),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Corrupt => write!(f, "save state is corrupt"),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+/// A 64 bit FNV-1a hash of `rom`, which save states use to recognise the
+/// ROM they belong to. Unlike `std`'s hashers it's the same on every build.
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+// Appends little endian fields to a save state
+pub(crate) struct StateWriter {
+    pub(crate) bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub(crate) fn new() -> StateWriter {
+        StateWriter { bytes: Vec::new() }
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u128(&mut self, value: u128) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn slice(&mut self, values: &[u8]) {
+        self.bytes.extend_from_slice(values);
+    }
+}
+
+// Reads back what StateWriter wrote, failing with Truncated at the end of the data
+pub(crate) struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> StateReader<'a> {
+        StateReader { bytes }
+    }
+
+    pub(crate) fn slice(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.bytes.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.slice(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Corrupt),
+        }
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, StateError> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.slice(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, StateError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.slice(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, StateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.slice(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub(crate) fn u128(&mut self) -> Result<u128, StateError> {
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(self.slice(16)?);
+        Ok(u128::from_le_bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_hash_roms_stably() {
+        assert_eq!(rom_hash(&[]), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(rom_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_ne!(rom_hash(&[0x12, 0x00]), rom_hash(&[0x12, 0x02]));
+    }
+
+    #[test]
+    fn should_read_what_was_written() {
+        let mut writer = StateWriter::new();
+        writer.u8(1);
+        writer.bool(true);
+        writer.u16(0x1234);
+        writer.u64(u64::MAX - 1);
+        writer.u128(1 << 100);
+
+        let mut reader = StateReader::new(&writer.bytes);
+        assert_eq!(reader.u8(), Ok(1));
+        assert_eq!(reader.bool(), Ok(true));
+        assert_eq!(reader.u16(), Ok(0x1234));
+        assert_eq!(reader.u64(), Ok(u64::MAX - 1));
+        assert_eq!(reader.u128(), Ok(1 << 100));
+        assert_eq!(reader.u8(), Err(StateError::Truncated));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::instruction::Instruction;
+
+/// Machine cycles in one 60 Hz frame of the COSMAC VIP's 1.76 MHz 1802.
+pub const VIP_CYCLES_PER_FRAME: u32 = 3668;
+
+/// Machine cycles per frame lost to the display DMA and the interrupt routine
+/// that services it, leaving the rest of the frame for the interpreter.
+pub const VIP_FRAME_OVERHEAD: u32 = 1024 + 46;
+
+// The interpreter's fetch and decode loop, paid by every instruction
+const VIP_FETCH_CYCLES: u32 = 40;
+
+/// How [`Chip8::run_frame`](crate::Chip8::run_frame) decides how much to
+/// execute in a frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Timing {
+    /// A fixed number of instructions per frame, whatever they are, set with
+    /// [`Chip8::set_instructions_per_frame`](crate::Chip8::set_instructions_per_frame).
+    #[default]
+    InstructionCount,
+    /// Each instruction costs the machine cycles it took the original COSMAC
+    /// VIP interpreter, out of a budget of what the VIP had left each frame.
+    /// Cycles an instruction overruns by come out of the next frame. Sprite
+    /// draws wait for the vertical blank interrupt, ending the frame.
+    CosmacVip,
+}
+
+impl Timing {
+    // How the timing is identified in movies
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Timing::InstructionCount => 0,
+            Timing::CosmacVip => 1,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Timing> {
+        match id {
+            0 => Some(Timing::InstructionCount),
+            1 => Some(Timing::CosmacVip),
+            _ => None,
+        }
+    }
+}
+
+/// The machine cycles the COSMAC VIP interpreter spends executing
+/// `instruction`, including fetching and decoding it. `skipped` is whether a
+/// conditional skip was taken, which costs a little more.
+///
+/// Where the real cost depends on data, such as the position of a sprite or
+/// the carries in `fx1e`, this is the typical case.
+pub fn vip_cycles(instruction: Instruction, skipped: bool) -> u32 {
+    use crate::instruction::Instruction::*;
+
+    let skip = if skipped { 4 } else { 0 };
+    let execute = match instruction {
+        // Clearing writes all 256 bytes of display memory
+        ClearScreen => 3078,
+        Return => 10,
+        Jump { .. } => 12,
+        Call { .. } => 26,
+        SkipIfRegEqualsByte { .. } | SkipIfRegNotEqualsByte { .. } => 10 + skip,
+        SkipIfRegEqualsReg { .. } | SkipIfRegNotEqualsReg { .. } => 14 + skip,
+        SetRegister { .. } => 6,
+        AddToRegister { .. } => 10,
+        CopyToRegister { .. } | OrWithRegister { .. } | AndWithRegister { .. } | XorWithRegister { .. }
+            | AddRegisters { .. } | Sub { .. } | ShiftRight { .. } | SubReverse { .. } | ShiftLeft { .. } => 44,
+        SetI { .. } => 12,
+        JumpPlusV0 { .. } => 22,
+        Random { .. } => 36,
+        // Each row is shifted into place and XORed over two bytes of display memory
+        Draw { n, .. } => 26 + 68 * n as u32,
+        SkipIfPressed { .. } | SkipIfNotPressed { .. } => 14 + skip,
+        SetRegToDt { .. } | SetDt { .. } | SetSt { .. } => 10,
+        WaitForInput { .. } => 16,
+        IPlusReg { .. } => 16,
+        SetIDigitSprite { .. } => 16,
+        Bcd { .. } => 84,
+        StoreRegsThrough { x } | ReadToRegs { x } => 14 + 14 * (x as u32 + 1),
+        // The VIP never ran SUPER-CHIP or XO-CHIP instructions, charge them
+        // like the nearest original
+        ScrollDown { .. } | ScrollUp { .. } | ScrollRight | ScrollLeft => 3078,
+        Exit | Lores | Hires | SelectPlanes { .. } | SetPitch { .. } => 10,
+        SetIBigDigitSprite { .. } => 16,
+        SetILong { .. } => 24,
+        LoadAudio => 14 + 14 * 16,
+        StoreRegRange { x, y } | ReadRegRange { x, y } => 14 + 14 * ((x as i32 - y as i32).unsigned_abs() + 1),
+        SaveFlags { x } | LoadFlags { x } => 14 + 14 * (x as u32 + 1),
+    };
+    VIP_FETCH_CYCLES + execute
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::Instruction::*;
+
+    #[test]
+    fn should_charge_fetch_and_execute() {
+        assert_eq!(vip_cycles(SetRegister { x: 0, byte: 1 }, false), 46);
+        assert_eq!(vip_cycles(ClearScreen, false), 3118);
+    }
+
+    #[test]
+    fn should_charge_more_for_taken_skips() {
+        let skip = SkipIfRegEqualsByte { x: 0, byte: 0 };
+        assert_eq!(vip_cycles(skip, true), vip_cycles(skip, false) + 4);
+    }
+
+    #[test]
+    fn should_charge_by_data_size() {
+        assert!(vip_cycles(Draw { x: 0, y: 0, n: 15 }, false) > vip_cycles(Draw { x: 0, y: 0, n: 1 }, false));
+        assert_eq!(vip_cycles(StoreRegsThrough { x: 3 }, false), 40 + 14 + 56);
+    }
+}
@@ -0,0 +1,179 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crate::chip8::Chip8;
+use crate::rng::RandomSource;
+
+// Frames per second of emulated time, which the audio is kept in step with
+const FRAME_RATE: u32 = 60;
+
+// Size of the RIFF, fmt and data chunk headers before the first sample
+const HEADER_SIZE: u32 = 44;
+
+/// The lowest sample rate [`WavRecorder`] records at, in Hz.
+pub const MIN_SAMPLE_RATE: u32 = 8000;
+/// The highest sample rate [`WavRecorder`] records at, in Hz.
+pub const MAX_SAMPLE_RATE: u32 = 192000;
+
+/// Records a machine's audio output to a 16 bit mono WAV file.
+///
+/// Recording is driven by emulated frames rather than the wall clock: each
+/// call to [`WavRecorder::record_frame`] appends exactly a 60th of a second
+/// of whatever [`Chip8::fill_audio`] produces, so the file lines up with the
+/// game however fast it's run.
+pub struct WavRecorder<W: Write + Seek> {
+    writer: W,
+    sample_rate: u32,
+    samples: u64, // Samples written so far
+    remainder: u64, // Leftover sample_rate / FRAME_RATE fractions, in 60ths of a sample
+    buffer: Vec<f32>,
+}
+
+impl<W: Write + Seek> WavRecorder<W> {
+    /// Starts a recording at `sample_rate`, writing a header that
+    /// [`WavRecorder::finish`] fills in once the length is known. Fails with
+    /// [`io::ErrorKind::InvalidInput`] unless `sample_rate` is between
+    /// [`MIN_SAMPLE_RATE`] and [`MAX_SAMPLE_RATE`].
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<WavRecorder<W>> {
+        if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&sample_rate) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("sample rate {} Hz isn't between {} and {} Hz", sample_rate, MIN_SAMPLE_RATE, MAX_SAMPLE_RATE),
+            ));
+        }
+        write_header(&mut writer, sample_rate, 0)?;
+        Ok(WavRecorder { writer, sample_rate, samples: 0, remainder: 0, buffer: Vec::new() })
+    }
+
+    /// Appends one frame of audio. Call this once per frame, before
+    /// [`Chip8::run_frame`], so the frame is heard for as long as the sound
+    /// timer was running when it started.
+    pub fn record_frame<R: RandomSource>(&mut self, chip: &mut Chip8<R>) -> io::Result<()> {
+        self.remainder += self.sample_rate as u64;
+        let count = self.remainder / FRAME_RATE as u64;
+        self.remainder %= FRAME_RATE as u64;
+        // The header's sizes are 32 bits, which a recording can't outgrow
+        data_size(self.samples + count)?;
+        let count = count as usize;
+
+        self.buffer.resize(count, 0.0);
+        chip.fill_audio(&mut self.buffer, self.sample_rate);
+
+        let mut bytes = Vec::with_capacity(count * 2);
+        for &sample in &self.buffer {
+            // Half scale leaves headroom, the pattern is a full swing square wave
+            let value = (sample * i16::MAX as f32 * 0.5) as i16;
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        self.writer.write_all(&bytes)?;
+        self.samples += count as u64;
+        Ok(())
+    }
+
+    /// How many samples have been recorded.
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// Fills in the header's lengths and returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        write_header(&mut self.writer, self.sample_rate, data_size(self.samples)?)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+// Bytes of sample data `samples` take up, if they fit in a WAV file
+fn data_size(samples: u64) -> io::Result<u32> {
+    samples
+        .checked_mul(2)
+        .filter(|&size| size <= (u32::MAX - HEADER_SIZE) as u64)
+        .map(|size| size as u32)
+        .ok_or_else(|| io::Error::other("recording too long for a WAV file"))
+}
+
+fn write_header<W: Write>(writer: &mut W, sample_rate: u32, data_size: u32) -> io::Result<()> {
+    let byte_rate = sample_rate
+        .checked_mul(2)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "sample rate too high for a WAV file"))?;
+    let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(HEADER_SIZE - 8 + data_size).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes()); // fmt chunk size
+    header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    header.extend_from_slice(&1u16.to_le_bytes()); // Mono
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&byte_rate.to_le_bytes()); // Bytes per second
+    header.extend_from_slice(&2u16.to_le_bytes()); // Bytes per sample
+    header.extend_from_slice(&16u16.to_le_bytes()); // Bits per sample
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_size.to_le_bytes());
+    writer.write_all(&header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    #[test]
+    fn should_write_header() {
+        let mut chip = Chip8::new();
+        let mut recorder = WavRecorder::new(Cursor::new(Vec::new()), 22050).unwrap();
+        recorder.record_frame(&mut chip).unwrap();
+        recorder.record_frame(&mut chip).unwrap();
+        let bytes = recorder.finish().unwrap().into_inner();
+
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&bytes, 24), 22050);
+        assert_eq!(&bytes[36..40], b"data");
+        // Two frames at 367.5 samples each
+        assert_eq!(u32_at(&bytes, 40), 735 * 2);
+        assert_eq!(u32_at(&bytes, 4), 36 + 735 * 2);
+        assert_eq!(bytes.len(), 44 + 735 * 2);
+    }
+
+    #[test]
+    fn should_reject_unplayable_sample_rates() {
+        for &rate in &[0, MIN_SAMPLE_RATE - 1, MAX_SAMPLE_RATE + 1, u32::MAX] {
+            let err = WavRecorder::new(Cursor::new(Vec::new()), rate).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert!(WavRecorder::new(Cursor::new(Vec::new()), MAX_SAMPLE_RATE).is_ok());
+    }
+
+    #[test]
+    fn should_refuse_recordings_too_long_for_the_header() {
+        assert_eq!(data_size(1000).unwrap(), 2000);
+        assert!(data_size((u32::MAX as u64 - 44) / 2).is_ok());
+        assert!(data_size((u32::MAX as u64 - 44) / 2 + 1).is_err());
+        assert!(data_size(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn should_follow_sound_timer() {
+        let mut chip = Chip8::new();
+        // buzzer := v0 with v0 = 2, then loop forever. The timer ticks once in
+        // the frame that sets it, leaving one frame of sound.
+        chip.load(&[0x60, 0x02, 0xf0, 0x18, 0x12, 0x04]);
+        let mut recorder = WavRecorder::new(Cursor::new(Vec::new()), 12000).unwrap();
+        for _ in 0..3 {
+            recorder.record_frame(&mut chip).unwrap();
+            chip.run_frame().unwrap();
+        }
+        let bytes = recorder.finish().unwrap().into_inner();
+        let frames: Vec<&[u8]> = bytes[44..].chunks(400).collect();
+
+        assert_eq!(frames.len(), 3);
+        assert!(frames[0].iter().all(|&b| b == 0));
+        assert!(frames[1].iter().any(|&b| b != 0));
+        assert!(frames[2].iter().all(|&b| b == 0));
+    }
+}
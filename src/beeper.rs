@@ -0,0 +1,251 @@
+//! The buzzer, playing the XO-CHIP audio pattern while the sound timer runs.
+
+#[cfg(feature = "sound")]
+use std::sync::{Arc, Mutex};
+
+use chip8::PATTERN_SIZE;
+#[cfg(any(feature = "sound", test))]
+use chip8::{Voice, DEFAULT_PATTERN, DEFAULT_PERIOD_BITS, DEFAULT_PITCH};
+
+// How long the tone takes to fade in and out, long enough to avoid a click
+#[cfg(any(feature = "sound", test))]
+const RAMP_SECONDS: f32 = 0.005;
+
+/// Plays the audio pattern at the pitch a ROM set, fading in and out instead
+/// of starting and stopping abruptly. Until a ROM loads its own pattern or
+/// pitch it plays the default square wave at `frequency` Hz.
+#[cfg(any(feature = "sound", test))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Beeper {
+    frequency: f64,
+    volume: f32,
+    sample_rate: u32,
+    playing: bool,
+    voice: Voice,
+    gain: f32, // Current loudness, ramping towards volume or 0
+}
+
+#[cfg(any(feature = "sound", test))]
+impl Beeper {
+    /// Plays the default pattern at `frequency` Hz, and any pattern at
+    /// `volume`, from 0 for silent to 1 for full scale.
+    pub fn new(frequency: f64, volume: f32, sample_rate: u32) -> Beeper {
+        Beeper {
+            frequency,
+            volume: volume.clamp(0.0, 1.0),
+            sample_rate,
+            playing: false,
+            voice: Voice::new(),
+            gain: 0.0,
+        }
+    }
+
+    /// Starts the tone fading in, or fading out when `playing` is `false`.
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+    }
+
+    /// Switches to another pattern or pitch, carrying on from the same point
+    /// in the pattern.
+    pub fn set_voice(&mut self, pattern: &[u8; PATTERN_SIZE], pitch: u8) {
+        self.voice.pattern = *pattern;
+        self.voice.pitch = pitch;
+    }
+
+    /// Writes the next samples of the pattern to `out`.
+    pub fn fill(&mut self, out: &mut [f32]) {
+        if self.voice.pattern == DEFAULT_PATTERN && self.voice.pitch == DEFAULT_PITCH {
+            self.voice.render_at(out, self.sample_rate, self.frequency * DEFAULT_PERIOD_BITS);
+        } else {
+            self.voice.render(out, self.sample_rate);
+        }
+
+        let ramp = self.volume / (RAMP_SECONDS * self.sample_rate as f32);
+        let target = if self.playing { self.volume } else { 0.0 };
+
+        for sample in out.iter_mut() {
+            self.gain = if self.gain < target {
+                (self.gain + ramp).min(target)
+            } else {
+                (self.gain - ramp).max(target)
+            };
+            *sample *= self.gain;
+        }
+    }
+}
+
+/// Somewhere the buzzer can be heard.
+pub trait AudioBackend {
+    /// Sounds the buzzer while `playing` is `true`. Called once a frame with
+    /// whether the sound timer is running.
+    fn set_playing(&mut self, playing: bool);
+
+    /// Plays `pattern` at `pitch` from now on. Called once a frame with the
+    /// machine's XO-CHIP audio registers.
+    fn set_voice(&mut self, pattern: &[u8; PATTERN_SIZE], pitch: u8);
+}
+
+/// A backend that stays silent, for running without a sound device.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NullBackend;
+
+impl AudioBackend for NullBackend {
+    fn set_playing(&mut self, _playing: bool) {}
+
+    fn set_voice(&mut self, _pattern: &[u8; PATTERN_SIZE], _pitch: u8) {}
+}
+
+/// Plays the buzzer on the default output device.
+#[cfg(feature = "sound")]
+pub struct CpalBackend {
+    beeper: Arc<Mutex<Beeper>>,
+    // Dropping the stream stops playback
+    _stream: cpal::Stream,
+}
+
+#[cfg(feature = "sound")]
+impl CpalBackend {
+    /// Opens the default output device, failing with a description of why
+    /// if there isn't one that can play.
+    pub fn new(frequency: f64, volume: f32) -> Result<CpalBackend, String> {
+        use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| "no audio output device".to_string())?;
+        let config: cpal::StreamConfig = device.default_output_config().map_err(|err| err.to_string())?.into();
+        let channels = config.channels as usize;
+        let beeper = Arc::new(Mutex::new(Beeper::new(frequency, volume, config.sample_rate.0)));
+
+        let shared = beeper.clone();
+        let mut mono = Vec::new();
+        let stream = device
+            .build_output_stream(
+                &config,
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    mono.resize(data.len() / channels, 0.0);
+                    if let Ok(mut beeper) = shared.lock() {
+                        beeper.fill(&mut mono);
+                    }
+                    for (frame, &sample) in data.chunks_mut(channels).zip(&mono) {
+                        for out in frame {
+                            *out = sample;
+                        }
+                    }
+                },
+                |err| eprintln!("Audio error: {}", err),
+                None,
+            )
+            .map_err(|err| err.to_string())?;
+        stream.play().map_err(|err| err.to_string())?;
+
+        Ok(CpalBackend { beeper, _stream: stream })
+    }
+}
+
+#[cfg(feature = "sound")]
+impl AudioBackend for CpalBackend {
+    fn set_playing(&mut self, playing: bool) {
+        if let Ok(mut beeper) = self.beeper.lock() {
+            beeper.set_playing(playing);
+        }
+    }
+
+    fn set_voice(&mut self, pattern: &[u8; PATTERN_SIZE], pitch: u8) {
+        if let Ok(mut beeper) = self.beeper.lock() {
+            beeper.set_voice(pattern, pitch);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::DEFAULT_FREQUENCY;
+
+    #[test]
+    fn should_be_silent_until_played() {
+        let mut beeper = Beeper::new(DEFAULT_FREQUENCY, 0.5, 44100);
+        let mut out = [1.0; 64];
+        beeper.fill(&mut out);
+        assert!(out.iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn should_ramp_up_to_volume() {
+        let mut beeper = Beeper::new(DEFAULT_FREQUENCY, 0.5, 8000);
+        beeper.set_voice(&[0xff; PATTERN_SIZE], DEFAULT_PITCH);
+        beeper.set_playing(true);
+        // The ramp lasts 40 samples at 8 kHz
+        let mut out = [0.0; 40];
+        beeper.fill(&mut out);
+        assert!(out[0] > 0.0 && out[0] < 0.05);
+        assert!(out.windows(2).all(|pair| pair[1] > pair[0]));
+        assert!((out[39] - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn should_play_default_pattern_until_changed() {
+        let mut beeper = Beeper::new(DEFAULT_FREQUENCY, 1.0, 8000);
+        beeper.set_playing(true);
+        // 40 samples of ramp leave playback 20 bits into the pattern, and each
+        // bit lasts 2 samples at the default pitch
+        let mut ramp = [0.0; 40];
+        beeper.fill(&mut ramp);
+        let mut out = [0.0; 16];
+        beeper.fill(&mut out);
+        assert_eq!(out[..8], [-1.0; 8]);
+        assert_eq!(out[8..], [1.0; 8]);
+    }
+
+    #[test]
+    fn should_play_default_pattern_at_frequency() {
+        let mut beeper = Beeper::new(1000.0, 1.0, 8000);
+        beeper.set_playing(true);
+        // 40 samples of ramp leave playback 40 bits into the pattern, and each
+        // bit lasts 1 sample at 1000 Hz
+        let mut ramp = [0.0; 40];
+        beeper.fill(&mut ramp);
+        let mut out = [0.0; 8];
+        beeper.fill(&mut out);
+        assert_eq!(out, [1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0]);
+        // A ROM's own pattern plays at its pitch instead
+        beeper.set_voice(&[0xaa; PATTERN_SIZE], 112);
+        beeper.fill(&mut out[..4]);
+        assert_eq!(out[..4], [1.0, -1.0, 1.0, -1.0]);
+    }
+
+    #[test]
+    fn should_play_pattern_at_pitch() {
+        let mut beeper = Beeper::new(DEFAULT_FREQUENCY, 1.0, 8000);
+        beeper.set_playing(true);
+        let mut ramp = [0.0; 40];
+        beeper.fill(&mut ramp);
+        // Pitch 112 plays 8000 bits a second, one a sample
+        beeper.set_voice(&[0xaa; PATTERN_SIZE], 112);
+        let mut out = [0.0; 4];
+        beeper.fill(&mut out);
+        assert_eq!(out, [1.0, -1.0, 1.0, -1.0]);
+    }
+
+    #[test]
+    fn should_ramp_down_when_stopped() {
+        let mut beeper = Beeper::new(DEFAULT_FREQUENCY, 0.5, 8000);
+        beeper.set_voice(&[0xff; PATTERN_SIZE], DEFAULT_PITCH);
+        beeper.set_playing(true);
+        let mut out = [0.0; 40];
+        beeper.fill(&mut out);
+        beeper.set_playing(false);
+        beeper.fill(&mut out);
+        assert!(out[0].abs() > 0.4);
+        assert!(out[39].abs() < 1e-6);
+    }
+
+    #[test]
+    fn should_accept_anything_with_null_backend() {
+        let mut backend: Box<dyn AudioBackend> = Box::new(NullBackend);
+        backend.set_playing(true);
+        backend.set_voice(&[0; PATTERN_SIZE], 0);
+        backend.set_playing(false);
+    }
+}
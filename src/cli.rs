@@ -0,0 +1,541 @@
+use chip8::{Movie, Platform, Quirks, DEFAULT_FREQUENCY, DEFAULT_PALETTE, MAX_SAMPLE_RATE, MIN_SAMPLE_RATE};
+use crate::headless::Limit;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+Usage:
+    nemulator [run] <rom> [options]   Play a rom
+    nemulator debug <rom> [options] [--symbols <file>] [--gdb <port>]
+                                      Step through a rom, with labels from a symbol file,
+                                      or let a GDB remote protocol client do it
+                                      from a localhost port
+    nemulator info <rom>              Describe a rom
+    nemulator disasm <rom>            Print a disassembly listing
+    nemulator asm <source> <rom> [--symbols <file>]
+                                      Assemble Octo source into a rom
+    nemulator help                    Show this message
+
+Machine options:
+    --platform <name>       chip8, schip or xochip [default: chip8]
+    --quirks <preset>       vip, chip48, schip, xochip or classic
+                            [default: the platform's, or classic without --platform]
+    --ipf <count>           Instructions per frame [default: 10]
+    --seed <number>         Seed the random number generator
+
+Display options:
+    --scale <pixels>        Window pixels per lores pixel, 1 to 32 [default: 8]
+    --fullscreen            Fill the screen instead of opening a window
+    --colors <colors>       Two or four hex colors separated by commas, for
+                            off, on, then the XO-CHIP second plane and both
+                            planes [default: 000000,ffffff,999999,4d4d4d]
+
+Sound options:
+    --tone <hz>             Pitch of the buzzer before a rom sets its own, 20 to
+                            20000 [default: 500]
+    --volume <percent>      Loudness of the buzzer, 0 to 100 [default: 25]
+
+Other options:
+    --config <file>         Keyboard and controller map [default: nemulator.toml]
+    --rewind-kb <size>      Memory for rewinding, 0 to 1048576 [default: 8192]
+    --wav <file>            Record the sound
+    --sample-rate <hz>      Sample rate of the recording, 8000 to 192000 [default: 44100]
+    --gif <file>            Record the display from the start
+    --record-movie <file>   Record the input to replay later
+    --play-movie <file>     Replay recorded input, with its machine settings
+
+Headless options:
+    --headless              Run without a window or sound
+    --frames <count>        Stop after this many frames
+    --cycles <count>        Stop after this many instructions
+    --input <script>        Keys to press, as in \"press 5 at frame 30, release at 35\"
+    --input-file <file>     Read the input script from a file
+    --output <file>         Save the final display as a PGM image
+";
+
+// Options that take a value
+const VALUE_OPTIONS: [&str; 20] = [
+    "--platform", "--quirks", "--ipf", "--seed", "--scale", "--colors", "--tone", "--volume", "--config",
+    "--rewind-kb", "--wav", "--sample-rate", "--gif", "--record-movie", "--play-movie", "--frames", "--cycles",
+    "--input", "--input-file", "--output",
+];
+
+// Options that are either given or not
+const SWITCHES: [&str; 2] = ["--fullscreen", "--headless"];
+
+// Options the debugger takes, which leave out recording and running headless
+const DEBUG_OPTIONS: [&str; 11] = [
+    "--platform", "--quirks", "--ipf", "--seed", "--scale", "--colors", "--tone", "--volume", "--config", "--symbols",
+    "--gdb",
+];
+
+// Options that only mean something without a window
+const HEADLESS_OPTIONS: [&str; 5] = ["--frames", "--cycles", "--input", "--input-file", "--output"];
+
+const DEFAULT_SCALE: usize = 8;
+const MAX_SCALE: usize = 32;
+// Memory the rewind history may use unless --rewind-kb says otherwise
+const DEFAULT_REWIND_KB: usize = 8 * 1024;
+const MAX_REWIND_KB: usize = 1024 * 1024;
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+const DEFAULT_TONE: u32 = DEFAULT_FREQUENCY as u32;
+const MIN_TONE: u32 = 20;
+const MAX_TONE: u32 = 20000;
+const DEFAULT_VOLUME: u32 = 25;
+
+/// What the command line asks for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Run(Box<RunOptions>),
+    Debug { options: Box<RunOptions>, symbols: Option<String>, gdb: Option<u16> },
+    Info(String),
+    Disasm(String),
+    Asm { source: String, output: String, symbols: Option<String> },
+    Help,
+}
+
+/// How to play a rom.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunOptions {
+    pub rom: String,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    /// Whether `--platform` was given rather than defaulted.
+    pub platform_given: bool,
+    /// Whether `--quirks` was given rather than following the platform.
+    pub quirks_given: bool,
+    pub instructions_per_frame: Option<u32>,
+    pub seed: Option<u64>,
+    pub scale: usize,
+    pub fullscreen: bool,
+    pub palette: [[u8; 3]; 4],
+    /// Frequency of the buzzer in Hz, until a rom sets its own pitch or pattern.
+    pub tone: u32,
+    /// Loudness of the buzzer as a percentage of full scale.
+    pub volume: u32,
+    pub config: Option<String>,
+    /// Bytes the rewind history may use.
+    pub rewind_bytes: usize,
+    pub wav: Option<String>,
+    pub sample_rate: u32,
+    pub gif: Option<String>,
+    pub record_movie: Option<String>,
+    pub play_movie: Option<String>,
+    pub headless: Option<HeadlessOptions>,
+}
+
+/// How to run a rom with `--headless`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeadlessOptions {
+    pub limit: Limit,
+    pub input: Option<ScriptSource>,
+    pub output: Option<String>,
+}
+
+/// Where the headless input script comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScriptSource {
+    Inline(String),
+    File(String),
+}
+
+/// Reads the arguments after the program name. Giving a rom without a
+/// subcommand is the same as `run`.
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => return Err("no rom given".to_string()),
+    };
+    match command {
+        "help" | "--help" | "-h" => Ok(Command::Help),
+        "run" => parse_run(rest),
+        "debug" => parse_debug(rest),
+        "info" => Ok(Command::Info(single_rom("info", rest)?)),
+        "disasm" => Ok(Command::Disasm(single_rom("disasm", rest)?)),
+        "asm" => parse_asm(rest),
+        _ => parse_run(args),
+    }
+}
+
+// The one rom `command` works on
+fn single_rom(command: &str, args: &[String]) -> Result<String, String> {
+    match args {
+        [rom] if !rom.starts_with("--") => Ok(rom.clone()),
+        [] => Err(format!("{} needs a rom", command)),
+        _ => Err(format!("{} takes a rom and nothing else", command)),
+    }
+}
+
+fn parse_asm(args: &[String]) -> Result<Command, String> {
+    let options = Options::parse(args, &["--symbols"], &[])?;
+    match options.positional.as_slice() {
+        [source, output] => Ok(Command::Asm {
+            source: source.clone(),
+            output: output.clone(),
+            symbols: options.values.get("--symbols").cloned(),
+        }),
+        _ => Err("asm needs a source file and a rom to write".to_string()),
+    }
+}
+
+fn parse_run(args: &[String]) -> Result<Command, String> {
+    let options = Options::parse(args, &VALUE_OPTIONS, &SWITCHES)?;
+    Ok(Command::Run(Box::new(run_options(&options)?)))
+}
+
+fn parse_debug(args: &[String]) -> Result<Command, String> {
+    let options = Options::parse(args, &DEBUG_OPTIONS, &["--fullscreen"])?;
+    let symbols = options.values.get("--symbols").cloned();
+    let gdb = options.number("--gdb")?;
+    Ok(Command::Debug { options: Box::new(run_options(&options)?), symbols, gdb })
+}
+
+fn run_options(options: &Options) -> Result<RunOptions, String> {
+    let rom = match options.positional.as_slice() {
+        [rom] => rom.clone(),
+        [] => return Err("no rom given".to_string()),
+        [_, extra, ..] => return Err(format!("unexpected argument {}, only one rom can be played", extra)),
+    };
+
+    let headless = if options.switch("--headless") {
+        Some(parse_headless(options)?)
+    } else if let Some(option) = HEADLESS_OPTIONS.iter().find(|&&name| options.values.contains_key(name)) {
+        return Err(format!("{} only applies with --headless", option));
+    } else {
+        None
+    };
+
+    let platform = options.value("--platform", parse_platform)?;
+    // A platform brings its own quirks, while the classic ones stay the default
+    let quirks = match options.value("--quirks", parse_quirks)? {
+        Some(quirks) => quirks,
+        None => platform.map_or_else(Quirks::default, Platform::default_quirks),
+    };
+    let instructions_per_frame = match options.number::<u32>("--ipf")? {
+        Some(0) => return Err("--ipf should be at least 1".to_string()),
+        ipf => ipf,
+    };
+    let scale = match options.number("--scale")? {
+        Some(scale) if !(1..=MAX_SCALE).contains(&scale) => {
+            return Err(format!("--scale should be from 1 to {}", MAX_SCALE))
+        }
+        scale => scale.unwrap_or(DEFAULT_SCALE),
+    };
+    let sample_rate = match options.number("--sample-rate")? {
+        Some(rate) if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&rate) => {
+            return Err(format!(
+                "--sample-rate should be from {} to {} Hz, like 44100 or 48000",
+                MIN_SAMPLE_RATE, MAX_SAMPLE_RATE
+            ))
+        }
+        rate => rate.unwrap_or(DEFAULT_SAMPLE_RATE),
+    };
+    let rewind_kb = options.number::<usize>("--rewind-kb")?.unwrap_or(DEFAULT_REWIND_KB);
+    let rewind_bytes = match rewind_kb.checked_mul(1024) {
+        Some(bytes) if rewind_kb <= MAX_REWIND_KB => bytes,
+        _ => return Err(format!("--rewind-kb should be from 0 to {}", MAX_REWIND_KB)),
+    };
+    let tone = match options.number("--tone")? {
+        Some(tone) if !(MIN_TONE..=MAX_TONE).contains(&tone) => {
+            return Err(format!("--tone should be from {} to {} Hz", MIN_TONE, MAX_TONE))
+        }
+        tone => tone.unwrap_or(DEFAULT_TONE),
+    };
+    let volume = match options.number("--volume")? {
+        Some(volume) if volume > 100 => return Err("--volume should be a percentage from 0 to 100".to_string()),
+        volume => volume.unwrap_or(DEFAULT_VOLUME),
+    };
+
+    Ok(RunOptions {
+        rom,
+        platform: platform.unwrap_or(Platform::Chip8),
+        quirks,
+        platform_given: platform.is_some(),
+        quirks_given: options.values.contains_key("--quirks"),
+        instructions_per_frame,
+        seed: options.number("--seed")?,
+        scale,
+        fullscreen: options.switch("--fullscreen"),
+        palette: options.value("--colors", parse_palette)?.unwrap_or(DEFAULT_PALETTE),
+        tone,
+        volume,
+        config: options.values.get("--config").cloned(),
+        rewind_bytes,
+        wav: options.values.get("--wav").cloned(),
+        sample_rate,
+        gif: options.values.get("--gif").cloned(),
+        record_movie: options.values.get("--record-movie").cloned(),
+        play_movie: options.values.get("--play-movie").cloned(),
+        headless,
+    })
+}
+
+impl RunOptions {
+    /// Checks the machine options given don't contradict the settings
+    /// `movie` was recorded with, which it always plays back with.
+    pub fn check_movie(&self, movie: &Movie) -> Result<(), String> {
+        if self.platform_given && self.platform != movie.platform {
+            return Err("--platform differs from the platform the movie was recorded on".to_string());
+        }
+        if self.quirks_given && self.quirks != movie.quirks {
+            return Err("--quirks differs from the quirks the movie was recorded with".to_string());
+        }
+        match self.instructions_per_frame {
+            Some(ipf) if ipf != movie.instructions_per_frame => {
+                return Err(format!("--ipf {} differs from the movie's {}", ipf, movie.instructions_per_frame))
+            }
+            _ => {}
+        }
+        match self.seed {
+            Some(seed) if seed != movie.seed => Err(format!("--seed {} differs from the movie's {}", seed, movie.seed)),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn parse_headless(options: &Options) -> Result<HeadlessOptions, String> {
+    let limit = match (options.number("--frames")?, options.number("--cycles")?) {
+        (Some(frames), None) => Limit::Frames(frames),
+        (None, Some(cycles)) => Limit::Cycles(cycles),
+        _ => return Err("--headless needs either --frames or --cycles".to_string()),
+    };
+    let input = match (options.values.get("--input"), options.values.get("--input-file")) {
+        (Some(script), None) => Some(ScriptSource::Inline(script.clone())),
+        (None, Some(path)) => Some(ScriptSource::File(path.clone())),
+        (None, None) => None,
+        _ => return Err("give only one of --input and --input-file".to_string()),
+    };
+    Ok(HeadlessOptions { limit, input, output: options.values.get("--output").cloned() })
+}
+
+/// Reads a platform by the name `--platform` takes.
+pub fn parse_platform(name: &str) -> Result<Platform, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "chip8" | "chip-8" => Ok(Platform::Chip8),
+        "schip" | "superchip" | "super-chip" => Ok(Platform::SuperChip),
+        "xochip" | "xo-chip" => Ok(Platform::XoChip),
+        _ => Err(format!("{} isn't a platform, expected chip8, schip or xochip", name)),
+    }
+}
+
+/// Reads a quirk preset by the name `--quirks` takes.
+pub fn parse_quirks(name: &str) -> Result<Quirks, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "vip" | "cosmac-vip" => Ok(Quirks::cosmac_vip()),
+        "chip48" | "chip-48" => Ok(Quirks::chip48()),
+        "schip" | "superchip" | "super-chip" => Ok(Quirks::superchip()),
+        "xochip" | "xo-chip" => Ok(Quirks::xochip()),
+        "classic" => Ok(Quirks::default()),
+        _ => Err(format!("{} isn't a quirk preset, expected vip, chip48, schip, xochip or classic", name)),
+    }
+}
+
+/// Reads the palette `--colors` takes: two or four RGB colors in hex,
+/// separated by commas. Two colors only replace off and on, keeping the
+/// default greys for the second XO-CHIP plane.
+pub fn parse_palette(colors: &str) -> Result<[[u8; 3]; 4], String> {
+    let colors = colors.split(',').map(|color| parse_color(color.trim())).collect::<Result<Vec<_>, _>>()?;
+    let mut palette = DEFAULT_PALETTE;
+    match colors.len() {
+        2 | 4 => palette[..colors.len()].copy_from_slice(&colors),
+        count => return Err(format!("expected 2 or 4 colors, got {}", count)),
+    }
+    Ok(palette)
+}
+
+// A color as six hex digits, optionally after a #
+fn parse_color(color: &str) -> Result<[u8; 3], String> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    let error = || format!("{} isn't a color, expected six hex digits like ff8800", color);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(error());
+    }
+    let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16).map_err(|_| error());
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+// The arguments split into options and everything else
+struct Options {
+    values: HashMap<String, String>,
+    switches: Vec<String>,
+    positional: Vec<String>,
+}
+
+impl Options {
+    // Takes `--name value` or `--name=value` for `value_options`, and bare `switches`
+    fn parse(args: &[String], value_options: &[&str], switches: &[&str]) -> Result<Options, String> {
+        let mut options = Options { values: HashMap::new(), switches: Vec::new(), positional: Vec::new() };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                options.positional.push(arg.clone());
+                continue;
+            }
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            if switches.contains(&name) && inline.is_none() {
+                options.switches.push(name.to_string());
+            } else if value_options.contains(&name) {
+                let value = match inline.or_else(|| args.next().cloned()) {
+                    Some(value) => value,
+                    None => return Err(format!("{} needs a value", name)),
+                };
+                if options.values.insert(name.to_string(), value).is_some() {
+                    return Err(format!("{} is given more than once", name));
+                }
+            } else if switches.contains(&name) {
+                return Err(format!("{} doesn't take a value", name));
+            } else {
+                return Err(format!("unknown option {}", name));
+            }
+        }
+        Ok(options)
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.iter().any(|switch| switch == name)
+    }
+
+    // The value of `name` read with `parse`, prefixing errors with the option
+    fn value<T>(&self, name: &str, parse: impl Fn(&str) -> Result<T, String>) -> Result<Option<T>, String> {
+        self.values.get(name).map(|value| parse(value).map_err(|err| format!("{}: {}", name, err))).transpose()
+    }
+
+    fn number<T: FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+        self.value(name, |value| value.parse().map_err(|_| format!("{} isn't a valid number", value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &str) -> Result<Command, String> {
+        parse(&args.split_whitespace().map(String::from).collect::<Vec<_>>())
+    }
+
+    fn run_options(args: &str) -> RunOptions {
+        match parse_args(args) {
+            Ok(Command::Run(options)) => *options,
+            other => panic!("expected run options, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn should_run_a_bare_rom_with_defaults() {
+        let options = run_options("pong.ch8");
+        assert_eq!(options, run_options("run pong.ch8"));
+        assert_eq!(options.rom, "pong.ch8");
+        assert_eq!(options.platform, Platform::Chip8);
+        assert_eq!(options.quirks, Quirks::default());
+        assert_eq!(options.instructions_per_frame, None);
+        assert_eq!(options.scale, DEFAULT_SCALE);
+        assert_eq!(options.palette, DEFAULT_PALETTE);
+        assert_eq!((options.tone, options.volume), (500, 25));
+        assert_eq!(options.rewind_bytes, 8 * 1024 * 1024);
+        assert!(!options.fullscreen);
+        assert_eq!(options.headless, None);
+    }
+
+    #[test]
+    fn should_read_machine_and_display_options() {
+        let options = run_options("run --platform schip game.ch8 --ipf=30 --seed 7 --scale 4 --fullscreen --colors #102030,405060");
+        assert_eq!(options.rom, "game.ch8");
+        assert_eq!(options.platform, Platform::SuperChip);
+        assert_eq!(options.quirks, Quirks::superchip());
+        assert_eq!(options.instructions_per_frame, Some(30));
+        assert_eq!(options.seed, Some(7));
+        assert_eq!(options.scale, 4);
+        assert!(options.fullscreen);
+        assert_eq!(options.palette, [[0x10, 0x20, 0x30], [0x40, 0x50, 0x60], DEFAULT_PALETTE[2], DEFAULT_PALETTE[3]]);
+
+        let options = run_options("game.ch8 --tone 440 --volume 0");
+        assert_eq!((options.tone, options.volume), (440, 0));
+
+        // An explicit preset wins over the platform's
+        assert_eq!(run_options("game.ch8 --platform chip8 --quirks vip").quirks, Quirks::cosmac_vip());
+    }
+
+    #[test]
+    fn should_read_subcommands() {
+        assert_eq!(parse_args("info pong.ch8"), Ok(Command::Info("pong.ch8".to_string())));
+        assert_eq!(parse_args("disasm pong.ch8"), Ok(Command::Disasm("pong.ch8".to_string())));
+        assert_eq!(
+            parse_args("asm pong.8o pong.ch8 --symbols pong.sym"),
+            Ok(Command::Asm { source: "pong.8o".to_string(), output: "pong.ch8".to_string(), symbols: Some("pong.sym".to_string()) })
+        );
+        assert_eq!(parse_args("--help"), Ok(Command::Help));
+        match parse_args("debug pong.ch8 --ipf 20 --symbols pong.sym") {
+            Ok(Command::Debug { options, symbols, gdb }) => {
+                assert_eq!(options.instructions_per_frame, Some(20));
+                assert_eq!(symbols, Some("pong.sym".to_string()));
+                assert_eq!(gdb, None);
+            }
+            other => panic!("expected the debugger, got {:?}", other),
+        }
+        match parse_args("debug pong.ch8 --gdb 1234") {
+            Ok(Command::Debug { gdb, .. }) => assert_eq!(gdb, Some(1234)),
+            other => panic!("expected the debugger, got {:?}", other),
+        }
+        assert_eq!(parse_args("debug pong.ch8 --headless"), Err("unknown option --headless".to_string()));
+    }
+
+    #[test]
+    fn should_read_headless_options() {
+        let options = run_options("run pong.ch8 --headless --frames 60 --input-file keys.txt --output out.pgm");
+        assert_eq!(
+            options.headless,
+            Some(HeadlessOptions {
+                limit: Limit::Frames(60),
+                input: Some(ScriptSource::File("keys.txt".to_string())),
+                output: Some("out.pgm".to_string()),
+            })
+        );
+        assert!(parse_args("run pong.ch8 --headless").unwrap_err().contains("--frames or --cycles"));
+        assert!(parse_args("run pong.ch8 --frames 60").unwrap_err().contains("only applies with --headless"));
+    }
+
+    #[test]
+    fn should_refuse_options_contradicting_a_movie() {
+        let mut chip = chip8::Chip8::with_platform(Platform::SuperChip, Quirks::superchip());
+        chip.set_instructions_per_frame(20);
+        chip.seed_rng(7);
+        let movie = chip8::MovieRecorder::start(&mut chip).finish();
+        let check = |args| run_options(args).check_movie(&movie);
+
+        assert_eq!(check("pong.ch8 --play-movie run.c8m"), Ok(()));
+        assert_eq!(check("pong.ch8 --play-movie run.c8m --platform schip --ipf 20 --seed 7"), Ok(()));
+        assert!(check("pong.ch8 --play-movie run.c8m --platform xochip").unwrap_err().contains("--platform"));
+        assert!(check("pong.ch8 --play-movie run.c8m --platform schip --quirks vip").unwrap_err().contains("--quirks"));
+        assert_eq!(check("pong.ch8 --play-movie run.c8m --ipf 10"), Err("--ipf 10 differs from the movie's 20".to_string()));
+        assert_eq!(check("pong.ch8 --play-movie run.c8m --seed 8"), Err("--seed 8 differs from the movie's 7".to_string()));
+    }
+
+    #[test]
+    fn should_explain_bad_arguments() {
+        let error = |args| parse_args(args).unwrap_err();
+        assert_eq!(error(""), "no rom given");
+        assert_eq!(error("run"), "no rom given");
+        assert_eq!(error("info"), "info needs a rom");
+        assert_eq!(error("pong.ch8 --speed 3"), "unknown option --speed");
+        assert_eq!(error("pong.ch8 --scale"), "--scale needs a value");
+        assert_eq!(error("pong.ch8 --scale big"), "--scale: big isn't a valid number");
+        assert_eq!(error("pong.ch8 --scale 0"), "--scale should be from 1 to 32");
+        assert_eq!(error("pong.ch8 --ipf 0"), "--ipf should be at least 1");
+        assert!(error("pong.ch8 --sample-rate 1000").contains("from 8000 to 192000 Hz"));
+        assert!(error("pong.ch8 --wav out.wav --sample-rate 200000").contains("from 8000 to 192000 Hz"));
+        assert_eq!(error("pong.ch8 --rewind-kb 1048577"), "--rewind-kb should be from 0 to 1048576");
+        assert_eq!(error("pong.ch8 --rewind-kb 18014398509481984"), "--rewind-kb should be from 0 to 1048576");
+        assert_eq!(error("pong.ch8 --tone 10"), "--tone should be from 20 to 20000 Hz");
+        assert_eq!(error("pong.ch8 --volume 101"), "--volume should be a percentage from 0 to 100");
+        assert_eq!(error("pong.ch8 --seed 1 --seed 2"), "--seed is given more than once");
+        assert_eq!(error("pong.ch8 tetris.ch8"), "unexpected argument tetris.ch8, only one rom can be played");
+        assert!(error("pong.ch8 --platform nes").contains("nes isn't a platform"));
+        assert!(error("pong.ch8 --quirks fast").contains("fast isn't a quirk preset"));
+        assert!(error("pong.ch8 --colors 000000").contains("expected 2 or 4 colors"));
+        assert!(error("pong.ch8 --colors 000000,fffffg").contains("fffffg isn't a color"));
+        assert_eq!(error("pong.ch8 --fullscreen=yes"), "--fullscreen doesn't take a value");
+    }
+}
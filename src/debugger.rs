@@ -0,0 +1,679 @@
+use chip8::{decode_long, Chip8, Chip8Error, Instruction, MemoryAccess, RandomSource, PROGRAM_START};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use std::ops::Range;
+
+pub const HELP: &str = "\
+Commands:
+    break [<addr>]          Stop when the pc gets to an address or label, or list breakpoints
+    delete [<addr>]         Clear a breakpoint, or all of them
+    step [<count>]          Execute one instruction, or <count> of them
+    next                    Step, running a whole subroutine if the instruction calls one
+    finish                  Run until the current subroutine returns
+    continue                Run until a breakpoint
+    pause                   Stop running
+    regs                    Print the registers, timers and stack
+    x <addr> [<length>]     Dump memory, 40 bytes unless told otherwise
+    write <addr> <byte>...  Change memory
+    set <register> <value>  Change v0 to vf, i, pc, sp, dt or st
+    list [<lines>]          Disassemble around the pc, 8 lines either side unless told otherwise,
+                            up to 64
+    help                    Show this message
+    quit                    Close the debugger
+Addresses, values and lengths are hex, step counts are decimal. Labels from
+the disassembly, like L214, and from a symbol file can stand in for addresses.
+";
+
+// Bytes a memory dump shows unless told otherwise
+const DEFAULT_DUMP: u16 = 0x40;
+// Lines list shows either side of the pc unless told otherwise
+const DEFAULT_LIST: usize = 8;
+// The most lines list shows either side, about a screenful
+const MAX_LIST: usize = 64;
+
+/// A line typed at the debugger's prompt.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DebugCommand {
+    Break(Option<u16>),
+    Delete(Option<u16>),
+    Step(u32),
+    Next,
+    Finish,
+    Continue,
+    Pause,
+    Registers,
+    Dump { addr: u16, len: u16 },
+    Write { addr: u16, bytes: Vec<u8> },
+    Set { register: Register, value: u16 },
+    List(usize),
+    Help,
+    Quit,
+}
+
+/// Something `set` can change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+}
+
+/// Why the machine stopped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Stop {
+    /// The pc got to a breakpoint.
+    Breakpoint(u16),
+    /// An instruction touched memory watched from `addr`.
+    Watchpoint { kind: WatchKind, addr: u16 },
+    /// A `next` or `finish` got where it was going.
+    Reached,
+    Exited,
+    /// Nothing runs until a key is pressed, for `fx0a`.
+    Waiting,
+    Halted(Chip8Error),
+}
+
+/// What a watchpoint stops on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    Access,
+}
+
+// What ends a run started by continue, next or finish, other than a breakpoint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Until {
+    Breakpoint,
+    // The pc is back at `pc` with the stack as deep as `sp`, after a call
+    Return { pc: u16, sp: u8 },
+    // The stack is shallower than `sp`
+    Shallower { sp: u8 },
+}
+
+/// Breakpoints, labels and stepping for a paused machine.
+///
+/// The debugger runs the machine itself rather than through
+/// [`Chip8::run_frame`], so it can stop on any instruction. It still ticks
+/// the timers once every `instructions_per_frame` instructions, so a game
+/// stepped through sees the same timers as one that plays.
+pub struct Debugger {
+    symbols: HashMap<String, u16>,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<(WatchKind, Range<usize>)>,
+    running: Option<Until>,
+    resuming: bool, // Whether the next instruction runs even on a breakpoint, as it's the one stopped at
+    frame_instructions: u32, // Instructions run so far in the current frame
+}
+
+impl Debugger {
+    /// Starts out paused, knowing the labels the disassembly of `rom` gives
+    /// and any in `symbol_file`, as `nemulator asm --symbols` writes them.
+    pub fn new(rom: &[u8], symbol_file: Option<&str>) -> Result<Debugger, String> {
+        let mut symbols: HashMap<String, u16> = chip8::disassemble(rom, PROGRAM_START)
+            .into_iter()
+            .filter_map(|line| Some((line.label?, line.addr)))
+            .collect();
+        for (index, line) in symbol_file.unwrap_or("").lines().enumerate() {
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [] => {}
+                [name, addr] => {
+                    let addr = parse_hex(addr).map_err(|err| format!("line {}: {}", index + 1, err))?;
+                    symbols.insert(name.to_string(), addr);
+                }
+                _ => return Err(format!("line {}: expected a name and an address", index + 1)),
+            }
+        }
+        Ok(Debugger {
+            symbols,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            running: None,
+            resuming: false,
+            frame_instructions: 0,
+        })
+    }
+
+    /// Whether the machine is running until something stops it.
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Reads a command, looking up any labels in it.
+    pub fn parse(&self, line: &str) -> Result<DebugCommand, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return Err("type a command, or help to see them".to_string()),
+        };
+        let command = match (command, args) {
+            ("break" | "b", []) => DebugCommand::Break(None),
+            ("break" | "b", [addr]) => DebugCommand::Break(Some(self.addr(addr)?)),
+            ("delete" | "d", []) => DebugCommand::Delete(None),
+            ("delete" | "d", [addr]) => DebugCommand::Delete(Some(self.addr(addr)?)),
+            ("step" | "s", []) => DebugCommand::Step(1),
+            ("step" | "s", [count]) => match count.parse() {
+                Ok(count) if count > 0 => DebugCommand::Step(count),
+                _ => return Err(format!("{} isn't a number of steps", count)),
+            },
+            ("next" | "n", []) => DebugCommand::Next,
+            ("finish" | "f", []) => DebugCommand::Finish,
+            ("continue" | "c", []) => DebugCommand::Continue,
+            ("pause" | "p", []) => DebugCommand::Pause,
+            ("regs" | "r", []) => DebugCommand::Registers,
+            ("x", [addr]) => DebugCommand::Dump { addr: self.addr(addr)?, len: DEFAULT_DUMP },
+            ("x", [addr, len]) => DebugCommand::Dump { addr: self.addr(addr)?, len: parse_hex(len)? },
+            ("write" | "w", [addr, bytes @ ..]) if !bytes.is_empty() => DebugCommand::Write {
+                addr: self.addr(addr)?,
+                bytes: bytes.iter().map(|byte| parse_byte(byte)).collect::<Result<_, _>>()?,
+            },
+            ("set", [register, value]) => {
+                let register = parse_register(register)?;
+                let value = match register {
+                    Register::Pc | Register::I => self.addr(value)?,
+                    _ => parse_byte(value)? as u16,
+                };
+                DebugCommand::Set { register, value }
+            }
+            ("list" | "l", []) => DebugCommand::List(DEFAULT_LIST),
+            ("list" | "l", [lines]) => match lines.parse() {
+                Ok(lines) if lines <= MAX_LIST => DebugCommand::List(lines),
+                Ok(_) => return Err(format!("list shows at most {} lines either side of the pc", MAX_LIST)),
+                Err(_) => return Err(format!("{} isn't a number of lines", lines)),
+            },
+            ("help" | "h", []) => DebugCommand::Help,
+            ("quit" | "q", []) => DebugCommand::Quit,
+            ("break" | "b" | "delete" | "d" | "step" | "s" | "next" | "n" | "finish" | "f" | "continue" | "c" | "pause" | "p"
+            | "regs" | "r" | "x" | "write" | "w" | "set" | "list" | "l" | "help" | "h" | "quit" | "q", _) => {
+                return Err(format!("wrong arguments for {}, see help", command))
+            }
+            _ => return Err(format!("unknown command {}, see help", command)),
+        };
+        Ok(command)
+    }
+
+    /// Carries out `command`, returning what to print. Commands that run the
+    /// machine for a while only start it, and [`Debugger::run_frame`] says
+    /// when it stops.
+    pub fn execute<R: RandomSource>(&mut self, chip: &mut Chip8<R>, command: DebugCommand) -> String {
+        match command {
+            DebugCommand::Break(None) if self.breakpoints.is_empty() => "No breakpoints\n".to_string(),
+            DebugCommand::Break(None) => self.breakpoints.iter().map(|&addr| format!("{}\n", self.name(addr))).collect(),
+            DebugCommand::Break(Some(addr)) => {
+                self.breakpoints.insert(addr);
+                format!("Breakpoint at {}\n", self.name(addr))
+            }
+            DebugCommand::Delete(None) => {
+                self.breakpoints.clear();
+                "Cleared all breakpoints\n".to_string()
+            }
+            DebugCommand::Delete(Some(addr)) if self.clear_breakpoint(addr) => {
+                format!("Cleared the breakpoint at {}\n", self.name(addr))
+            }
+            DebugCommand::Delete(Some(addr)) => format!("No breakpoint at {}\n", self.name(addr)),
+            DebugCommand::Step(count) => {
+                self.running = None;
+                for _ in 0..count {
+                    if let Some(stop) = self.step(chip) {
+                        return self.report(chip, &stop);
+                    }
+                }
+                self.location(chip)
+            }
+            DebugCommand::Next => match self.instruction_at(chip, chip.pc()) {
+                Some(Instruction::Call { .. }) => {
+                    self.start(Until::Return { pc: chip.pc() + 2, sp: chip.sp() });
+                    String::new()
+                }
+                _ => self.execute(chip, DebugCommand::Step(1)),
+            },
+            DebugCommand::Finish if chip.sp() == 0 => "Not in a subroutine\n".to_string(),
+            DebugCommand::Finish => {
+                self.start(Until::Shallower { sp: chip.sp() });
+                String::new()
+            }
+            DebugCommand::Continue => {
+                self.resume();
+                String::new()
+            }
+            DebugCommand::Pause if self.pause() => format!("Paused\n{}", self.location(chip)),
+            DebugCommand::Pause => "Not running\n".to_string(),
+            DebugCommand::Registers => registers(chip),
+            DebugCommand::Dump { addr, len } => dump(chip.memory(), addr, len),
+            DebugCommand::Write { addr, bytes } => match chip.write_memory(addr, &bytes) {
+                Ok(()) => dump(chip.memory(), addr, bytes.len() as u16),
+                Err(_) => format!("{:03X} to {:03X} is past the end of memory\n", addr, addr as usize + bytes.len() - 1),
+            },
+            DebugCommand::Set { register, value } => {
+                match register {
+                    Register::V(x) => {
+                        if chip.set_v(x, value as u8).is_err() {
+                            return "There are only 16 registers, V0 to VF\n".to_string();
+                        }
+                    }
+                    Register::I => chip.set_index(value),
+                    Register::Pc => chip.set_pc(value),
+                    Register::Sp => {
+                        if chip.set_sp(value as u8).is_err() {
+                            return "The stack only holds 16 addresses\n".to_string();
+                        }
+                    }
+                    Register::Dt => chip.set_delay_timer(value as u8),
+                    Register::St => chip.set_sound_timer(value as u8),
+                }
+                registers(chip)
+            }
+            DebugCommand::List(lines) => self.listing(chip, lines),
+            DebugCommand::Help => HELP.to_string(),
+            DebugCommand::Quit => String::new(),
+        }
+    }
+
+    /// Describes why the machine stopped and where it is now.
+    pub fn report<R: RandomSource>(&self, chip: &Chip8<R>, stop: &Stop) -> String {
+        let why = match stop {
+            Stop::Breakpoint(addr) => format!("Breakpoint at {}\n", self.name(*addr)),
+            Stop::Watchpoint { kind, addr } => {
+                let access = match kind {
+                    WatchKind::Read => "read",
+                    WatchKind::Write => "written",
+                    WatchKind::Access => "accessed",
+                };
+                format!("Watched memory at {} {}\n", self.name(*addr), access)
+            }
+            Stop::Reached => String::new(),
+            Stop::Exited => "The program has exited\n".to_string(),
+            Stop::Waiting => "Waiting for a key\n".to_string(),
+            Stop::Halted(err) => format!("Halted: {}\n", err),
+        };
+        why + &self.location(chip)
+    }
+
+    /// Stops when the pc gets to `addr`.
+    pub fn set_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    /// Clears the breakpoint at `addr`, returning whether there was one.
+    pub fn clear_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    /// Stops after an instruction touches any of the `len` bytes at `addr`
+    /// the way `kind` says.
+    pub fn set_watchpoint(&mut self, kind: WatchKind, addr: u16, len: u16) {
+        self.watchpoints.push((kind, addr as usize..addr as usize + len as usize));
+    }
+
+    /// Clears a watchpoint set with the same arguments, returning whether
+    /// there was one.
+    pub fn clear_watchpoint(&mut self, kind: WatchKind, addr: u16, len: u16) -> bool {
+        let watched = (kind, addr as usize..addr as usize + len as usize);
+        let before = self.watchpoints.len();
+        self.watchpoints.retain(|watchpoint| *watchpoint != watched);
+        self.watchpoints.len() != before
+    }
+
+    /// Clears every breakpoint and watchpoint.
+    pub fn clear_all(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+    }
+
+    /// Runs until a breakpoint or watchpoint, starting with the instruction
+    /// the pc is on even if it has a breakpoint.
+    pub fn resume(&mut self) {
+        self.start(Until::Breakpoint);
+    }
+
+    /// Stops running, returning whether it was.
+    pub fn pause(&mut self) -> bool {
+        self.running.take().is_some()
+    }
+
+    /// Runs what's left of a frame while the machine is running, and says
+    /// why if it stopped. Call this at 60 Hz, like [`Chip8::run_frame`].
+    pub fn run_frame<R: RandomSource>(&mut self, chip: &mut Chip8<R>) -> Option<Stop> {
+        let until = self.running?;
+        // Nothing executes while waiting for a key, but the frame still goes by
+        while !chip.is_waiting() {
+            let pc = chip.pc();
+            let stop = match until {
+                Until::Return { pc: to, sp } if pc == to && chip.sp() == sp => Some(Stop::Reached),
+                Until::Shallower { sp } if chip.sp() < sp => Some(Stop::Reached),
+                _ if self.breakpoints.contains(&pc) && !self.resuming => Some(Stop::Breakpoint(pc)),
+                _ => self.step(chip),
+            };
+            if stop.is_some() {
+                self.running = None;
+                return stop;
+            }
+            if self.frame_instructions == 0 {
+                return None;
+            }
+        }
+        chip.tick_timers();
+        self.frame_instructions = 0;
+        None
+    }
+
+    /// Executes one instruction, ticking the timers at the end of a frame.
+    /// Says why if nothing could execute, or if the instruction touched
+    /// watched memory.
+    pub fn step<R: RandomSource>(&mut self, chip: &mut Chip8<R>) -> Option<Stop> {
+        if chip.has_exited() {
+            return Some(Stop::Exited);
+        }
+        if chip.is_waiting() {
+            return Some(Stop::Waiting);
+        }
+        let access = self.instruction_at(chip, chip.pc()).map(|instruction| chip.memory_access(instruction));
+        if let Err(err) = chip.cycle() {
+            return Some(Stop::Halted(err));
+        }
+        self.resuming = false;
+        self.frame_instructions += 1;
+        if self.frame_instructions >= chip.instructions_per_frame() {
+            chip.tick_timers();
+            self.frame_instructions = 0;
+        }
+        let MemoryAccess { reads, writes } = access?;
+        let overlaps = |range: &Range<usize>, watched: &Range<usize>| range.start < watched.end && watched.start < range.end;
+        self.watchpoints.iter().find_map(|(kind, watched)| {
+            let touched = match kind {
+                WatchKind::Read => overlaps(&reads, watched),
+                WatchKind::Write => overlaps(&writes, watched),
+                WatchKind::Access => overlaps(&reads, watched) || overlaps(&writes, watched),
+            };
+            Some(Stop::Watchpoint { kind: *kind, addr: watched.start as u16 }).filter(|_| touched)
+        })
+    }
+
+    fn start(&mut self, until: Until) {
+        self.running = Some(until);
+        self.resuming = true;
+    }
+
+    // An address, or the address of a label
+    fn addr(&self, word: &str) -> Result<u16, String> {
+        match self.symbols.get(word) {
+            Some(&addr) => Ok(addr),
+            None => parse_hex(word).map_err(|_| format!("{} isn't an address or a label", word)),
+        }
+    }
+
+    // An address with its label, if it has one
+    fn name(&self, addr: u16) -> String {
+        match self.labels(addr).first() {
+            Some(label) => format!("{:03X} ({})", addr, label),
+            None => format!("{:03X}", addr),
+        }
+    }
+
+    // The labels at `addr`, symbol file names sorting before generated ones
+    fn labels(&self, addr: u16) -> Vec<&str> {
+        let mut labels: Vec<&str> =
+            self.symbols.iter().filter(|&(_, &at)| at == addr).map(|(name, _)| name.as_str()).collect();
+        labels.sort_by_key(|&label| (label == format!("L{:03X}", addr), label));
+        labels
+    }
+
+    fn instruction_at<R: RandomSource>(&self, chip: &Chip8<R>, addr: u16) -> Option<Instruction> {
+        let word = |at: usize| Some((*chip.memory().get(at)? as u16) << 8 | *chip.memory().get(at + 1)? as u16);
+        decode_long(word(addr as usize)?, word(addr as usize + 2).unwrap_or(0)).ok()
+    }
+
+    // The instruction the pc is on
+    fn location<R: RandomSource>(&self, chip: &Chip8<R>) -> String {
+        self.listing(chip, 0)
+    }
+
+    // Disassembles `lines` lines either side of the pc, marking it with => and
+    // breakpoints with *
+    fn listing<R: RandomSource>(&self, chip: &Chip8<R>, lines: usize) -> String {
+        let pc = chip.pc();
+        let memory = chip.memory();
+        let window = |start: u16| {
+            let end = (pc as usize + 4 * (lines + 1)).min(memory.len());
+            chip8::disassemble(memory.get(start as usize..end).unwrap_or(&[]), start)
+        };
+        // Going back from the pc can land in the middle of a long instruction, in which case start at the pc
+        let mut disassembly = window((pc as usize).saturating_sub(4 * lines) as u16);
+        if !disassembly.iter().any(|line| line.addr == pc) {
+            disassembly = window(pc);
+        }
+        let at = disassembly.iter().position(|line| line.addr == pc).unwrap_or(0);
+
+        let mut out = String::new();
+        for line in disassembly.iter().skip(at.saturating_sub(lines)).take(lines * 2 + 1) {
+            for label in self.labels(line.addr) {
+                writeln!(out, ": {}", label).unwrap();
+            }
+            let marker = match (line.addr == pc, self.breakpoints.contains(&line.addr)) {
+                (true, _) => "=>",
+                (false, true) => " *",
+                (false, false) => "  ",
+            };
+            let bytes: Vec<String> = line.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            writeln!(out, "{} {:03X}  {:<11}  {}", marker, line.addr, bytes.join(" "), line.text).unwrap();
+        }
+        if disassembly.is_empty() {
+            writeln!(out, "=> {:03X}  past the end of memory", pc).unwrap();
+        }
+        out
+    }
+}
+
+// V0 to VF, I, the pc, timers and the stack
+fn registers<R: RandomSource>(chip: &Chip8<R>) -> String {
+    let mut out = String::new();
+    for (row, values) in chip.registers().chunks(8).enumerate() {
+        let cells: Vec<String> = values.iter().enumerate().map(|(x, v)| format!("V{:X} {:02X}", row * 8 + x, v)).collect();
+        writeln!(out, "{}", cells.join("  ")).unwrap();
+    }
+    writeln!(out, "I {:03X}  PC {:03X}  SP {}  DT {:02X}  ST {:02X}", chip.i(), chip.pc(), chip.sp(), chip.dt(), chip.st()).unwrap();
+    if chip.sp() > 0 {
+        let stack: Vec<String> = chip.stack()[..chip.sp() as usize].iter().map(|addr| format!("{:03X}", addr)).collect();
+        writeln!(out, "Stack {}", stack.join(" ")).unwrap();
+    }
+    out
+}
+
+// `len` bytes from `addr`, 16 to a row with the printable ones alongside
+fn dump(memory: &[u8], addr: u16, len: u16) -> String {
+    let start = addr as usize;
+    let end = (start + len as usize).min(memory.len());
+    if start >= end {
+        return format!("{:03X} is past the end of memory\n", addr);
+    }
+    let mut out = String::new();
+    for (row, bytes) in memory[start..end].chunks(16).enumerate() {
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let text: String = bytes.iter().map(|&byte| if byte.is_ascii_graphic() { byte as char } else { '.' }).collect();
+        writeln!(out, "{:03X}  {:<47}  {}", start + row * 16, hex.join(" "), text).unwrap();
+    }
+    out
+}
+
+fn parse_hex(word: &str) -> Result<u16, String> {
+    let digits = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")).unwrap_or(word);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("{} isn't a hex number", word))
+}
+
+fn parse_byte(word: &str) -> Result<u8, String> {
+    match parse_hex(word)? {
+        byte if byte <= 0xff => Ok(byte as u8),
+        _ => Err(format!("{} doesn't fit in a byte", word)),
+    }
+}
+
+fn parse_register(name: &str) -> Result<Register, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "i" => Ok(Register::I),
+        "pc" => Ok(Register::Pc),
+        "sp" => Ok(Register::Sp),
+        "dt" => Ok(Register::Dt),
+        "st" => Ok(Register::St),
+        register => match register.strip_prefix('v').map(|x| usize::from_str_radix(x, 16)) {
+            Some(Ok(x)) if x < 16 && register.len() == 2 => Ok(Register::V(x)),
+            _ => Err(format!("{} isn't a register, expected v0 to vf, i, pc, sp, dt or st", name)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Calls a subroutine that adds to v0, twice, then loops forever
+    const ROM: [u8; 12] = [
+        0x22, 0x08, // 200: :call L208
+        0x22, 0x08, // 202: :call L208
+        0x12, 0x04, // 204: jump L204
+        0x00, 0xe0, // 206: clear, never reached
+        0x70, 0x01, // 208: v0 += 1
+        0x00, 0xee, // 20A: return
+    ];
+
+    fn setup() -> (Debugger, Chip8) {
+        let mut chip = Chip8::new();
+        chip.load(&ROM);
+        chip.set_instructions_per_frame(4);
+        (Debugger::new(&ROM, Some("add_one 0x208\n")).unwrap(), chip)
+    }
+
+    fn run(debugger: &mut Debugger, chip: &mut Chip8, line: &str) -> String {
+        let command = debugger.parse(line).unwrap();
+        debugger.execute(chip, command)
+    }
+
+    // Runs frames until the debugger pauses
+    fn wait(debugger: &mut Debugger, chip: &mut Chip8) -> String {
+        for _ in 0..100 {
+            if let Some(stop) = debugger.run_frame(chip) {
+                return debugger.report(chip, &stop);
+            }
+        }
+        panic!("the debugger didn't stop");
+    }
+
+    #[test]
+    fn should_stop_at_breakpoints_by_address_or_label() {
+        let (mut debugger, mut chip) = setup();
+        run(&mut debugger, &mut chip, "break add_one");
+        run(&mut debugger, &mut chip, "b 204");
+        assert_eq!(run(&mut debugger, &mut chip, "break"), "204 (L204)\n208 (add_one)\n");
+
+        run(&mut debugger, &mut chip, "continue");
+        assert!(wait(&mut debugger, &mut chip).starts_with("Breakpoint at 208 (add_one)\n: add_one\n: L208\n=> 208"));
+        // Continuing from a breakpoint runs the instruction it's on
+        run(&mut debugger, &mut chip, "c");
+        assert!(wait(&mut debugger, &mut chip).starts_with("Breakpoint at 208"));
+        assert_eq!(chip.registers()[0], 1);
+
+        run(&mut debugger, &mut chip, "delete add_one");
+        run(&mut debugger, &mut chip, "c");
+        assert!(wait(&mut debugger, &mut chip).starts_with("Breakpoint at 204"));
+        assert_eq!(chip.registers()[0], 2);
+        assert!(!debugger.is_running());
+    }
+
+    #[test]
+    fn should_step_over_and_out_of_calls() {
+        let (mut debugger, mut chip) = setup();
+        assert!(run(&mut debugger, &mut chip, "step").starts_with(": add_one"));
+        assert_eq!((chip.pc(), chip.sp()), (0x208, 1));
+
+        run(&mut debugger, &mut chip, "finish");
+        assert!(wait(&mut debugger, &mut chip).contains("=> 202"));
+        assert_eq!((chip.pc(), chip.sp(), chip.registers()[0]), (0x202, 0, 1));
+
+        // next runs the whole call
+        run(&mut debugger, &mut chip, "next");
+        assert!(debugger.is_running());
+        assert!(wait(&mut debugger, &mut chip).contains("=> 204"));
+        assert_eq!(chip.registers()[0], 2);
+
+        // and is a plain step on anything else
+        run(&mut debugger, &mut chip, "next");
+        assert_eq!(chip.pc(), 0x204);
+        assert!(!debugger.is_running());
+        assert_eq!(run(&mut debugger, &mut chip, "finish"), "Not in a subroutine\n");
+    }
+
+    #[test]
+    fn should_tick_timers_once_a_frame_while_stepping() {
+        let (mut debugger, mut chip) = setup();
+        chip.set_delay_timer(10);
+        run(&mut debugger, &mut chip, "step 3");
+        assert_eq!(chip.dt(), 10);
+        run(&mut debugger, &mut chip, "step 5");
+        assert_eq!(chip.dt(), 8);
+    }
+
+    #[test]
+    fn should_show_and_change_registers_and_memory() {
+        let (mut debugger, mut chip) = setup();
+        run(&mut debugger, &mut chip, "set va 3f");
+        run(&mut debugger, &mut chip, "set i add_one");
+        let registers = run(&mut debugger, &mut chip, "set dt 0x10");
+        assert!(registers.contains("V8 00  V9 00  VA 3F"));
+        assert!(registers.contains("I 208  PC 200  SP 0  DT 10  ST 00\n"));
+
+        assert_eq!(run(&mut debugger, &mut chip, "x 200 6"), format!("200  22 08 22 08 12 04{}\".\"...\n", " ".repeat(32)));
+        assert_eq!(run(&mut debugger, &mut chip, "write 206 41 42"), format!("206  41 42{}  AB\n", " ".repeat(42)));
+        assert_eq!(chip.memory()[0x206..0x208], [0x41, 0x42]);
+        assert!(run(&mut debugger, &mut chip, "write fff 1 2").contains("past the end of memory"));
+        assert_eq!(run(&mut debugger, &mut chip, "set sp 11"), "The stack only holds 16 addresses\n");
+        assert_eq!(chip.sp(), 0);
+
+        // Moving the pc changes what runs next
+        run(&mut debugger, &mut chip, "set pc 208");
+        run(&mut debugger, &mut chip, "step");
+        assert_eq!(chip.registers()[0], 1);
+    }
+
+    #[test]
+    fn should_list_around_the_pc() {
+        let (mut debugger, mut chip) = setup();
+        run(&mut debugger, &mut chip, "b 208");
+        run(&mut debugger, &mut chip, "step 2");
+        let listing = run(&mut debugger, &mut chip, "list 2");
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "   206  00 E0        clear",
+                ": add_one",
+                ": L208",
+                " * 208  70 01        v0 += 0x01",
+                "=> 20A  00 EE        return",
+                "   20C  00           db 0x00",
+                "   20D  00           db 0x00",
+            ]
+        );
+
+        // As far as list goes either side, at either end of memory
+        run(&mut debugger, &mut chip, "set pc 0");
+        assert!(run(&mut debugger, &mut chip, "list 64").contains("=> 000"));
+        run(&mut debugger, &mut chip, "set pc ffe");
+        let listing = run(&mut debugger, &mut chip, "list 64");
+        assert!(listing.ends_with("=> FFE  00           db 0x00\n   FFF  00           db 0x00\n"));
+    }
+
+    #[test]
+    fn should_explain_bad_commands() {
+        let (debugger, _) = setup();
+        assert_eq!(debugger.parse("jump"), Err("unknown command jump, see help".to_string()));
+        assert_eq!(debugger.parse("step two"), Err("two isn't a number of steps".to_string()));
+        assert_eq!(debugger.parse("list 16384"), Err("list shows at most 64 lines either side of the pc".to_string()));
+        assert_eq!(debugger.parse("break nowhere"), Err("nowhere isn't an address or a label".to_string()));
+        assert_eq!(debugger.parse("set vg 1"), Err("vg isn't a register, expected v0 to vf, i, pc, sp, dt or st".to_string()));
+        assert_eq!(debugger.parse("set v0 100"), Err("100 doesn't fit in a byte".to_string()));
+        assert_eq!(debugger.parse("regs now"), Err("wrong arguments for regs, see help".to_string()));
+        assert!(matches!(Debugger::new(&ROM, Some("start")), Err(err) if err.contains("line 1")));
+    }
+}
@@ -0,0 +1,450 @@
+
+use crate::keymap;
+use std::collections::{HashMap, HashSet};
+use toml::Value;
+
+/// How far a stick has to be pushed, from 0 to 1, to press its hex key unless
+/// the config says otherwise.
+#[cfg(feature = "gamepad")]
+pub const DEFAULT_THRESHOLD: f32 = 0.5;
+
+/// A button on a controller, or a stick pushed one way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Control {
+    South,
+    East,
+    North,
+    West,
+    LeftTrigger,
+    LeftTrigger2,
+    RightTrigger,
+    RightTrigger2,
+    Select,
+    Start,
+    Mode,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+    LeftStickUp,
+    LeftStickDown,
+    LeftStickLeft,
+    LeftStickRight,
+    RightStickUp,
+    RightStickDown,
+    RightStickLeft,
+    RightStickRight,
+}
+
+// Every control by the name the config uses for it
+const CONTROLS: [(&str, Control); 25] = [
+    ("South", Control::South),
+    ("East", Control::East),
+    ("North", Control::North),
+    ("West", Control::West),
+    ("LeftTrigger", Control::LeftTrigger),
+    ("LeftTrigger2", Control::LeftTrigger2),
+    ("RightTrigger", Control::RightTrigger),
+    ("RightTrigger2", Control::RightTrigger2),
+    ("Select", Control::Select),
+    ("Start", Control::Start),
+    ("Mode", Control::Mode),
+    ("LeftThumb", Control::LeftThumb),
+    ("RightThumb", Control::RightThumb),
+    ("DPadUp", Control::DPadUp),
+    ("DPadDown", Control::DPadDown),
+    ("DPadLeft", Control::DPadLeft),
+    ("DPadRight", Control::DPadRight),
+    ("LeftStickUp", Control::LeftStickUp),
+    ("LeftStickDown", Control::LeftStickDown),
+    ("LeftStickLeft", Control::LeftStickLeft),
+    ("LeftStickRight", Control::LeftStickRight),
+    ("RightStickUp", Control::RightStickUp),
+    ("RightStickDown", Control::RightStickDown),
+    ("RightStickLeft", Control::RightStickLeft),
+    ("RightStickRight", Control::RightStickRight),
+];
+
+// Octo's layout: movement on 5, 7, 8 and 9 like WASD, with actions on 6 and 4
+const DEFAULT_BINDINGS: [(Control, usize); 10] = [
+    (Control::DPadUp, 0x5),
+    (Control::DPadLeft, 0x7),
+    (Control::DPadDown, 0x8),
+    (Control::DPadRight, 0x9),
+    (Control::LeftStickUp, 0x5),
+    (Control::LeftStickLeft, 0x7),
+    (Control::LeftStickDown, 0x8),
+    (Control::LeftStickRight, 0x9),
+    (Control::South, 0x6),
+    (Control::East, 0x4),
+];
+
+/// An analog axis, positive to the right and up.
+#[cfg(feature = "gamepad")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Axis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    // Some controllers report the d-pad as a pair of axes
+    DPadX,
+    DPadY,
+}
+
+#[cfg(feature = "gamepad")]
+impl Axis {
+    // The controls pushing the axis negative and positive press
+    fn controls(self) -> (Control, Control) {
+        match self {
+            Axis::LeftStickX => (Control::LeftStickLeft, Control::LeftStickRight),
+            Axis::LeftStickY => (Control::LeftStickDown, Control::LeftStickUp),
+            Axis::RightStickX => (Control::RightStickLeft, Control::RightStickRight),
+            Axis::RightStickY => (Control::RightStickDown, Control::RightStickUp),
+            Axis::DPadX => (Control::DPadLeft, Control::DPadRight),
+            Axis::DPadY => (Control::DPadDown, Control::DPadUp),
+        }
+    }
+}
+
+/// Which hex key each controller button or stick direction presses, and how
+/// far sticks have to be pushed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GamepadMap {
+    bindings: HashMap<Control, usize>,
+    // Only sticks, which only gilrs moves, have a threshold
+    #[cfg(feature = "gamepad")]
+    threshold: f32,
+}
+
+impl Default for GamepadMap {
+    /// The d-pad and left stick on 5, 7, 8 and 9, the bottom face button on 6
+    /// and the right one on 4.
+    fn default() -> GamepadMap {
+        GamepadMap {
+            bindings: DEFAULT_BINDINGS.iter().copied().collect(),
+            #[cfg(feature = "gamepad")]
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+}
+
+impl GamepadMap {
+    /// Reads a controller map from the same TOML config as the keymap. The
+    /// `[gamepad]` table maps hex keys to controls, replacing the defaults
+    /// for that hex key, and can set the stick `threshold`:
+    ///
+    /// ```toml
+    /// [gamepad]
+    /// threshold = 0.3
+    /// 6 = ["South", "RightTrigger"]
+    ///
+    /// [rom."pong.ch8".gamepad]
+    /// 1 = ["DPadUp", "LeftStickUp"]
+    /// 4 = ["DPadDown", "LeftStickDown"]
+    /// ```
+    ///
+    /// A `[rom."<file name>".gamepad]` table does the same for one rom, on
+    /// top of the `[gamepad]` table.
+    pub fn from_config(config: &str, rom: Option<&str>) -> Result<GamepadMap, String> {
+        let config: Value = config.parse().map_err(|err| format!("{}", err))?;
+        let mut map = GamepadMap::default();
+        if let Some(table) = config.get("gamepad") {
+            map.apply(table).map_err(|err| format!("[gamepad]: {}", err))?;
+        }
+        let overrides = rom.and_then(|rom| config.get("rom")?.get(rom)?.get("gamepad"));
+        if let (Some(table), Some(rom)) = (overrides, rom) {
+            map.apply(table).map_err(|err| format!("[rom.\"{}\".gamepad]: {}", rom, err))?;
+        }
+        Ok(map)
+    }
+
+    /// Replaces the controls that press `hex` with `controls`.
+    pub fn bind(&mut self, hex: usize, controls: &[Control]) {
+        self.bindings.retain(|_, bound| *bound != hex);
+        for &control in controls {
+            self.bindings.insert(control, hex);
+        }
+    }
+
+    /// The hex key `control` presses, if any.
+    pub fn hex_key(&self, control: Control) -> Option<usize> {
+        self.bindings.get(&control).copied()
+    }
+
+    fn apply(&mut self, table: &Value) -> Result<(), String> {
+        let table = table.as_table().ok_or("expected a table of hex keys")?;
+        for (name, value) in table {
+            if name == "threshold" {
+                // Checked either way, so a config works with and without the gamepad feature
+                #[cfg(feature = "gamepad")]
+                {
+                    self.threshold = parse_threshold(value)?;
+                }
+                #[cfg(not(feature = "gamepad"))]
+                parse_threshold(value)?;
+                continue;
+            }
+            let hex = keymap::parse_hex_key(name)?;
+            let names = keymap::names(value).map_err(|err| format!("the controls for {} {}", name, err))?;
+            let controls = names.into_iter().map(parse_control).collect::<Result<Vec<_>, _>>()?;
+            self.bind(hex, &controls);
+        }
+        Ok(())
+    }
+}
+
+// Above 0, since a centred stick would otherwise count as pushed
+fn parse_threshold(value: &Value) -> Result<f32, String> {
+    match value.as_float() {
+        Some(threshold) if threshold > 0.0 && threshold <= 1.0 => Ok(threshold as f32),
+        _ => Err("threshold should be a number above 0.0, up to 1.0".to_string()),
+    }
+}
+
+fn parse_control(name: &str) -> Result<Control, String> {
+    CONTROLS
+        .iter()
+        .find(|(control_name, _)| control_name.eq_ignore_ascii_case(name))
+        .map(|&(_, control)| control)
+        .ok_or(format!("{} isn't a controller button or stick direction", name))
+}
+
+/// What every connected controller is doing, fed by a [`GamepadBackend`].
+#[derive(Clone, Debug, Default)]
+pub struct GamepadState {
+    pressed: HashSet<(usize, Control)>, // Buttons held, by controller
+    #[cfg(feature = "gamepad")]
+    axes: HashMap<(usize, Axis), f32>, // Where each stick is, by controller
+}
+
+// Events only come from gilrs
+#[cfg(any(feature = "gamepad", test))]
+impl GamepadState {
+    pub fn press(&mut self, pad: usize, control: Control) {
+        self.pressed.insert((pad, control));
+    }
+
+    pub fn release(&mut self, pad: usize, control: Control) {
+        self.pressed.remove(&(pad, control));
+    }
+
+    #[cfg(feature = "gamepad")]
+    pub fn move_axis(&mut self, pad: usize, axis: Axis, value: f32) {
+        self.axes.insert((pad, axis), value);
+    }
+
+    /// Forgets a controller that was unplugged, so nothing it held stays
+    /// pressed.
+    pub fn disconnect(&mut self, pad: usize) {
+        self.pressed.retain(|&(held_by, _)| held_by != pad);
+        #[cfg(feature = "gamepad")]
+        self.axes.retain(|&(moved_on, _), _| moved_on != pad);
+    }
+}
+
+impl GamepadState {
+    /// The hex keys any controller is pressing, going by `map`.
+    pub fn keypad(&self, map: &GamepadMap) -> [bool; 16] {
+        let mut keypad = [false; 16];
+        for &(_, control) in &self.pressed {
+            if let Some(hex) = map.hex_key(control) {
+                keypad[hex] = true;
+            }
+        }
+        #[cfg(feature = "gamepad")]
+        for (&(_, axis), &value) in &self.axes {
+            let (negative, positive) = axis.controls();
+            let pushed = if value >= map.threshold {
+                positive
+            } else if value <= -map.threshold {
+                negative
+            } else {
+                continue;
+            };
+            if let Some(hex) = map.hex_key(pushed) {
+                keypad[hex] = true;
+            }
+        }
+        keypad
+    }
+}
+
+/// A source of controller events.
+pub trait GamepadBackend {
+    /// Applies every event since the last poll to `state`.
+    fn poll(&mut self, state: &mut GamepadState);
+}
+
+/// No controllers, for builds without the `gamepad` feature.
+pub struct NullGamepads;
+
+impl GamepadBackend for NullGamepads {
+    fn poll(&mut self, _state: &mut GamepadState) {}
+}
+
+/// Controllers read through gilrs, which picks them up as they're plugged in.
+#[cfg(feature = "gamepad")]
+pub struct GilrsGamepads {
+    gilrs: gilrs::Gilrs,
+}
+
+#[cfg(feature = "gamepad")]
+impl GilrsGamepads {
+    pub fn new() -> Result<GilrsGamepads, String> {
+        let gilrs = gilrs::Gilrs::new().map_err(|err| err.to_string())?;
+        Ok(GilrsGamepads { gilrs })
+    }
+}
+
+#[cfg(feature = "gamepad")]
+impl GamepadBackend for GilrsGamepads {
+    fn poll(&mut self, state: &mut GamepadState) {
+        use gilrs::EventType;
+
+        while let Some(gilrs::Event { id, event, .. }) = self.gilrs.next_event() {
+            let pad = usize::from(id);
+            match event {
+                EventType::ButtonPressed(button, _) => {
+                    if let Some(control) = gilrs_control(button) {
+                        state.press(pad, control);
+                    }
+                }
+                EventType::ButtonReleased(button, _) => {
+                    if let Some(control) = gilrs_control(button) {
+                        state.release(pad, control);
+                    }
+                }
+                EventType::AxisChanged(axis, value, _) => {
+                    if let Some(axis) = gilrs_axis(axis) {
+                        state.move_axis(pad, axis, value);
+                    }
+                }
+                EventType::Connected => eprintln!("Controller connected: {}", self.gilrs.gamepad(id).name()),
+                EventType::Disconnected => {
+                    eprintln!("Controller disconnected");
+                    state.disconnect(pad);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(feature = "gamepad")]
+fn gilrs_control(button: gilrs::Button) -> Option<Control> {
+    use gilrs::Button;
+
+    Some(match button {
+        Button::South => Control::South,
+        Button::East => Control::East,
+        Button::North => Control::North,
+        Button::West => Control::West,
+        Button::LeftTrigger => Control::LeftTrigger,
+        Button::LeftTrigger2 => Control::LeftTrigger2,
+        Button::RightTrigger => Control::RightTrigger,
+        Button::RightTrigger2 => Control::RightTrigger2,
+        Button::Select => Control::Select,
+        Button::Start => Control::Start,
+        Button::Mode => Control::Mode,
+        Button::LeftThumb => Control::LeftThumb,
+        Button::RightThumb => Control::RightThumb,
+        Button::DPadUp => Control::DPadUp,
+        Button::DPadDown => Control::DPadDown,
+        Button::DPadLeft => Control::DPadLeft,
+        Button::DPadRight => Control::DPadRight,
+        _ => return None,
+    })
+}
+
+#[cfg(feature = "gamepad")]
+fn gilrs_axis(axis: gilrs::Axis) -> Option<Axis> {
+    use gilrs::Axis as GilrsAxis;
+
+    Some(match axis {
+        GilrsAxis::LeftStickX => Axis::LeftStickX,
+        GilrsAxis::LeftStickY => Axis::LeftStickY,
+        GilrsAxis::RightStickX => Axis::RightStickX,
+        GilrsAxis::RightStickY => Axis::RightStickY,
+        GilrsAxis::DPadX => Axis::DPadX,
+        GilrsAxis::DPadY => Axis::DPadY,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_default_to_octo_layout() {
+        let map = GamepadMap::default();
+        let mut state = GamepadState::default();
+        state.press(0, Control::DPadUp);
+        state.press(0, Control::South);
+        let keypad = state.keypad(&map);
+        assert!(keypad[0x5] && keypad[0x6]);
+        assert_eq!(keypad.iter().filter(|&&pressed| pressed).count(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "gamepad")]
+    fn should_press_keys_past_stick_threshold() {
+        let map = GamepadMap::from_config("[gamepad]\nthreshold = 0.4", None).unwrap();
+        let mut state = GamepadState::default();
+        state.move_axis(0, Axis::LeftStickX, 0.3);
+        assert!(!state.keypad(&map)[0x9]);
+        state.move_axis(0, Axis::LeftStickX, 0.45);
+        assert!(state.keypad(&map)[0x9]);
+        state.move_axis(0, Axis::LeftStickX, -0.9);
+        assert!(state.keypad(&map)[0x7] && !state.keypad(&map)[0x9]);
+        state.move_axis(0, Axis::LeftStickY, 1.0);
+        assert!(state.keypad(&map)[0x5]);
+        // A d-pad reported as axes works like the buttons
+        state.move_axis(1, Axis::DPadY, -1.0);
+        assert!(state.keypad(&map)[0x8]);
+    }
+
+    #[test]
+    fn should_apply_rom_overrides() {
+        let config = "[gamepad]\n6 = [\"South\", \"RightTrigger\"]\n\n\
+                      [rom.\"pong.ch8\".gamepad]\nthreshold = 0.8\n1 = [\"DPadUp\", \"leftstickup\"]\n";
+        let pong = GamepadMap::from_config(config, Some("pong.ch8")).unwrap();
+        assert_eq!(pong.hex_key(Control::DPadUp), Some(0x1));
+        assert_eq!(pong.hex_key(Control::LeftStickUp), Some(0x1));
+        assert_eq!(pong.hex_key(Control::RightTrigger), Some(0x6));
+        #[cfg(feature = "gamepad")]
+        assert_eq!(pong.threshold, 0.8);
+
+        let other = GamepadMap::from_config(config, Some("astro.ch8")).unwrap();
+        assert_eq!(other.hex_key(Control::DPadUp), Some(0x5));
+        #[cfg(feature = "gamepad")]
+        assert_eq!(other.threshold, DEFAULT_THRESHOLD);
+    }
+
+    #[test]
+    fn should_release_everything_on_disconnect() {
+        let map = GamepadMap::default();
+        let mut state = GamepadState::default();
+        state.press(0, Control::South);
+        #[cfg(feature = "gamepad")]
+        state.move_axis(0, Axis::LeftStickX, 1.0);
+        state.press(1, Control::East);
+        state.disconnect(0);
+        let keypad = state.keypad(&map);
+        assert!(!keypad[0x6] && !keypad[0x9]);
+        assert!(keypad[0x4]);
+        state.release(1, Control::East);
+        assert_eq!(state.keypad(&map), [false; 16]);
+    }
+
+    #[test]
+    fn should_report_config_errors() {
+        assert!(GamepadMap::from_config("[gamepad]\n1 = \"Jump\"", None).unwrap_err().contains("Jump"));
+        assert!(GamepadMap::from_config("[gamepad]\nthreshold = 2.0", None).is_err());
+        assert!(GamepadMap::from_config("[gamepad]\nthreshold = 0.0", None).unwrap_err().contains("above 0.0"));
+        assert!(GamepadMap::from_config("[gamepad]\nx = \"South\"", None).is_err());
+    }
+}
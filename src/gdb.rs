@@ -0,0 +1,694 @@
+use crate::debugger::{Debugger, Stop, WatchKind};
+use chip8::{Chip8, Chip8Error, RandomSource};
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+
+// The registers in the order `g` sends them and gdb numbers them: V0 to VF,
+// then I, PC and SP
+const REGISTERS: usize = 19;
+const I: usize = 16;
+const PC: usize = 17;
+const SP: usize = 18;
+
+// Longest packet the stub takes, as qSupported tells the client, and which
+// replies keep to as well
+const PACKET_SIZE: usize = 0x1000;
+// Most bytes an m reply holds, sent as two hex digits each between $ and #xx
+const MAX_READ: usize = (PACKET_SIZE - 4) / 2;
+
+/// Speaks the GDB remote serial protocol for a [`Debugger`], without doing
+/// any IO itself. Bytes from the client go into [`GdbStub::receive`], and
+/// whatever it and [`GdbStub::run_frame`] return go back.
+///
+/// There's no CHIP-8 architecture to name, so the stub only describes its
+/// registers with a target description: V0 to VF, then the 16-bit I and PC
+/// and the 8-bit SP, sent little-endian. That suits RSP clients that go by
+/// the register list; stock gdb, which wants an architecture it knows, isn't
+/// supported.
+pub struct GdbStub {
+    debugger: Debugger,
+    input: Vec<u8>,
+    last_reply: Vec<u8>,
+    acks: bool,
+    swbreak: bool,
+    killed: bool,
+}
+
+impl GdbStub {
+    pub fn new(debugger: Debugger) -> GdbStub {
+        GdbStub { debugger, input: Vec::new(), last_reply: Vec::new(), acks: true, swbreak: false, killed: false }
+    }
+
+    /// Starts over for a new client, which begins with acknowledgements on.
+    pub fn connect(&mut self) {
+        self.input.clear();
+        self.last_reply.clear();
+        self.acks = true;
+        self.swbreak = false;
+    }
+
+    /// Lets the program carry on without the client, as detaching does,
+    /// clearing the client's breakpoints and watchpoints so none of them
+    /// stop it again.
+    pub fn disconnect(&mut self) {
+        self.debugger.clear_all();
+        self.debugger.resume();
+    }
+
+    /// Whether the machine is running, rather than stopped for the client.
+    pub fn is_running(&self) -> bool {
+        self.debugger.is_running()
+    }
+
+    /// Whether the client asked to kill the program, which closes the
+    /// emulator.
+    pub fn is_killed(&self) -> bool {
+        self.killed
+    }
+
+    /// Handles bytes from the client, returning what to send back. Packets
+    /// can arrive split across calls.
+    pub fn receive<R: RandomSource>(&mut self, chip: &mut Chip8<R>, bytes: &[u8]) -> Vec<u8> {
+        self.input.extend_from_slice(bytes);
+        let mut out = Vec::new();
+        while let Some(&first) = self.input.first() {
+            match first {
+                b'$' => {
+                    let end = match self.input.iter().position(|&byte| byte == b'#') {
+                        Some(end) if self.input.len() >= end + 3 => end,
+                        // The rest of the packet hasn't arrived yet
+                        _ => break,
+                    };
+                    let packet: Vec<u8> = self.input.drain(..end + 3).collect();
+                    let data = &packet[1..end];
+                    let sum = std::str::from_utf8(&packet[end + 1..]).ok().and_then(|sum| u8::from_str_radix(sum, 16).ok());
+                    if sum != Some(checksum(data)) {
+                        if self.acks {
+                            out.push(b'-');
+                        }
+                        continue;
+                    }
+                    if self.acks {
+                        out.push(b'+');
+                    }
+                    let data = String::from_utf8_lossy(data).into_owned();
+                    if let Some(reply) = self.handle(chip, &data) {
+                        self.reply(&mut out, &reply);
+                    }
+                }
+                // The client didn't get the last reply intact
+                b'-' => {
+                    self.input.remove(0);
+                    out.extend_from_slice(&self.last_reply);
+                }
+                // Ctrl-C in gdb
+                0x03 => {
+                    self.input.remove(0);
+                    self.debugger.pause();
+                    self.reply(&mut out, "S02");
+                }
+                // Acknowledgements, and anything else between packets
+                _ => {
+                    self.input.remove(0);
+                }
+            }
+        }
+        out
+    }
+
+    /// Runs what's left of a frame while the machine is running, returning
+    /// a stop reply for the client if it stopped. Call this at 60 Hz.
+    pub fn run_frame<R: RandomSource>(&mut self, chip: &mut Chip8<R>) -> Vec<u8> {
+        let mut out = Vec::new();
+        if let Some(stop) = self.debugger.run_frame(chip) {
+            let reply = self.stop_reply(Some(&stop));
+            self.reply(&mut out, &reply);
+        }
+        out
+    }
+
+    fn reply(&mut self, out: &mut Vec<u8>, data: &str) {
+        self.last_reply = packet(data);
+        out.extend_from_slice(&self.last_reply);
+    }
+
+    // The reply to a packet, or None if it doesn't get one yet
+    fn handle<R: RandomSource>(&mut self, chip: &mut Chip8<R>, packet: &str) -> Option<String> {
+        let mut chars = packet.chars();
+        let command = chars.next();
+        let args = chars.as_str();
+        let reply = match command {
+            Some('?') if chip.has_exited() => "W00".to_string(),
+            Some('?') => "S05".to_string(),
+            Some('g') => hex(&registers(chip)),
+            Some('G') => ok(parse_bytes(args).and_then(|bytes| set_registers(chip, &bytes))),
+            Some('p') => usize::from_str_radix(args, 16)
+                .ok()
+                .and_then(|n| registers(chip).get(register_bytes(n)?).map(hex))
+                .unwrap_or_else(error),
+            Some('P') => ok(set_register(chip, args)),
+            Some('m') => read_memory(chip, args).unwrap_or_else(error),
+            Some('M') => ok(write_memory(chip, args)),
+            Some('c') => {
+                if !jump(chip, args) {
+                    return Some(error());
+                }
+                self.debugger.resume();
+                return None;
+            }
+            Some('s') => {
+                if !jump(chip, args) {
+                    return Some(error());
+                }
+                let stop = self.debugger.step(chip);
+                self.stop_reply(stop.as_ref())
+            }
+            Some('Z') => self.watch(args, true),
+            Some('z') => self.watch(args, false),
+            Some('q') => self.query(packet),
+            Some('Q') if packet == "QStartNoAckMode" => {
+                self.acks = false;
+                "OK".to_string()
+            }
+            Some('H') | Some('T') => "OK".to_string(),
+            // Detaching leaves the program running without the client
+            Some('D') => {
+                self.disconnect();
+                "OK".to_string()
+            }
+            Some('k') => {
+                self.killed = true;
+                return None;
+            }
+            // An empty reply tells the client a packet isn't supported
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    // Z and z, setting and clearing breakpoints and watchpoints
+    fn watch(&mut self, args: &str, insert: bool) -> String {
+        let mut fields = args.split(',');
+        let kind = fields.next();
+        let addr = fields.next().and_then(|addr| u16::from_str_radix(addr, 16).ok());
+        let len = fields.next().and_then(|len| u16::from_str_radix(len, 16).ok());
+        let watch = match kind {
+            Some("2") => WatchKind::Write,
+            Some("3") => WatchKind::Read,
+            Some("4") => WatchKind::Access,
+            // Software and hardware breakpoints are the same thing here
+            Some("0") | Some("1") => {
+                match addr {
+                    Some(addr) if insert => self.debugger.set_breakpoint(addr),
+                    Some(addr) => {
+                        self.debugger.clear_breakpoint(addr);
+                    }
+                    None => return error(),
+                }
+                return "OK".to_string();
+            }
+            _ => return String::new(),
+        };
+        match (addr, len) {
+            (Some(addr), Some(len)) if insert => self.debugger.set_watchpoint(watch, addr, len),
+            (Some(addr), Some(len)) => {
+                self.debugger.clear_watchpoint(watch, addr, len);
+            }
+            _ => return error(),
+        }
+        "OK".to_string()
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if let Some(features) = packet.strip_prefix("qSupported") {
+            self.swbreak = features.split([':', ';']).any(|feature| feature == "swbreak+");
+            format!("PacketSize={:x};QStartNoAckMode+;qXfer:features:read+;swbreak+;hwbreak+", PACKET_SIZE)
+        } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let description = target_description();
+            let chunk = range.split_once(',').and_then(|(offset, len)| {
+                let offset = usize::from_str_radix(offset, 16).ok()?;
+                let len = usize::from_str_radix(len, 16).ok()?;
+                let end = offset.saturating_add(len).min(description.len());
+                Some(description.get(offset.min(description.len())..end)?.to_string())
+            });
+            match chunk {
+                Some(chunk) if chunk.is_empty() => "l".to_string(),
+                Some(chunk) => format!("m{}", chunk),
+                None => error(),
+            }
+        } else if packet == "qAttached" {
+            // The emulator was already running when the client connected
+            "1".to_string()
+        } else {
+            String::new()
+        }
+    }
+
+    // Why the machine stopped, as gdb wants to hear it
+    fn stop_reply(&self, stop: Option<&Stop>) -> String {
+        match stop {
+            Some(Stop::Breakpoint(_)) if self.swbreak => "T05swbreak:;".to_string(),
+            Some(Stop::Watchpoint { kind, addr }) => {
+                let name = match kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Access => "awatch",
+                };
+                format!("T05{}:{:x};", name, addr)
+            }
+            Some(Stop::Exited) => "W00".to_string(),
+            // SIGILL
+            Some(Stop::Halted(Chip8Error::UnknownOpcode { .. })) => "S04".to_string(),
+            // SIGSEGV, for the stack and memory
+            Some(Stop::Halted(_)) => "S0b".to_string(),
+            // SIGTRAP
+            _ => "S05".to_string(),
+        }
+    }
+}
+
+/// Listens for a gdb client on a localhost port and drives a [`GdbStub`]
+/// for it. The socket is read on a thread of its own, so
+/// [`GdbServer::poll`] never blocks the window.
+pub struct GdbServer {
+    stub: GdbStub,
+    port: u16,
+    events: mpsc::Receiver<Event>,
+    client: Option<TcpStream>,
+}
+
+enum Event {
+    Connected(TcpStream),
+    Received(Vec<u8>),
+    Disconnected,
+}
+
+impl GdbServer {
+    /// Listens on `port`, or any free port for 0. Clients connect one at a
+    /// time.
+    pub fn listen(port: u16, debugger: Debugger) -> io::Result<GdbServer> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        let port = listener.local_addr()?.port();
+        let (send, events) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let writer = match stream.try_clone() {
+                    Ok(writer) => writer,
+                    Err(_) => continue,
+                };
+                if send.send(Event::Connected(writer)).is_err() {
+                    return;
+                }
+                let mut buffer = [0; 4096];
+                loop {
+                    let event = match stream.read(&mut buffer) {
+                        Ok(0) | Err(_) => Event::Disconnected,
+                        Ok(len) => Event::Received(buffer[..len].to_vec()),
+                    };
+                    let disconnected = matches!(event, Event::Disconnected);
+                    if send.send(event).is_err() {
+                        return;
+                    }
+                    if disconnected {
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(GdbServer { stub: GdbStub::new(debugger), port, events, client: None })
+    }
+
+    /// The port clients connect to.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Whether the machine is running, rather than stopped for the client.
+    pub fn is_running(&self) -> bool {
+        self.stub.is_running()
+    }
+
+    /// Whether the client killed the program.
+    pub fn is_killed(&self) -> bool {
+        self.stub.is_killed()
+    }
+
+    /// Handles whatever the client has sent since the last call.
+    pub fn poll<R: RandomSource>(&mut self, chip: &mut Chip8<R>) {
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                Event::Connected(client) => {
+                    self.stub.connect();
+                    self.client = Some(client);
+                }
+                Event::Received(bytes) => {
+                    let reply = self.stub.receive(chip, &bytes);
+                    self.send(&reply);
+                }
+                Event::Disconnected => {
+                    self.stub.disconnect();
+                    self.client = None;
+                }
+            }
+        }
+    }
+
+    /// Runs what's left of a frame while the machine is running, telling
+    /// the client if it stopped. Call this at 60 Hz.
+    pub fn run_frame<R: RandomSource>(&mut self, chip: &mut Chip8<R>) {
+        let reply = self.stub.run_frame(chip);
+        self.send(&reply);
+    }
+
+    fn send(&mut self, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        if let Some(client) = &mut self.client {
+            if client.write_all(bytes).is_err() {
+                self.client = None;
+            }
+        }
+    }
+}
+
+// Frames a reply as $data#checksum, escaping the bytes that mean something
+fn packet(data: &str) -> Vec<u8> {
+    let mut out = vec![b'$'];
+    for &byte in data.as_bytes() {
+        if matches!(byte, b'$' | b'#' | b'}' | b'*') {
+            out.extend_from_slice(&[b'}', byte ^ 0x20]);
+        } else {
+            out.push(byte);
+        }
+    }
+    let sum = checksum(&out[1..]);
+    out.extend_from_slice(format!("#{:02x}", sum).as_bytes());
+    out
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum: u8, &byte| sum.wrapping_add(byte))
+}
+
+fn error() -> String {
+    "E01".to_string()
+}
+
+fn ok(done: Option<()>) -> String {
+    done.map_or_else(error, |_| "OK".to_string())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_bytes(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect()
+}
+
+// What g sends
+fn registers<R: RandomSource>(chip: &Chip8<R>) -> Vec<u8> {
+    let mut bytes = chip.registers().to_vec();
+    bytes.extend_from_slice(&chip.i().to_le_bytes());
+    bytes.extend_from_slice(&chip.pc().to_le_bytes());
+    bytes.push(chip.sp());
+    bytes
+}
+
+// Where register `n` is in what g sends
+fn register_bytes(n: usize) -> Option<std::ops::Range<usize>> {
+    match n {
+        0..=15 => Some(n..n + 1),
+        I | PC => Some(16 + (n - I) * 2..18 + (n - I) * 2),
+        SP => Some(20..21),
+        _ => None,
+    }
+}
+
+fn set_registers<R: RandomSource>(chip: &mut Chip8<R>, bytes: &[u8]) -> Option<()> {
+    if bytes.len() != registers(chip).len() {
+        return None;
+    }
+    (0..REGISTERS).try_for_each(|n| write_register(chip, n, &bytes[register_bytes(n)?]))
+}
+
+// P, as n=value
+fn set_register<R: RandomSource>(chip: &mut Chip8<R>, args: &str) -> Option<()> {
+    let (n, value) = args.split_once('=')?;
+    let n = usize::from_str_radix(n, 16).ok()?;
+    write_register(chip, n, &parse_bytes(value)?)
+}
+
+fn write_register<R: RandomSource>(chip: &mut Chip8<R>, n: usize, value: &[u8]) -> Option<()> {
+    if value.len() != register_bytes(n)?.len() {
+        return None;
+    }
+    match n {
+        0..=15 => return chip.set_v(n, value[0]).ok(),
+        I => chip.set_index(u16::from_le_bytes([value[0], value[1]])),
+        PC => chip.set_pc(u16::from_le_bytes([value[0], value[1]])),
+        _ => return chip.set_sp(value[0]).ok(),
+    }
+    Some(())
+}
+
+// m, as addr,len, replying with fewer bytes than asked for if they wouldn't
+// fit in a packet
+fn read_memory<R: RandomSource>(chip: &Chip8<R>, args: &str) -> Option<String> {
+    let (addr, len) = args.split_once(',')?;
+    let addr = usize::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?.min(MAX_READ);
+    chip.memory().get(addr..addr.checked_add(len)?).map(hex)
+}
+
+// M, as addr,len:bytes
+fn write_memory<R: RandomSource>(chip: &mut Chip8<R>, args: &str) -> Option<()> {
+    let (range, bytes) = args.split_once(':')?;
+    let (addr, len) = range.split_once(',')?;
+    let addr = u16::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+    let bytes = parse_bytes(bytes)?;
+    if bytes.len() != len {
+        return None;
+    }
+    chip.write_memory(addr, &bytes).ok()
+}
+
+// The optional address c and s carry on from, returning whether it was valid
+fn jump<R: RandomSource>(chip: &mut Chip8<R>, addr: &str) -> bool {
+    if addr.is_empty() {
+        return true;
+    }
+    match u16::from_str_radix(addr, 16) {
+        Ok(addr) => {
+            chip.set_pc(addr);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+// The registers as gdb numbers them, in the target description it reads
+fn target_description() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n<target version=\"1.0\">\n<feature name=\"org.nemulator.chip8\">\n",
+    );
+    for x in 0..16 {
+        writeln!(xml, "<reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\" regnum=\"{}\"/>", x, x).unwrap();
+    }
+    writeln!(xml, "<reg name=\"i\" bitsize=\"16\" type=\"uint16\" regnum=\"{}\"/>", I).unwrap();
+    writeln!(xml, "<reg name=\"pc\" bitsize=\"16\" type=\"uint16\" regnum=\"{}\"/>", PC).unwrap();
+    writeln!(xml, "<reg name=\"sp\" bitsize=\"8\" type=\"uint8\" regnum=\"{}\"/>", SP).unwrap();
+    xml.push_str("</feature>\n</target>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::PROGRAM_START;
+    use std::io::BufReader;
+
+    // Calls 208 twice then jumps to itself, with 208 adding one to v0 and
+    // storing it at 300
+    const ROM: [u8; 16] = [0x22, 0x08, 0x22, 0x08, 0x12, 0x04, 0x00, 0xe0, 0x70, 0x01, 0xa3, 0x00, 0xf0, 0x55, 0x00, 0xee];
+
+    fn machine() -> Chip8 {
+        let mut chip = Chip8::new();
+        chip.load(&ROM);
+        chip
+    }
+
+    fn stub() -> GdbStub {
+        GdbStub::new(Debugger::new(&ROM, None).unwrap())
+    }
+
+    // The packet's data, checking it's framed correctly
+    fn unframe(reply: &[u8]) -> String {
+        let reply = std::str::from_utf8(reply).unwrap();
+        let (data, sum) = reply.strip_prefix('$').unwrap().split_once('#').unwrap();
+        assert_eq!(u8::from_str_radix(sum, 16).unwrap(), checksum(data.as_bytes()));
+        data.to_string()
+    }
+
+    fn request(stub: &mut GdbStub, chip: &mut Chip8, data: &str) -> String {
+        let reply = stub.receive(chip, &packet(data));
+        unframe(reply.strip_prefix(b"+").expect("the packet should be acknowledged"))
+    }
+
+    #[test]
+    fn should_frame_packets() {
+        assert_eq!(packet("OK"), b"$OK#9a");
+        assert_eq!(packet(""), b"$#00");
+        assert_eq!(packet("a}b"), b"$a}]b#9d".to_vec());
+
+        let (mut stub, mut chip) = (stub(), machine());
+        // Split across reads, with a bad checksum asking for it again
+        assert_eq!(stub.receive(&mut chip, b"$?#"), b"");
+        assert_eq!(stub.receive(&mut chip, b"3f"), b"+$S05#b8");
+        assert_eq!(stub.receive(&mut chip, b"$?#00"), b"-");
+        assert_eq!(stub.receive(&mut chip, b"-"), b"$S05#b8");
+
+        assert_eq!(request(&mut stub, &mut chip, "QStartNoAckMode"), "OK");
+        assert_eq!(stub.receive(&mut chip, &packet("?")), b"$S05#b8");
+        assert_eq!(unframe(&stub.receive(&mut chip, &packet("vMustReplyEmpty"))), "");
+    }
+
+    #[test]
+    fn should_read_and_write_registers_and_memory() {
+        let (mut stub, mut chip) = (stub(), machine());
+        chip.set_v(0xa, 0x12).unwrap();
+        chip.set_index(0x345);
+        let registers = request(&mut stub, &mut chip, "g");
+        assert_eq!(registers, format!("{}12{}4503000200", "00".repeat(10), "00".repeat(5)));
+        assert_eq!(request(&mut stub, &mut chip, "p11"), "0002");
+
+        assert_eq!(request(&mut stub, &mut chip, "Pf=ff"), "OK");
+        assert_eq!(request(&mut stub, &mut chip, "P11=0602"), "OK");
+        assert_eq!(chip.registers()[0xf], 0xff);
+        assert_eq!(chip.pc(), 0x206);
+        assert_eq!(request(&mut stub, &mut chip, "P12=11"), "E01");
+        assert_eq!(request(&mut stub, &mut chip, "G00"), "E01");
+        assert_eq!(request(&mut stub, &mut chip, &format!("G{}", "01".repeat(16) + "0003" + "0002" + "01")), "OK");
+        assert_eq!((chip.registers()[0], chip.i(), chip.pc(), chip.sp()), (1, 0x300, 0x200, 1));
+
+        assert_eq!(request(&mut stub, &mut chip, "m200,4"), "22082208");
+        assert_eq!(request(&mut stub, &mut chip, "M300,2:abcd"), "OK");
+        assert_eq!(&chip.memory()[0x300..0x302], &[0xab, 0xcd]);
+        assert_eq!(request(&mut stub, &mut chip, "mfff,2"), "E01");
+        assert_eq!(request(&mut stub, &mut chip, "m0,1000").len(), 2 * MAX_READ);
+        assert_eq!(request(&mut stub, &mut chip, "Mfff,2:0000"), "E01");
+    }
+
+    #[test]
+    fn should_describe_the_registers() {
+        let (mut stub, mut chip) = (stub(), machine());
+        let features = request(&mut stub, &mut chip, "qSupported:multiprocess+;swbreak+");
+        assert!(features.contains("qXfer:features:read+"));
+
+        let mut description = String::new();
+        loop {
+            let chunk = request(&mut stub, &mut chip, &format!("qXfer:features:read:target.xml:{:x},40", description.len()));
+            description.push_str(&chunk[1..]);
+            if chunk.starts_with('l') {
+                break;
+            }
+        }
+        assert_eq!(description, target_description());
+        assert_eq!(request(&mut stub, &mut chip, "qXfer:features:read:target.xml:ffffffffffffffff,40"), "l");
+        assert!(description.contains("<reg name=\"pc\" bitsize=\"16\" type=\"uint16\" regnum=\"17\"/>"));
+    }
+
+    #[test]
+    fn should_stop_at_breakpoints_and_watchpoints() {
+        let (mut stub, mut chip) = (stub(), machine());
+        request(&mut stub, &mut chip, "qSupported:swbreak+");
+        assert_eq!(request(&mut stub, &mut chip, "Z0,208,2"), "OK");
+        assert_eq!(request(&mut stub, &mut chip, "s"), "S05");
+        assert_eq!(chip.pc(), 0x208);
+
+        // Continuing doesn't reply until the machine stops
+        assert_eq!(stub.receive(&mut chip, &packet("c")), b"+");
+        assert!(stub.is_running());
+        assert_eq!(unframe(&stub.run_frame(&mut chip)), "T05swbreak:;");
+        assert_eq!(chip.pc(), 0x208);
+        assert_eq!(chip.registers()[0], 1);
+
+        assert_eq!(request(&mut stub, &mut chip, "z0,208,2"), "OK");
+        assert_eq!(request(&mut stub, &mut chip, "Z2,300,1"), "OK");
+        stub.receive(&mut chip, &packet("c"));
+        assert_eq!(unframe(&stub.run_frame(&mut chip)), "T05watch:300;");
+        assert_eq!(chip.pc(), 0x20e);
+        assert_eq!(chip.memory()[0x300], 2);
+
+        // Interrupted while running
+        assert_eq!(request(&mut stub, &mut chip, "z2,300,1"), "OK");
+        stub.receive(&mut chip, &packet("c"));
+        assert_eq!(unframe(&stub.receive(&mut chip, &[0x03])), "S02");
+        assert!(!stub.is_running());
+    }
+
+    #[test]
+    fn should_forget_breakpoints_and_watchpoints_on_detach() {
+        let (mut stub, mut chip) = (stub(), machine());
+        assert_eq!(request(&mut stub, &mut chip, "Z0,208,2"), "OK");
+        assert_eq!(request(&mut stub, &mut chip, "Z2,300,1"), "OK");
+        assert_eq!(request(&mut stub, &mut chip, "D"), "OK");
+        assert!(stub.is_running());
+
+        // Both calls to 208 run, storing at 300, without stopping
+        assert_eq!(stub.run_frame(&mut chip), b"");
+        assert!(stub.is_running());
+        assert_eq!(chip.registers()[0], 2);
+        assert_eq!(chip.memory()[0x300], 2);
+    }
+
+    #[test]
+    fn should_serve_a_client_over_tcp() {
+        let server = GdbServer::listen(0, Debugger::new(&ROM, None).unwrap()).unwrap();
+        let port = server.port();
+        let emulator = thread::spawn(move || {
+            let (mut server, mut chip) = (server, machine());
+            while !server.is_killed() {
+                server.poll(&mut chip);
+                server.run_frame(&mut chip);
+                thread::sleep(std::time::Duration::from_millis(1));
+            }
+            chip
+        });
+
+        let mut client = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        let mut replies = BufReader::new(client.try_clone().unwrap());
+        let mut request = |data: &str| {
+            client.write_all(&packet(data)).unwrap();
+            let mut reply = Vec::new();
+            io::BufRead::read_until(&mut replies, b'#', &mut reply).unwrap();
+            let mut sum = [0; 2];
+            replies.read_exact(&mut sum).unwrap();
+            reply.extend_from_slice(&sum);
+            unframe(reply.strip_prefix(b"+").unwrap())
+        };
+        assert!(request("qSupported:swbreak+").contains("PacketSize"));
+        assert_eq!(request("?"), "S05");
+        assert_eq!(request(&format!("m{:x},2", PROGRAM_START)), "2208");
+        assert_eq!(request("Z0,20e,2"), "OK");
+        assert_eq!(request("c"), "T05swbreak:;");
+        assert_eq!(&request("g")[36..40], "0e02");
+        assert_eq!(request("m300,1"), "01");
+        client.write_all(&packet("k")).unwrap();
+
+        let chip = emulator.join().unwrap();
+        assert_eq!(chip.pc(), 0x20e);
+    }
+}
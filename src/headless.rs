@@ -0,0 +1,184 @@
+use chip8::{Chip8, Chip8Error, RandomSource};
+use std::io::{self, Write};
+
+/// How long a headless run goes on for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    /// Whole frames, each run with `Chip8::run_frame`.
+    Frames(u64),
+    /// Single instructions, with the timers ticked every
+    /// `instructions_per_frame` of them.
+    Cycles(u64),
+}
+
+/// A key changing in an input script.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScriptEvent {
+    pub frame: u64,
+    pub key: usize,
+    pub pressed: bool,
+}
+
+/// Parses an input script, a list of steps like `press 5 at frame 30` or
+/// `release 5 at 35` separated by commas, semicolons or new lines. `release`
+/// without a key lets go of the key last pressed, and `#` starts a comment.
+pub fn parse_script(script: &str) -> Result<Vec<ScriptEvent>, String> {
+    let mut events = Vec::new();
+    let mut last_pressed = None;
+
+    let steps = script.lines().map(|line| line.split('#').next().unwrap_or(""));
+    for step in steps.flat_map(|line| line.split([',', ';'])) {
+        let words: Vec<&str> = step.split_whitespace().filter(|&word| word != "frame").collect();
+        let (pressed, key, frame) = match words[..] {
+            [] => continue,
+            ["press", key, "at", frame] => (true, Some(key), frame),
+            ["release", key, "at", frame] => (false, Some(key), frame),
+            ["release", "at", frame] => (false, None, frame),
+            _ => return Err(format!("can't understand \"{}\", expected \"press <key> at <frame>\"", step.trim())),
+        };
+
+        let key = match key {
+            Some(key) => match usize::from_str_radix(key, 16) {
+                Ok(key) if key < 16 => key,
+                _ => return Err(format!("{} isn't a key, expected 0 to f", key)),
+            },
+            None => last_pressed.ok_or(format!("\"{}\" comes before any key is pressed", step.trim()))?,
+        };
+        let frame = frame.parse().map_err(|_| format!("{} isn't a frame number", frame))?;
+        if pressed {
+            last_pressed = Some(key);
+        }
+        events.push(ScriptEvent { frame, key, pressed });
+    }
+
+    // Stable, so steps on the same frame happen in the order written
+    events.sort_by_key(|event| event.frame);
+    Ok(events)
+}
+
+/// Runs `chip` until `limit` is reached or the program exits, pressing and
+/// releasing keys as `script` says. Returns the number of frames run.
+pub fn run<R: RandomSource>(chip: &mut Chip8<R>, limit: Limit, script: &[ScriptEvent]) -> Result<u64, Chip8Error> {
+    let mut keys = [false; 16];
+    let mut events = script.iter().peekable();
+    let mut frame = 0;
+    let mut cycles = 0;
+
+    loop {
+        let done = match limit {
+            Limit::Frames(frames) => frame >= frames,
+            Limit::Cycles(limit) => cycles >= limit,
+        };
+        if done || chip.has_exited() {
+            return Ok(frame);
+        }
+
+        while let Some(event) = events.next_if(|event| event.frame <= frame) {
+            keys[event.key] = event.pressed;
+            chip.update_input(keys);
+        }
+
+        match limit {
+            Limit::Frames(_) => chip.run_frame()?,
+            Limit::Cycles(limit) => {
+                // Stop partway through the last frame once the limit is hit
+                let frame_cycles = (chip.instructions_per_frame() as u64).min(limit - cycles);
+                for _ in 0..frame_cycles {
+                    chip.cycle()?;
+                }
+                cycles += frame_cycles;
+                if frame_cycles < chip.instructions_per_frame() as u64 {
+                    return Ok(frame);
+                }
+                chip.tick_timers();
+            }
+        }
+        frame += 1;
+    }
+}
+
+/// Writes the framebuffer as a binary PGM image at the current resolution,
+/// each pixel's value being its `Chip8::pixel_color` from 0 to 3.
+pub fn write_framebuffer<R: RandomSource, W: Write>(chip: &Chip8<R>, mut out: W) -> io::Result<()> {
+    write!(out, "P5\n{} {}\n3\n", chip.width(), chip.height())?;
+    let pixels: Vec<u8> = (0..chip.height())
+        .flat_map(|y| (0..chip.width()).map(move |x| (x, y)))
+        .map(|(x, y)| chip.pixel_color(x, y))
+        .collect();
+    out.write_all(&pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Draws the digit of whichever key is held, then waits for the next key
+    const ECHO_KEY: [u8; 12] = [0xf0, 0x0a, 0x00, 0xe0, 0xf0, 0x29, 0xd1, 0x15, 0x12, 0x00, 0x00, 0x00];
+
+    fn event(frame: u64, key: usize, pressed: bool) -> ScriptEvent {
+        ScriptEvent { frame, key, pressed }
+    }
+
+    #[test]
+    fn should_parse_scripts() {
+        let script = "press 5 at frame 30, release at 35\npress a at 2; release a at frame 3 # done";
+        assert_eq!(
+            parse_script(script),
+            Ok(vec![event(2, 0xa, true), event(3, 0xa, false), event(30, 5, true), event(35, 5, false)])
+        );
+        assert_eq!(parse_script(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn should_reject_bad_scripts() {
+        assert!(parse_script("press g at 1").is_err());
+        assert!(parse_script("press 1 at soon").is_err());
+        assert!(parse_script("release at 3").is_err());
+        assert!(parse_script("hold 1").is_err());
+    }
+
+    #[test]
+    fn should_run_to_frame_limit_with_input() {
+        let mut chip = Chip8::new();
+        chip.load(&ECHO_KEY);
+        let frames = run(&mut chip, Limit::Frames(10), &[event(3, 7, true), event(5, 7, false)]).unwrap();
+        assert_eq!(frames, 10);
+        assert_eq!(chip.registers()[0], 7);
+        assert!(chip.is_waiting());
+        // The top row of the digit 7 sprite
+        assert!((0..4).all(|x| chip.pixel(x, 0)));
+    }
+
+    #[test]
+    fn should_stop_at_cycle_limit() {
+        let mut chip = Chip8::new();
+        chip.load(&[0x70, 0x01, 0x12, 0x00]);
+        chip.set_instructions_per_frame(4);
+        assert_eq!(run(&mut chip, Limit::Cycles(9), &[]), Ok(2));
+        assert_eq!(chip.registers()[0], 5);
+    }
+
+    #[test]
+    fn should_stop_on_errors_and_exit() {
+        let mut chip = Chip8::new();
+        chip.load(&[0x00, 0xee]);
+        assert_eq!(run(&mut chip, Limit::Frames(5), &[]), Err(Chip8Error::StackUnderflow { addr: 0x200 }));
+
+        let mut chip = Chip8::with_platform(chip8::Platform::SuperChip, chip8::Quirks::superchip());
+        chip.load(&[0x00, 0xfd]);
+        assert_eq!(run(&mut chip, Limit::Frames(5), &[]), Ok(1));
+    }
+
+    #[test]
+    fn should_write_framebuffer_as_pgm() {
+        let mut chip = Chip8::new();
+        chip.load(&[0xd0, 0x01]);
+        chip.cycle().unwrap();
+        let mut out = Vec::new();
+        write_framebuffer(&chip, &mut out).unwrap();
+        assert!(out.starts_with(b"P5\n64 32\n3\n"));
+        assert_eq!(out.len(), 11 + 64 * 32);
+        // The top row of the 0 digit, 0xf0
+        assert_eq!(out[11..19], [1, 1, 1, 1, 0, 0, 0, 0]);
+    }
+}
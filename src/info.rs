@@ -0,0 +1,67 @@
+use chip8::{Platform, PROGRAM_START};
+use std::fmt::Write;
+
+/// What `nemulator info` prints about a rom: its size, the hash save states
+/// and movies check it by, and the oldest platform that runs it.
+pub fn describe(rom: &[u8]) -> String {
+    let lines = chip8::disassemble(rom, PROGRAM_START);
+    let instructions: Vec<_> = lines.iter().filter_map(|line| line.instruction).collect();
+    let data = lines.iter().filter(|line| line.instruction.is_none()).count();
+
+    let mut out = String::new();
+    writeln!(out, "size      {} bytes", rom.len()).unwrap();
+    writeln!(out, "hash      {:016x}", chip8::rom_hash(rom)).unwrap();
+    match platform(rom) {
+        Some(platform) => writeln!(out, "platform  {}", platform_name(platform)).unwrap(),
+        None => writeln!(out, "platform  none, too big for any platform's memory").unwrap(),
+    }
+    writeln!(out, "code      {} instructions, {} bytes of data", instructions.len(), data).unwrap();
+    out
+}
+
+/// The oldest platform with the memory and instructions `rom` needs. Data
+/// can look like instructions, so this is a guess that errs towards newer
+/// platforms.
+pub fn platform(rom: &[u8]) -> Option<Platform> {
+    let fits = |platform: Platform| rom.len() <= platform.memory_size() - PROGRAM_START as usize;
+    let instructions: Vec<_> = chip8::disassemble(rom, PROGRAM_START).iter().filter_map(|line| line.instruction).collect();
+    [Platform::Chip8, Platform::SuperChip, Platform::XoChip].iter().copied().find(|&platform| {
+        fits(platform) && instructions.iter().all(|&instruction| platform.supports(instruction))
+    })
+}
+
+/// A platform as it's written in messages.
+pub fn platform_name(platform: Platform) -> &'static str {
+    match platform {
+        Platform::Chip8 => "CHIP-8",
+        Platform::SuperChip => "SUPER-CHIP",
+        Platform::XoChip => "XO-CHIP",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_pick_the_oldest_platform_that_runs_a_rom() {
+        // cls, then jump back
+        assert_eq!(platform(&[0x00, 0xe0, 0x12, 0x00]), Some(Platform::Chip8));
+        // hires
+        assert_eq!(platform(&[0x00, 0xff, 0x12, 0x00]), Some(Platform::SuperChip));
+        // plane 1
+        assert_eq!(platform(&[0xf1, 0x01, 0x00, 0xff]), Some(Platform::XoChip));
+        // Too big for 4 KiB, even with only CHIP-8 instructions
+        assert_eq!(platform(&vec![0x00; 4000]), Some(Platform::XoChip));
+        assert_eq!(platform(&vec![0x00; 0x10000]), None);
+    }
+
+    #[test]
+    fn should_describe_a_rom() {
+        let description = describe(&[0x00, 0xe0, 0x12, 0x00, 0xff]);
+        assert!(description.contains("size      5 bytes\n"));
+        assert!(description.contains(&format!("hash      {:016x}\n", chip8::rom_hash(&[0x00, 0xe0, 0x12, 0x00, 0xff]))));
+        assert!(description.contains("platform  CHIP-8\n"));
+        assert!(description.contains("code      2 instructions, 1 bytes of data\n"));
+    }
+}
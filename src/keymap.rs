@@ -0,0 +1,190 @@
+use piston_window::Key;
+use serde::Deserialize;
+use std::collections::HashMap;
+use toml::Value;
+
+/// Where the keyboard and controller maps are read from unless `--config`
+/// names another file.
+pub const DEFAULT_CONFIG: &str = "nemulator.toml";
+
+// The COSMAC VIP keypad as it's laid out, next to the keys it sits on in the
+// usual 1234/QWER/ASDF/ZXCV layout
+const QWERTY: [(usize, Key); 16] = [
+    (0x1, Key::D1), (0x2, Key::D2), (0x3, Key::D3), (0xc, Key::D4),
+    (0x4, Key::Q), (0x5, Key::W), (0x6, Key::E), (0xd, Key::R),
+    (0x7, Key::A), (0x8, Key::S), (0x9, Key::D), (0xe, Key::F),
+    (0xa, Key::Z), (0x0, Key::X), (0xb, Key::C), (0xf, Key::V),
+];
+
+/// Which hex key each keyboard key presses. Any number of keyboard keys can
+/// press the same hex key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: HashMap<Key, usize>,
+}
+
+impl Default for Keymap {
+    /// The hex keypad on the left of a QWERTY keyboard, from 1 to V.
+    fn default() -> Keymap {
+        Keymap { bindings: QWERTY.iter().map(|&(hex, key)| (key, hex)).collect() }
+    }
+}
+
+impl Keymap {
+    /// Reads a keymap from a TOML config. The `[keys]` table maps hex keys to
+    /// a keyboard key or a list of them, replacing the default bindings for
+    /// that hex key:
+    ///
+    /// ```toml
+    /// [keys]
+    /// 5 = ["W", "Up"]
+    /// 0 = "Space"
+    ///
+    /// [rom."pong.ch8".keys]
+    /// 1 = "Up"
+    /// 4 = "Down"
+    /// ```
+    ///
+    /// A `[rom."<file name>".keys]` table does the same for one rom, on top of
+    /// the `[keys]` table. Keyboard keys are named as piston names them, with
+    /// digits also allowed on their own.
+    pub fn from_config(config: &str, rom: Option<&str>) -> Result<Keymap, String> {
+        let config: Value = config.parse().map_err(|err| format!("{}", err))?;
+        let mut keymap = Keymap::default();
+        if let Some(keys) = config.get("keys") {
+            keymap.apply(keys).map_err(|err| format!("[keys]: {}", err))?;
+        }
+        let overrides = rom.and_then(|rom| config.get("rom")?.get(rom)?.get("keys"));
+        if let (Some(keys), Some(rom)) = (overrides, rom) {
+            keymap.apply(keys).map_err(|err| format!("[rom.\"{}\".keys]: {}", rom, err))?;
+        }
+        Ok(keymap)
+    }
+
+    /// Replaces the keyboard keys that press `hex` with `keys`.
+    pub fn bind(&mut self, hex: usize, keys: &[Key]) {
+        self.bindings.retain(|_, bound| *bound != hex);
+        for &key in keys {
+            self.bindings.insert(key, hex);
+        }
+    }
+
+    /// The hex key `key` presses, if any.
+    pub fn hex_key(&self, key: Key) -> Option<usize> {
+        self.bindings.get(&key).copied()
+    }
+
+    /// The state of the hex keypad while `held` are held down, a hex key
+    /// being pressed while any of its keys is.
+    pub fn keypad<'a>(&self, held: impl IntoIterator<Item = &'a Key>) -> [bool; 16] {
+        let mut keypad = [false; 16];
+        for &key in held {
+            if let Some(hex) = self.hex_key(key) {
+                keypad[hex] = true;
+            }
+        }
+        keypad
+    }
+
+    fn apply(&mut self, table: &Value) -> Result<(), String> {
+        let table = table.as_table().ok_or("expected a table of hex keys")?;
+        for (hex_name, keys) in table {
+            let hex = parse_hex_key(hex_name)?;
+            let names = names(keys).map_err(|err| format!("the keys for {} {}", hex_name, err))?;
+            let keys = names.into_iter().map(parse_key).collect::<Result<Vec<_>, _>>()?;
+            self.bind(hex, &keys);
+        }
+        Ok(())
+    }
+}
+
+/// Reads a hex key named in a config, from 0 to f.
+pub fn parse_hex_key(name: &str) -> Result<usize, String> {
+    match usize::from_str_radix(name, 16) {
+        Ok(hex) if hex < 16 => Ok(hex),
+        _ => Err(format!("{} isn't a hex key, expected 0 to f", name)),
+    }
+}
+
+/// Reads what a hex key is bound to in a config, a name or a list of them.
+pub fn names(value: &Value) -> Result<Vec<&str>, String> {
+    let error = || "should be a name or a list of names".to_string();
+    match value {
+        Value::String(name) => Ok(vec![name.as_str()]),
+        Value::Array(names) => names.iter().map(|name| name.as_str().ok_or_else(error)).collect(),
+        _ => Err(error()),
+    }
+}
+
+// Looks a keyboard key up by its piston name, or a lone digit as the key on
+// the number row
+fn parse_key(name: &str) -> Result<Key, String> {
+    let name = match name {
+        digit if digit.len() == 1 && digit.as_bytes()[0].is_ascii_digit() => format!("D{}", digit),
+        name => name.to_string(),
+    };
+    match Key::deserialize(Value::String(name.clone())) {
+        Ok(Key::Unknown) | Err(_) => Err(format!("{} isn't a key", name)),
+        Ok(key) => Ok(key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_default_to_qwerty_layout() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.hex_key(Key::D1), Some(0x1));
+        assert_eq!(keymap.hex_key(Key::D4), Some(0xc));
+        assert_eq!(keymap.hex_key(Key::W), Some(0x5));
+        assert_eq!(keymap.hex_key(Key::X), Some(0x0));
+        assert_eq!(keymap.hex_key(Key::V), Some(0xf));
+        assert_eq!(keymap.hex_key(Key::Up), None);
+        assert_eq!(keymap.bindings.len(), 16);
+    }
+
+    #[test]
+    fn should_bind_several_keys_to_one_hex_key() {
+        let keymap = Keymap::from_config("[keys]\n5 = [\"W\", \"Up\"]\nA = \"Space\"\n0 = \"0\"", None).unwrap();
+        assert_eq!(keymap.hex_key(Key::W), Some(0x5));
+        assert_eq!(keymap.hex_key(Key::Up), Some(0x5));
+        assert_eq!(keymap.hex_key(Key::Space), Some(0xa));
+        assert_eq!(keymap.hex_key(Key::Z), None);
+        assert_eq!(keymap.hex_key(Key::D0), Some(0x0));
+        assert_eq!(keymap.hex_key(Key::X), None);
+        // Hex keys the config doesn't mention keep the default
+        assert_eq!(keymap.hex_key(Key::Q), Some(0x4));
+    }
+
+    #[test]
+    fn should_apply_rom_overrides() {
+        let config = "[keys]\n1 = \"Up\"\n\n[rom.\"pong.ch8\".keys]\n1 = [\"W\", \"K\"]\n";
+        let pong = Keymap::from_config(config, Some("pong.ch8")).unwrap();
+        assert_eq!(pong.hex_key(Key::K), Some(0x1));
+        assert_eq!(pong.hex_key(Key::Up), None);
+        assert_eq!(pong.hex_key(Key::W), Some(0x1));
+
+        let other = Keymap::from_config(config, Some("astro.ch8")).unwrap();
+        assert_eq!(other.hex_key(Key::Up), Some(0x1));
+        assert_eq!(other.hex_key(Key::K), None);
+    }
+
+    #[test]
+    fn should_keep_hex_key_pressed_while_any_key_is_held() {
+        let keymap = Keymap::from_config("[keys]\n5 = [\"W\", \"Up\"]", None).unwrap();
+        assert!(keymap.keypad(&[Key::W, Key::Up])[5]);
+        assert!(keymap.keypad(&[Key::Up])[5]);
+        assert!(!keymap.keypad(&[Key::P])[5]);
+        assert_eq!(keymap.keypad(&[Key::D1, Key::V]).iter().filter(|&&pressed| pressed).count(), 2);
+    }
+
+    #[test]
+    fn should_report_config_errors() {
+        assert!(Keymap::from_config("[keys]\ng = \"A\"", None).unwrap_err().contains("g isn't a hex key"));
+        assert!(Keymap::from_config("[keys]\n1 = \"Nope\"", None).unwrap_err().contains("Nope isn't a key"));
+        assert!(Keymap::from_config("[keys]\n1 = 5", None).is_err());
+        assert!(Keymap::from_config("keys = [", None).is_err());
+    }
+}
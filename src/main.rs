@@ -1,98 +1,588 @@
+extern crate chip8;
 extern crate piston_window;
 
-mod chip8;
+mod beeper;
+mod cli;
+mod debugger;
+mod gamepad;
+mod gdb;
+mod headless;
+mod info;
+mod keymap;
 
+use beeper::AudioBackend;
+use cli::{Command, RunOptions, ScriptSource};
+use debugger::{DebugCommand, Debugger};
+use gamepad::{GamepadBackend, GamepadMap, GamepadState};
+use gdb::GdbServer;
+use keymap::Keymap;
+use chip8::{
+    Chip8, GifRecorder, Movie, MoviePlayer, MovieRecorder, Rewind, WavRecorder, DISPLAY_HEIGHT, DISPLAY_WIDTH, PROGRAM_START,
+};
 use piston_window::*;
-use std::io::prelude::*;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufWriter, Write};
 use std::fs::File;
+use std::path::Path;
+use std::process;
+use std::sync::mpsc;
+use std::thread;
 
+// Frames between rewind snapshots, so holding rewind goes back this many times faster than real time
+const REWIND_INTERVAL: u32 = 4;
 fn main() {
-    let mut window: PistonWindow = WindowSettings::new("Chip8", [64 * 8, 32 * 8])
-        .exit_on_esc(true)
-        .build()
-        .unwrap();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = match cli::parse(&args) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("{}\nRun `nemulator help` to see the options.", err);
+            process::exit(2);
+        }
+    };
+    match command {
+        Command::Help => print!("{}", cli::USAGE),
+        Command::Info(rom) => print!("{}", info::describe(&read_file(&rom))),
+        Command::Disasm(rom) => print!("{}", chip8::listing(&read_file(&rom))),
+        Command::Asm { source, output, symbols } => assemble(&source, &output, symbols.as_deref()),
+        Command::Run(options) if options.headless.is_some() => process::exit(run_headless(&options)),
+        Command::Run(options) => run(&options),
+        Command::Debug { options, symbols, gdb: None } => debug(&options, symbols.as_deref()),
+        Command::Debug { options, symbols, gdb: Some(port) } => debug_remote(&options, symbols.as_deref(), port),
+    }
+}
+
+// Plays a rom in a window until it's closed
+fn run(options: &RunOptions) {
+    let rom = options.rom.as_str();
+    let mut window = open_window(options);
 
     let mut input: [bool; 16] = [false; 16];
-    let mut chip = chip8::new_chip8();
-    chip.init();
+    let movie = options.play_movie.as_ref().map(|path| match Movie::from_bytes(&read_file(path)) {
+        Ok(movie) => match options.check_movie(&movie) {
+            Ok(()) => movie,
+            Err(err) => fail(format!("{}: {}", path, err)),
+        },
+        Err(err) => fail(format!("{}: {}", path, err)),
+    });
+    // A movie brings the settings it was recorded with
+    let program = read_file(rom);
+    let mut chip = match &movie {
+        Some(movie) => movie.machine(),
+        None => machine(options, &program).unwrap_or_else(|err| fail(err)),
+    };
+    chip.load(&program);
+    let mut halted = false;
+    let mut audio = open_audio(options);
+    let mut recorder = options.wav.as_ref().map(|path| {
+        let file = File::create(path).and_then(|file| WavRecorder::new(BufWriter::new(file), options.sample_rate));
+        file.unwrap_or_else(|err| fail(format!("{}: {}", path, err)))
+    });
 
-    chip.load(&read_file("astro.ch8"));
+    // A fixed seed makes cxkk, and so the whole game, play out the same every run
+    if let Some(seed) = options.seed {
+        chip.seed_rng(seed);
+    }
+    // While a movie plays it supplies the input and the keypad is ignored
+    let mut player = movie.map(|movie| match MoviePlayer::new(movie, &mut chip) {
+        Ok(player) => player,
+        Err(err) => fail(format!("Can't play movie: {}", err)),
+    });
+    let movie_path = options.record_movie.as_ref();
+    let mut movie_recorder = movie_path.map(|_| MovieRecorder::start(&mut chip));
+    // Held shift turns the save slot keys into load slot keys
+    let mut shift = false;
+    let mut rewind = Rewind::new(REWIND_INTERVAL, options.rewind_bytes);
+    let mut rewinding = false;
+    let (keymap, gamepad_map) = load_config(options);
+    // Keyboard keys held down, which the keymap turns into the hex keypad
+    let mut held = HashSet::new();
+    let mut gamepads = open_gamepads();
+    let mut gamepad_state = GamepadState::default();
+    let mut gif = options.gif.as_ref().map(|path| match start_gif(path, options) {
+        Ok(recorder) => (recorder, path.clone()),
+        Err(err) => fail(format!("{}: {}", path, err)),
+    });
 
     while let Some(e) = window.next() {
         if let Some(Button::Keyboard(key)) = e.press_args() {
+            if let Some(slot) = save_slot(key) {
+                let path = format!("{}.state{}", rom, slot);
+                if shift && (player.is_some() || movie_recorder.is_some()) {
+                    // Jumping to another state would make the movie impossible to replay
+                    eprintln!("Can't load slot {} while a movie is playing or recording", slot);
+                } else if shift {
+                    let loaded = fs::read(&path).map_err(|err| err.to_string())
+                        .and_then(|state| chip.load_state(&state).map_err(|err| err.to_string()));
+                    match loaded {
+                        Ok(()) => {
+                            // The keys held now aren't necessarily the ones held when saving
+                            chip.update_input(input);
+                            halted = false;
+                            window.set_title(format!("Chip8 - loaded slot {}", slot));
+                        }
+                        Err(err) => eprintln!("Couldn't load slot {}: {}", slot, err),
+                    }
+                } else if let Err(err) = fs::write(&path, chip.save_state()) {
+                    eprintln!("Couldn't save slot {}: {}", slot, err);
+                } else {
+                    window.set_title(format!("Chip8 - saved slot {}", slot));
+                }
+            }
+            if key == Key::F11 {
+                // Starts a recording, or stops the one going
+                match gif.take() {
+                    Some((recorder, path)) => match recorder.finish() {
+                        Ok(_) => window.set_title(format!("Chip8 - saved {}", path)),
+                        Err(err) => eprintln!("Couldn't save {}: {}", path, err),
+                    },
+                    None => {
+                        let path = unused_path(rom, "recording", "gif");
+                        match start_gif(&path, options) {
+                            Ok(recorder) => {
+                                window.set_title(format!("Chip8 - recording {}", path));
+                                gif = Some((recorder, path));
+                            }
+                            Err(err) => eprintln!("Couldn't record {}: {}", path, err),
+                        }
+                    }
+                }
+            }
+            if key == Key::F12 {
+                match screenshot(&chip, options) {
+                    Ok(path) => window.set_title(format!("Chip8 - saved {}", path)),
+                    Err(err) => eprintln!("Couldn't save screenshot: {}", err),
+                }
+            }
             match key {
-                Key::Insert => input[0x0] = true,
-                Key::End => input[0x1] = true,
-                Key::Down => input[0x2] = true,
-                Key::PageDown => input[0x3] = true,
-                Key::Left => input[0x4] = true,
-                Key::D5 => input[0x5] = true,
-                Key::Right => input[0x6] = true,
-                Key::Home => input[0x7] = true,
-                Key::Up => input[0x8] = true,
-                Key::PageUp => input[0x9] = true,
-                Key::A => input[0xa] = true,
-                Key::B => input[0xb] = true,
-                Key::C => input[0xc] = true,
-                Key::D => input[0xd] = true,
-                Key::E => input[0xe] = true,
-                Key::F => input[0xf] = true,
+                Key::LShift | Key::RShift => shift = true,
+                Key::Backspace => rewinding = player.is_none() && movie_recorder.is_none(),
                 _ => {}
             }
-            chip.update_input(input);
+            held.insert(key);
         }
         if let Some(Button::Keyboard(key)) = e.release_args() {
             match key {
-                Key::Insert => input[0x0] = false,
-                Key::End => input[0x1] = false,
-                Key::Down => input[0x2] = false,
-                Key::PageDown => input[0x3] = false,
-                Key::Left => input[0x4] = false,
-                Key::D5 => input[0x5] = false,
-                Key::Right => input[0x6] = false,
-                Key::Home => input[0x7] = false,
-                Key::Up => input[0x8] = false,
-                Key::PageUp => input[0x9] = false,
-                Key::A => input[0xa] = false,
-                Key::B => input[0xb] = false,
-                Key::C => input[0xc] = false,
-                Key::D => input[0xd] = false,
-                Key::E => input[0xe] = false,
-                Key::F => input[0xf] = false,
+                Key::LShift | Key::RShift => shift = false,
+                Key::Backspace => rewinding = false,
                 _ => {}
             }
-            chip.update_input(input);
+            held.remove(&key);
+        }
+        gamepads.poll(&mut gamepad_state);
+        let mut keypad = keymap.keypad(&held);
+        for (key, pressed) in keypad.iter_mut().zip(gamepad_state.keypad(&gamepad_map).iter()) {
+            *key |= pressed;
+        }
+        if keypad != input {
+            input = keypad;
+            // Movies pass the keypad on themselves at the start of each frame
+            if player.is_none() && movie_recorder.is_none() {
+                chip.update_input(input);
+            }
+        }
+
+        if e.update_args().is_some() && rewinding {
+            // Holding rewind steps back a snapshot each frame, stopping at the oldest
+            if rewind.rewind(&mut chip) {
+                chip.update_input(input);
+                halted = false;
+                window.set_title("Chip8 - rewinding".to_string());
+            }
+            audio.set_playing(false);
+        } else if e.update_args().is_some() && !halted {
+            rewind.record(&chip);
+            if let Some(Err(err)) = recorder.as_mut().map(|recorder| recorder.record_frame(&mut chip)) {
+                // A recording that can't be written stops, rather than the game
+                eprintln!("Stopped recording sound: {}", err);
+                recorder = None;
+            }
+            let result = if let Some(movie) = &mut player {
+                movie.run_frame(&mut chip)
+            } else if let Some(movie) = &mut movie_recorder {
+                movie.update_input(&mut chip, input);
+                movie.run_frame(&mut chip)
+            } else {
+                chip.run_frame()
+            };
+            if let Some(Err(err)) = gif.as_mut().map(|(recorder, _)| recorder.record_frame(&chip)) {
+                eprintln!("Stopped recording the display: {}", err);
+                gif = None;
+            }
+            if player.as_ref().is_some_and(|movie| movie.is_finished()) {
+                // Hand the keypad back once the recording runs out
+                player = None;
+                chip.update_input(input);
+                window.set_title("Chip8 - movie finished".to_string());
+            }
+            if let Err(err) = result {
+                // Stop executing but keep the window open showing the last frame
+                eprintln!("Halted: {}", err);
+                window.set_title(format!("Chip8 - halted: {}", err));
+                halted = true;
+            } else if chip.has_exited() {
+                window.set_title("Chip8 - exited".to_string());
+                halted = true;
+            }
+            audio.set_voice(chip.audio_pattern(), chip.pitch());
+            audio.set_playing(!halted && chip.is_sound_playing());
         }
 
-        if let Some(_) = e.update_args() {
-            chip.cycle();
+        if e.render_args().is_some() {
+            window.draw_2d(&e, |c, g, _d| draw(&chip, &options.palette, c, g));
         }
+    }
 
-        if let Some(_) = e.render_args() {
-            window.draw_2d(&e, |c, g, _d| {
-                clear([0.0, 0.0, 0.0, 0.0], g);
-                for y in 0..32 {
-                    for x in 0..64 {
-                        if (chip.display[y] >> x) & 1 == 1 {
-                            rectangle(
-                                [1.0, 1.0, 1.0, 1.0],
-                                [(63 - x) as f64 * 8.0, y as f64 * 8.0, 8.0, 8.0],
-                                c.transform,
-                                g
-                            );
-                        }
-                    }
-                }
-            });
+    if let (Some(recorder), Some(path)) = (recorder, &options.wav) {
+        if let Err(err) = recorder.finish() {
+            eprintln!("Couldn't save {}: {}", path, err);
         }
     }
+    if let Some((recorder, path)) = gif {
+        if let Err(err) = recorder.finish() {
+            eprintln!("Couldn't save {}: {}", path, err);
+        }
+    }
+    if let (Some(path), Some(movie)) = (movie_path, movie_recorder) {
+        if let Err(err) = fs::write(path, movie.finish().to_bytes()) {
+            eprintln!("Couldn't save {}: {}", path, err);
+        }
+    }
+}
+
+// Runs a rom under the debugger, reading commands from stdin while the
+// window keeps showing the display
+fn debug(options: &RunOptions, symbols: Option<&str>) {
+    let (mut chip, mut debugger) = debug_machine(options, symbols);
+    let mut window = open_window(options);
+    let mut audio = open_audio(options);
+    let (keymap, _) = load_config(options);
+    let mut held = HashSet::new();
+
+    // Reading stdin blocks, so it happens on its own thread and the window carries on
+    let (send, commands) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if send.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    println!("Paused at the start, type help to see the commands");
+    print!("{}", debugger.execute(&mut chip, DebugCommand::List(0)));
+    prompt();
+
+    while let Some(e) = window.next() {
+        loop {
+            let line = match commands.try_recv() {
+                Ok(line) => line,
+                Err(mpsc::TryRecvError::Empty) => break,
+                // Stdin closed, as if quit was typed
+                Err(mpsc::TryRecvError::Disconnected) => return,
+            };
+            let output = match debugger.parse(&line) {
+                Ok(DebugCommand::Quit) => return,
+                Ok(command) => debugger.execute(&mut chip, command),
+                Err(_) if line.trim().is_empty() => String::new(),
+                Err(err) => format!("{}\n", err),
+            };
+            print!("{}", output);
+            if !debugger.is_running() {
+                prompt();
+            }
+        }
+
+        if let Some(Button::Keyboard(key)) = e.press_args() {
+            held.insert(key);
+            chip.update_input(keymap.keypad(&held));
+        }
+        if let Some(Button::Keyboard(key)) = e.release_args() {
+            held.remove(&key);
+            chip.update_input(keymap.keypad(&held));
+        }
+        if e.update_args().is_some() {
+            if let Some(stop) = debugger.run_frame(&mut chip) {
+                print!("{}", debugger.report(&chip, &stop));
+                prompt();
+            }
+            audio.set_voice(chip.audio_pattern(), chip.pitch());
+            audio.set_playing(debugger.is_running() && chip.is_sound_playing());
+            window.set_title(format!("Chip8 - {}", if debugger.is_running() { "running" } else { "paused" }));
+        }
+        if e.render_args().is_some() {
+            window.draw_2d(&e, |c, g, _d| draw(&chip, &options.palette, c, g));
+        }
+    }
+}
+
+// Runs `debug` with a gdb client giving the commands, over a localhost port
+fn debug_remote(options: &RunOptions, symbols: Option<&str>, port: u16) {
+    let (mut chip, debugger) = debug_machine(options, symbols);
+    let mut server =
+        GdbServer::listen(port, debugger).unwrap_or_else(|err| fail(format!("Couldn't listen on port {}: {}", port, err)));
+    let mut window = open_window(options);
+    let mut audio = open_audio(options);
+    let (keymap, _) = load_config(options);
+    let mut held = HashSet::new();
+    println!("Paused at the start, waiting for a GDB remote protocol client on localhost:{}", server.port());
+
+    while let Some(e) = window.next() {
+        server.poll(&mut chip);
+        if server.is_killed() {
+            return;
+        }
+        if let Some(Button::Keyboard(key)) = e.press_args() {
+            held.insert(key);
+            chip.update_input(keymap.keypad(&held));
+        }
+        if let Some(Button::Keyboard(key)) = e.release_args() {
+            held.remove(&key);
+            chip.update_input(keymap.keypad(&held));
+        }
+        if e.update_args().is_some() {
+            server.run_frame(&mut chip);
+            audio.set_voice(chip.audio_pattern(), chip.pitch());
+            audio.set_playing(server.is_running() && chip.is_sound_playing());
+            window.set_title(format!("Chip8 - {}", if server.is_running() { "running" } else { "paused" }));
+        }
+        if e.render_args().is_some() {
+            window.draw_2d(&e, |c, g, _d| draw(&chip, &options.palette, c, g));
+        }
+    }
+}
+
+// The machine and debugger `debug` starts with, the rom loaded and paused
+fn debug_machine(options: &RunOptions, symbols: Option<&str>) -> (Chip8, Debugger) {
+    let program = read_file(&options.rom);
+    let symbols = symbols.map(|path| String::from_utf8_lossy(&read_file(path)).into_owned());
+    let debugger = Debugger::new(&program, symbols.as_deref())
+        .unwrap_or_else(|err| fail(format!("{}: {}", options.rom, err)));
+    let mut chip = machine(options, &program).unwrap_or_else(|err| fail(err));
+    chip.load(&program);
+    if let Some(seed) = options.seed {
+        chip.seed_rng(seed);
+    }
+    (chip, debugger)
+}
+
+// Shows the debugger is waiting for a command
+fn prompt() {
+    print!("(debug) ");
+    io::stdout().flush().ok();
+}
+
+// Opens the window the display is drawn in, fullscreen if asked
+fn open_window(options: &RunOptions) -> PistonWindow {
+    let size = [(DISPLAY_WIDTH * options.scale) as u32, (DISPLAY_HEIGHT * options.scale) as u32];
+    let mut window: PistonWindow = match WindowSettings::new("Chip8", size)
+        .fullscreen(options.fullscreen)
+        .exit_on_esc(true)
+        .build()
+    {
+        Ok(window) => window,
+        Err(err) => fail(format!("Couldn't open a window: {}", err)),
+    };
+    // Each update runs one frame of instructions and ticks the 60 Hz timers
+    window.set_ups(60);
+    window
+}
+
+// Draws the display as big as fits in the window
+fn draw<G: Graphics>(chip: &Chip8, palette: &[[u8; 3]; 4], c: Context, g: &mut G) {
+    // Pixels shrink to fit the SUPER-CHIP hires display, and the display
+    // keeps its shape in the middle of a fullscreen window
+    let [width, height] = c.get_view_size();
+    let size = (width / chip.width() as f64).min(height / chip.height() as f64);
+    let transform = c.transform.trans(
+        (width - size * chip.width() as f64) / 2.0,
+        (height - size * chip.height() as f64) / 2.0,
+    );
+    clear(draw_color(palette[0]), g);
+    for y in 0..chip.height() {
+        for x in 0..chip.width() {
+            let color = chip.pixel_color(x, y);
+            if color != 0 {
+                rectangle(
+                    draw_color(palette[color as usize]),
+                    [x as f64 * size, y as f64 * size, size, size],
+                    transform,
+                    g
+                );
+            }
+        }
+    }
+}
+
+// The keyboard and controller maps from --config, or the default config file
+// if there is one, with any overrides for the rom
+fn load_config(options: &RunOptions) -> (Keymap, GamepadMap) {
+    let path = match &options.config {
+        Some(path) => path.as_str(),
+        None if Path::new(keymap::DEFAULT_CONFIG).exists() => keymap::DEFAULT_CONFIG,
+        None => return (Keymap::default(), GamepadMap::default()),
+    };
+    let rom_name = Path::new(&options.rom).file_name().and_then(|name| name.to_str());
+    let maps = fs::read_to_string(path).map_err(|err| err.to_string()).and_then(|config| {
+        Ok((Keymap::from_config(&config, rom_name)?, GamepadMap::from_config(&config, rom_name)?))
+    });
+    maps.unwrap_or_else(|err| fail(format!("{}: {}", path, err)))
+}
+
+// Runs a rom without a window and returns the exit code: 0 if it ran to the
+// limit or exited, 1 on an emulator error and 2 if it couldn't start
+fn run_headless(options: &RunOptions) -> i32 {
+    let headless = options.headless.as_ref().expect("run_headless needs headless options");
+    let setup = (|| -> Result<_, String> {
+        let script = match &headless.input {
+            Some(ScriptSource::Inline(script)) => script.clone(),
+            Some(ScriptSource::File(path)) => fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?,
+            None => String::new(),
+        };
+        let script = headless::parse_script(&script).map_err(|err| format!("input script: {}", err))?;
+        let program = fs::read(&options.rom).map_err(|err| format!("{}: {}", options.rom, err))?;
+        let mut chip = machine(options, &program)?;
+        chip.load(&program);
+        // Headless runs are repeatable unless told otherwise
+        chip.seed_rng(options.seed.unwrap_or(0));
+        Ok((chip, script))
+    })();
+    let (mut chip, script) = match setup {
+        Ok(setup) => setup,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 2;
+        }
+    };
+
+    let result = headless::run(&mut chip, headless.limit, &script);
+
+    // The framebuffer is written even after an error, to see where it went wrong
+    if let Some(path) = &headless.output {
+        let written = File::create(path).and_then(|file| headless::write_framebuffer(&chip, BufWriter::new(file)));
+        if let Err(err) = written {
+            eprintln!("{}: {}", path, err);
+            return 2;
+        }
+    }
+    match result {
+        Ok(frames) => {
+            println!("Ran {} frames", frames);
+            0
+        }
+        Err(err) => {
+            eprintln!("Halted: {}", err);
+            1
+        }
+    }
+}
+
+// A machine set up as the options say, checking `program` fits in its memory
+fn machine(options: &RunOptions, program: &[u8]) -> Result<Chip8, String> {
+    let room = options.platform.memory_size() - PROGRAM_START as usize;
+    if program.len() > room {
+        return Err(format!(
+            "{} is {} bytes, more than the {} bytes {} has room for",
+            options.rom,
+            program.len(),
+            room,
+            info::platform_name(options.platform)
+        ));
+    }
+    let mut chip = Chip8::with_platform(options.platform, options.quirks);
+    if let Some(instructions) = options.instructions_per_frame {
+        chip.set_instructions_per_frame(instructions);
+    }
+    Ok(chip)
+}
+
+// Saves the display as it looks in the window to the first unused
+// `{rom}.screenshot{N}.png`, returning the path
+fn screenshot(chip: &Chip8, options: &RunOptions) -> std::io::Result<String> {
+    let path = unused_path(&options.rom, "screenshot", "png");
+    let mut file = BufWriter::new(File::create(&path)?);
+    let (width, height) = (DISPLAY_WIDTH * options.scale, DISPLAY_HEIGHT * options.scale);
+    chip8::write_png_sized(chip, &mut file, width, height, &options.palette)?;
+    // Dropping the writer would flush it too, but throw away any error
+    file.flush()?;
+    Ok(path)
+}
+
+// Starts recording a GIF the size of the window
+fn start_gif(path: &str, options: &RunOptions) -> std::io::Result<GifRecorder<BufWriter<File>>> {
+    GifRecorder::new(BufWriter::new(File::create(path)?), options.scale, &options.palette)
+}
+
+// The first `{rom}.{name}{N}.{extension}` that doesn't exist yet
+fn unused_path(rom: &str, name: &str, extension: &str) -> String {
+    (1..).map(|n| format!("{}.{}{}.{}", rom, name, n, extension)).find(|path| !Path::new(path).exists()).unwrap()
+}
+
+// A palette color as piston wants it
+fn draw_color([r, g, b]: [u8; 3]) -> [f32; 4] {
+    [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0]
+}
+
+// The save slot bound to F1 to F9
+fn save_slot(key: Key) -> Option<u8> {
+    let keys = [Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9];
+    keys.iter().position(|&k| k == key).map(|index| index as u8 + 1)
+}
+
+#[cfg(feature = "sound")]
+fn open_audio(options: &RunOptions) -> Box<dyn AudioBackend> {
+    match beeper::CpalBackend::new(options.tone as f64, options.volume as f32 / 100.0) {
+        Ok(backend) => Box::new(backend),
+        Err(err) => {
+            eprintln!("No sound: {}", err);
+            Box::new(beeper::NullBackend)
+        }
+    }
+}
+
+#[cfg(not(feature = "sound"))]
+fn open_audio(_options: &RunOptions) -> Box<dyn AudioBackend> {
+    Box::new(beeper::NullBackend)
+}
+
+#[cfg(feature = "gamepad")]
+fn open_gamepads() -> Box<dyn GamepadBackend> {
+    match gamepad::GilrsGamepads::new() {
+        Ok(backend) => Box::new(backend),
+        Err(err) => {
+            eprintln!("No controllers: {}", err);
+            Box::new(gamepad::NullGamepads)
+        }
+    }
+}
+
+#[cfg(not(feature = "gamepad"))]
+fn open_gamepads() -> Box<dyn GamepadBackend> {
+    Box::new(gamepad::NullGamepads)
+}
+
+fn assemble(source: &str, output: &str, symbols: Option<&str>) {
+    let program = match chip8::assemble(&String::from_utf8_lossy(&read_file(source))) {
+        Ok(program) => program,
+        Err(err) => fail(format!("{}:{}", source, err)),
+    };
+
+    if let Err(err) = fs::write(output, &program.rom) {
+        fail(format!("{}: {}", output, err));
+    }
+    if let Some(path) = symbols {
+        if let Err(err) = fs::write(path, program.symbol_file()) {
+            fail(format!("{}: {}", path, err));
+        }
+    }
+}
+
+fn read_file(path: &str) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|err| fail(format!("{}: {}", path, err)))
 }
 
-fn read_file(path: &str) -> [u8; 3584] {
-    let mut f = File::open(path).unwrap();
-    let mut buffer = [0u8; 3584];
-    
-    f.read(&mut buffer).unwrap();
-    
-    buffer
+// Reports an error that stops nemulator from going on, and exits
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
 }